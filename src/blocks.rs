@@ -0,0 +1,467 @@
+// blocks.rs
+
+use crate::voxel::{BlockId, AIR};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+pub const TEXTURE_SIZE: usize = 16;
+
+// Texture layer 0 is plain white, used by blocks without a texture
+const PLAIN_LAYER: u32 = 0;
+
+// Faces in the order of `BlockType::faces`
+pub fn face_index(axis: usize, positive: bool) -> usize {
+    axis * 2 + !positive as usize
+}
+
+// Color for block ids the registry doesn't know, spreading them over distinct hues
+pub fn block_color(block: BlockId) -> [f32; 3] {
+    let h = (block as f32 * 0.618_034).fract() * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    match h as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Deserialize)]
+enum Pattern {
+    #[default]
+    Noise,
+    Edge((u8, u8, u8)), // Noise with a strip of a second color along the top
+    Frame,              // Opaque border around a see-through center
+    Glow,               // Bright center fading towards the border
+    Planks,
+    Bark,
+    Rings,
+    Bricks,
+}
+
+// A texture layer, read from a PNG next to the registry file. The color and pattern
+// generate a stand-in when there's no file or it can't be read.
+#[derive(Debug, Deserialize)]
+struct TextureDef {
+    name: String,
+    file: Option<String>,
+    #[serde(default = "missing_color")]
+    color: (u8, u8, u8),
+    #[serde(default)]
+    pattern: Pattern,
+}
+
+fn missing_color() -> (u8, u8, u8) {
+    (255, 0, 255)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct FaceTextures {
+    all: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    side: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockDef {
+    id: BlockId,
+    name: String,
+    #[serde(default = "default_true")]
+    opaque: bool,
+    #[serde(default)]
+    emissive: bool,
+    #[serde(default = "default_true")]
+    solid: bool,
+    #[serde(default)]
+    textures: FaceTextures,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegistryFile {
+    textures: Vec<TextureDef>,
+    blocks: Vec<BlockDef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockType {
+    pub name: String,
+    pub opaque: bool,   // Hides the faces of neighbouring blocks and casts ambient occlusion
+    pub emissive: bool, // Drawn at full brightness
+    pub solid: bool,    // Stops the player walking through it
+    pub color: [f32; 3],
+    pub faces: [u32; 6], // Texture layer for +X, -X, +Y, -Y, +Z and -Z
+}
+
+// What the mesher needs to draw one face of a block
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FaceMaterial {
+    pub color: [f32; 3],
+    pub layer: u32,
+    pub emissive: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockType>>, // Indexed by block id
+    textures: Vec<Vec<u8>>,         // RGBA8 sRGB pixels of each texture layer
+}
+
+impl BlockRegistry {
+    // Texture files are looked up in `texture_dir`; without one every texture is generated
+    pub fn parse(text: &str, texture_dir: Option<&Path>) -> io::Result<Self> {
+        let file: RegistryFile =
+            ron::from_str(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let mut textures = vec![vec![255; TEXTURE_SIZE * TEXTURE_SIZE * 4]];
+        let mut layers = HashMap::new();
+        for (i, texture) in file.textures.iter().enumerate() {
+            if layers.insert(texture.name.as_str(), i as u32 + 1).is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("texture {} is defined twice", texture.name),
+                ));
+            }
+            textures.push(texture_pixels(texture, i as u32, texture_dir));
+        }
+
+        let mut registry = Self {
+            blocks: Vec::new(),
+            textures,
+        };
+        for block in file.blocks {
+            if block.id == AIR {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "block id 0 is reserved for air"));
+            }
+            if let Some(existing) = registry.get(block.id) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("block id {} is used by both {} and {}", block.id, existing.name, block.name),
+                ));
+            }
+            let layer = |name: Option<&String>| match name {
+                Some(name) => layers.get(name.as_str()).copied().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("block {} uses unknown texture {}", block.name, name),
+                    )
+                }),
+                None => Ok(PLAIN_LAYER),
+            };
+            let t = &block.textures;
+            let all = t.all.as_ref();
+            let side = layer(t.side.as_ref().or(all))?;
+            let faces = [side, side, layer(t.top.as_ref().or(all))?, layer(t.bottom.as_ref().or(all))?, side, side];
+            registry.insert(
+                block.id,
+                BlockType {
+                    name: block.name,
+                    opaque: block.opaque,
+                    emissive: block.emissive,
+                    solid: block.solid,
+                    color: [1.0; 3],
+                    faces,
+                },
+            );
+        }
+        Ok(registry)
+    }
+
+    // Texture files are relative to the registry file
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&std::fs::read_to_string(path)?, path.parent())
+    }
+
+    // The registry shipped with the source, for when the data file can't be found. Its
+    // textures are all generated.
+    pub fn builtin() -> Self {
+        Self::parse(include_str!("../assets/blocks.ron"), None).expect("Built-in block registry is invalid")
+    }
+
+    // Opaque blocks with flat colors and no textures
+    pub fn untextured(colors: impl IntoIterator<Item = (BlockId, [f32; 3])>) -> Self {
+        let mut registry = Self {
+            blocks: Vec::new(),
+            textures: vec![vec![255; TEXTURE_SIZE * TEXTURE_SIZE * 4]],
+        };
+        for (id, color) in colors {
+            registry.insert(
+                id,
+                BlockType {
+                    name: format!("Color {}", id),
+                    opaque: true,
+                    emissive: false,
+                    solid: true,
+                    color,
+                    faces: [PLAIN_LAYER; 6],
+                },
+            );
+        }
+        registry
+    }
+
+    fn insert(&mut self, id: BlockId, block: BlockType) {
+        let index = id as usize;
+        if self.blocks.len() <= index {
+            self.blocks.resize(index + 1, None);
+        }
+        self.blocks[index] = Some(block);
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockType> {
+        self.blocks.get(id as usize).and_then(Option::as_ref)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockType)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(id, block)| Some((id as BlockId, block.as_ref()?)))
+    }
+
+    // Unknown blocks count as opaque
+    pub fn is_opaque(&self, id: BlockId) -> bool {
+        id != AIR && self.get(id).is_none_or(|block| block.opaque)
+    }
+
+    // Unknown blocks count as solid
+    pub fn is_solid(&self, id: BlockId) -> bool {
+        id != AIR && self.get(id).is_none_or(|block| block.solid)
+    }
+
+    pub fn face(&self, id: BlockId, face: usize) -> FaceMaterial {
+        match self.get(id) {
+            Some(block) => FaceMaterial {
+                color: block.color,
+                layer: block.faces[face],
+                emissive: block.emissive,
+            },
+            None => FaceMaterial {
+                color: block_color(id),
+                layer: PLAIN_LAYER,
+                emissive: false,
+            },
+        }
+    }
+
+    // Average color of a block's top face, for places that show a block as a flat color
+    pub fn preview_color(&self, id: BlockId) -> [f32; 3] {
+        let face = self.face(id, face_index(1, true));
+        let pixels = &self.textures[face.layer as usize];
+        let mut sum = [0.0; 3];
+        for pixel in pixels.chunks_exact(4) {
+            for c in 0..3 {
+                sum[c] += srgb_to_linear(pixel[c] as f32 / 255.0);
+            }
+        }
+        let count = (pixels.len() / 4) as f32;
+        std::array::from_fn(|c| sum[c] / count * face.color[c])
+    }
+
+    // Pixels of every texture layer, TEXTURE_SIZE squared RGBA8 sRGB each
+    pub fn texture_layers(&self) -> &[Vec<u8>] {
+        &self.textures
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn hash(seed: u32, x: u32, y: u32) -> u32 {
+    let mut h = seed.wrapping_mul(0x9e37_79b1) ^ x.wrapping_mul(0x85eb_ca6b) ^ y.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    h
+}
+
+// Random value in 0..1 for a pixel
+fn noise(seed: u32, x: usize, y: usize) -> f32 {
+    (hash(seed, x as u32, y as u32) & 0xffff) as f32 / 65535.0
+}
+
+// RGBA8 pixels of a texture layer, scaled to TEXTURE_SIZE if the file has another size
+fn texture_pixels(texture: &TextureDef, seed: u32, dir: Option<&Path>) -> Vec<u8> {
+    if let (Some(file), Some(dir)) = (&texture.file, dir) {
+        let path = dir.join(file);
+        match image::open(&path) {
+            Ok(image) => {
+                let size = TEXTURE_SIZE as u32;
+                let mut image = image.to_rgba8();
+                if image.dimensions() != (size, size) {
+                    let (width, height) = image.dimensions();
+                    log::warn!("{} is {}x{}, scaling it to {}x{}", path.display(), width, height, size, size);
+                    image = image::imageops::resize(&image, size, size, image::imageops::FilterType::Nearest);
+                }
+                return image.into_raw();
+            }
+            Err(e) => {
+                log::warn!("Couldn't load {}, generating texture {} instead: {}", path.display(), texture.name, e)
+            }
+        }
+    }
+    generate_texture(texture, seed)
+}
+
+fn generate_texture(texture: &TextureDef, seed: u32) -> Vec<u8> {
+    let size = TEXTURE_SIZE;
+    let (r, g, b) = texture.color;
+    let base = [r, g, b].map(|c| c as f32);
+    let mut pixels = Vec::with_capacity(size * size * 4);
+
+    for y in 0..size {
+        for x in 0..size {
+            let n = noise(seed, x, y);
+            let border = x == 0 || y == 0 || x == size - 1 || y == size - 1;
+            let (mut color, mut alpha) = (base, 255);
+            let brightness = match texture.pattern {
+                Pattern::Noise => 0.88 + 0.24 * n,
+                Pattern::Edge(accent) => {
+                    // A ragged strip two to four pixels deep
+                    if y < 2 + (hash(seed, x as u32, 0) % 3) as usize {
+                        color = [accent.0, accent.1, accent.2].map(|c| c as f32);
+                    }
+                    0.88 + 0.24 * n
+                }
+                Pattern::Frame => {
+                    if !border {
+                        alpha = 0;
+                    }
+                    0.9 + 0.1 * n
+                }
+                Pattern::Glow => {
+                    let d = ((x as f32 - 7.5).powi(2) + (y as f32 - 7.5).powi(2)).sqrt() / 10.6;
+                    if border {
+                        0.55
+                    } else {
+                        1.1 - 0.4 * d + 0.05 * n
+                    }
+                }
+                Pattern::Planks => {
+                    let board = y / 4;
+                    let seam = y % 4 == 3 || (x + board * 5) % 16 == 0;
+                    if seam {
+                        0.65
+                    } else {
+                        0.9 + 0.1 * noise(seed, x / 4, y) + 0.05 * n
+                    }
+                }
+                Pattern::Bark => 0.75 + 0.25 * noise(seed, x, y / 6) + 0.05 * n,
+                Pattern::Rings => {
+                    let d = ((x as f32 - 7.5).powi(2) + (y as f32 - 7.5).powi(2)).sqrt();
+                    if border {
+                        0.7
+                    } else {
+                        0.85 + 0.12 * (d * 1.8).sin() + 0.05 * n
+                    }
+                }
+                Pattern::Bricks => {
+                    let offset = if (y / 4) % 2 == 0 { 0 } else { 4 };
+                    if y % 4 == 3 || (x + offset) % 8 == 7 {
+                        color = [200.0, 196.0, 188.0]; // Mortar
+                        0.9 + 0.1 * n
+                    } else {
+                        0.85 + 0.2 * n
+                    }
+                }
+            };
+            for c in color {
+                pixels.push((c * brightness).clamp(0.0, 255.0) as u8);
+            }
+            pixels.push(alpha);
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTURES: &str = r#"#![enable(implicit_some)]
+    (
+        textures: [
+            (name: "painted", file: "painted.png", color: (10, 20, 30)),
+            (name: "lost", file: "missing.png", color: (200, 100, 50)),
+            (name: "plain", color: (1, 2, 3), pattern: Bricks),
+        ],
+        blocks: [
+            (id: 1, name: "Painted", textures: (all: "painted")),
+            (id: 2, name: "Lost", textures: (top: "lost", side: "plain")),
+        ],
+    )"#;
+
+    #[test]
+    fn textures_come_from_files() {
+        let dir = std::env::temp_dir().join(format!("voxxele-blocks-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Twice the layer size in 2x2 blocks, so scaling it down gives exact colors
+        let size = TEXTURE_SIZE as u32 * 2;
+        let image = image::RgbaImage::from_fn(size, size, |x, _| image::Rgba([(x / 2) as u8 * 16, 50, 60, 255]));
+        image.save(dir.join("painted.png")).unwrap();
+
+        let registry = BlockRegistry::parse(TEXTURES, Some(&dir)).unwrap();
+        let layers = registry.texture_layers();
+        assert_eq!(layers.len(), 4);
+        assert!(layers.iter().all(|layer| layer.len() == TEXTURE_SIZE * TEXTURE_SIZE * 4));
+        assert_eq!(&layers[1][0..4], &[0, 50, 60, 255]);
+        assert_eq!(&layers[1][4..8], &[16, 50, 60, 255]);
+
+        // Missing files and textures without one are generated the same way as with no directory
+        let generated = BlockRegistry::parse(TEXTURES, None).unwrap();
+        assert_eq!(layers[2], generated.texture_layers()[2]);
+        assert_eq!(layers[3], generated.texture_layers()[3]);
+        assert_ne!(layers[1], generated.texture_layers()[1]);
+
+        let lost = registry.get(2).unwrap();
+        assert_eq!(lost.faces[face_index(1, true)], 2);
+        assert_eq!(lost.faces[face_index(0, true)], 3);
+        assert_eq!(lost.faces[face_index(1, false)], PLAIN_LAYER);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn duplicates_are_rejected() {
+        let blocks = r#"(textures: [], blocks: [(id: 4, name: "Sand"), (id: 4, name: "Gravel")])"#;
+        let error = BlockRegistry::parse(blocks, None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("block id 4"), "{}", error);
+
+        let textures = r#"(textures: [(name: "a"), (name: "a")], blocks: [])"#;
+        assert!(BlockRegistry::parse(textures, None).unwrap_err().to_string().contains("texture a"));
+
+        let air = r#"(textures: [], blocks: [(id: 0, name: "Void")])"#;
+        assert!(BlockRegistry::parse(air, None).is_err());
+    }
+
+    #[test]
+    fn shipped_registry_parses() {
+        let registry = BlockRegistry::load(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/blocks.ron")).unwrap();
+        assert_eq!(registry.texture_layers().len(), BlockRegistry::builtin().texture_layers().len());
+        assert!(!registry.is_opaque(6));
+        assert!(registry.is_solid(6));
+        assert!(registry.get(7).unwrap().emissive);
+    }
+}
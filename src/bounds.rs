@@ -0,0 +1,90 @@
+// bounds.rs
+
+use glam::{Mat4, Vec3, Vec4};
+
+// Axis-aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // Smallest box containing all points. An empty iterator gives a box at the origin.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self::new(Vec3::ZERO, Vec3::ZERO);
+        };
+        points.fold(Self::new(first, first), |aabb, p| {
+            Self::new(aabb.min.min(p), aabb.max.max(p))
+        })
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn translated(&self, offset: Vec3) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+    }
+
+    // Box around the eight transformed corners
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        Self::from_points((0..8).map(|i| {
+            let corner = Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            matrix.transform_point3(corner)
+        }))
+    }
+}
+
+// View volume of a view-projection matrix, as six inward facing planes (xyz normal, w distance)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    pub view_proj: Mat4,
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Planes read straight off the matrix rows (Gribb & Hartmann), for wgpu's 0..1 depth range
+    pub fn from_matrix(view_proj: Mat4) -> Self {
+        let (x, y, z, w) = (view_proj.row(0), view_proj.row(1), view_proj.row(2), view_proj.row(3));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|p| p / p.truncate().length());
+        Self { view_proj, planes }
+    }
+
+    // False only when the box lies entirely outside one of the planes, so a few boxes
+    // near the corners pass even though they're out of view
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // Corner furthest along the plane normal
+            let corner = Vec3::select(plane.truncate().cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    // Near corners followed by far corners, each in the order of `Aabb::transformed`
+    pub fn corners(&self) -> [Vec3; 8] {
+        let inverse = self.view_proj.inverse();
+        std::array::from_fn(|i| {
+            let ndc = Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { 0.0 } else { 1.0 },
+            );
+            inverse.project_point3(ndc)
+        })
+    }
+}
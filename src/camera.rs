@@ -1,6 +1,7 @@
 // camera.rs
 
-use glam::{Mat4, Vec3};
+use crate::ray::Ray;
+use glam::{Mat4, Vec2, Vec3};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Camera {
@@ -8,6 +9,10 @@ pub struct Camera {
     pub target: Vec3,
     pub up: Vec3,
     pub speed: f32,
+    pub fovy: f32, // Vertical field of view in radians
+    pub znear: f32,
+    pub zfar: f32,
+    pub ortho: Option<f32>, // Half the height of an orthographic view, perspective when None
 }
 
 impl Camera {
@@ -17,6 +22,19 @@ impl Camera {
             target,
             up: Vec3::Y, // Default up direction
             speed,
+            fovy: 45f32.to_radians(),
+            znear: 0.1,
+            zfar: 100.0,
+            ortho: None,
+        }
+    }
+
+    pub fn orthographic(position: Vec3, target: Vec3, up: Vec3, half_height: f32) -> Self {
+        Self {
+            up,
+            zfar: 200.0,
+            ortho: Some(half_height),
+            ..Self::new(position, target, 0.1)
         }
     }
 
@@ -24,13 +42,63 @@ impl Camera {
         Mat4::look_at_rh(self.position, self.target, self.up)
     }
 
+    pub fn projection_matrix(&self, aspect: f32) -> Mat4 {
+        // wgpu uses a 0..1 depth range, which is what glam's `_rh` projections produce
+        match self.ortho {
+            Some(h) => Mat4::orthographic_rh(-h * aspect, h * aspect, -h, h, self.znear, self.zfar),
+            None => Mat4::perspective_rh(self.fovy, aspect, self.znear, self.zfar),
+        }
+    }
+
+    pub fn view_projection_matrix(&self, aspect: f32) -> Mat4 {
+        self.projection_matrix(aspect) * self.view_matrix()
+    }
+
+    // Ray through a pixel of a viewport of the given size, with the origin on the near plane
+    pub fn screen_ray(&self, cursor: Vec2, viewport: Vec2) -> Ray {
+        let ndc = Vec2::new(
+            cursor.x / viewport.x * 2.0 - 1.0,
+            1.0 - cursor.y / viewport.y * 2.0,
+        );
+        let inverse = self.view_projection_matrix(viewport.x / viewport.y).inverse();
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(1.0));
+        Ray::new(near, far - near)
+    }
+
+    // Half the height of the view at a distance in front of the camera
+    pub fn half_height_at(&self, distance: f32) -> f32 {
+        match self.ortho {
+            Some(h) => h,
+            None => distance * (self.fovy * 0.5).tan(),
+        }
+    }
+
+    // How far away a point looks, for things drawn at a constant size on screen. Orthographic
+    // views answer with the distance a perspective view would need to show as much.
+    pub fn apparent_distance(&self, point: Vec3) -> f32 {
+        match self.ortho {
+            Some(h) => h / (self.fovy * 0.5).tan(),
+            None => point.distance(self.position),
+        }
+    }
+
+    // Moving along the view doesn't change an orthographic one, so those zoom instead
     pub fn move_forward(&mut self) {
+        if let Some(h) = &mut self.ortho {
+            *h = (*h / 1.1).max(0.1);
+            return;
+        }
         let direction = (self.target - self.position).normalize();
         self.position += direction * self.speed;
         self.target += direction * self.speed;
     }
 
     pub fn move_backward(&mut self) {
+        if let Some(h) = &mut self.ortho {
+            *h = (*h * 1.1).min(1000.0);
+            return;
+        }
         let direction = (self.target - self.position).normalize();
         self.position -= direction * self.speed;
         self.target -= direction * self.speed;
@@ -1,11 +1,39 @@
 // Vertex shader
 
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+struct ObjectUniform {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    normal: mat4x4<f32>,
+    material: vec4<f32>, // Specular strength, shininess
+    pbr: vec4<f32>,      // Metallic, roughness, normal scale, occlusion strength
+    emissive: vec4<f32>, // Emitted color, alpha cutoff
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniform;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
 };
 
+// Per-instance model matrix and color, used instead of the object uniform when instancing
+struct InstanceInput {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+    @location(9) color: vec4<f32>,
+};
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
@@ -16,8 +44,25 @@ fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    out.color = model.color;
-    out.clip_position = vec4<f32>(model.position, 1.0);
+    out.color = model.color * object.color.rgb;
+    out.clip_position = camera.view_proj * object.model * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+@vertex
+fn vs_instanced(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_0,
+        instance.model_1,
+        instance.model_2,
+        instance.model_3,
+    );
+    var out: VertexOutput;
+    out.color = model.color * instance.color.rgb;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
     return out;
 }
 
@@ -0,0 +1,633 @@
+// chunk_streamer.rs
+
+use crate::camera::Camera;
+use crate::region::RegionStore;
+use crate::terrain::{TerrainGenerator, TerrainParams};
+use crate::voxel::{chunk_coord, BlockId, Chunk, VoxelWorld};
+use crate::worker_pool::WorkerPool;
+use glam::{IVec3, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Chunks loaded above and below the camera's chunk
+const VERTICAL_DISTANCE: i32 = 2;
+// Load jobs handed to the workers at once; the rest wait so they can be reprioritized
+const MAX_IN_FLIGHT: usize = 16;
+// Wait before writing chunks again after a save failed
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+struct LoadJob {
+    coord: IVec3,
+    epoch: u64,
+    terrain: TerrainGenerator,
+    store: RegionStore,
+}
+
+struct LoadResult {
+    coord: IVec3,
+    epoch: u64,
+    chunk: Option<Chunk>,
+}
+
+// Edits by chunk, each at chunk-local positions
+type PendingEdits = Vec<(IVec3, Vec<(IVec3, BlockId)>)>;
+
+// Edited chunks written in one go, rewriting each region they touch once
+struct SaveJob {
+    batch: u64,
+    terrain: u64, // Fingerprint of the terrain the store belongs to
+    store: RegionStore,
+    chunks: Vec<(IVec3, Arc<Chunk>)>,
+    // Edits to chunks that never loaded, made on top of what's saved or generated there first
+    merge: Option<(TerrainGenerator, PendingEdits)>,
+}
+
+struct SaveResult {
+    batch: u64,
+    terrain: u64,
+    store: RegionStore,
+    chunks: Vec<(IVec3, Arc<Chunk>)>,
+    result: io::Result<()>,
+}
+
+// Chunks of a failed save, written again once `at` has passed
+struct Retry {
+    at: Instant,
+    batch: u64,
+    terrain: u64,
+    store: RegionStore,
+    chunks: Vec<(IVec3, Arc<Chunk>)>,
+}
+
+// Keeps the chunks around the camera loaded. Missing chunks are read from the region store
+// or generated on worker threads, closest first and favouring what's in front of the camera;
+// chunks that fall out of range are unloaded, saving the ones that were edited in the background.
+pub struct ChunkStreamer {
+    pub view_distance: i32, // Horizontal radius in chunks
+    pool: WorkerPool<LoadJob, LoadResult>,
+    // A single thread, so rewrites of the same region can't race each other
+    saver: WorkerPool<SaveJob, SaveResult>,
+    // Chunks not written yet by terrain fingerprint and position, with their save batch
+    saving: HashMap<(u64, IVec3), (u64, Arc<Chunk>)>,
+    merging: HashMap<(u64, IVec3), u64>, // Edits being merged into saved chunks, by save batch
+    saves_in_flight: usize,
+    retries: Vec<Retry>,
+    next_batch: u64,
+    loaded: HashSet<IVec3>, // Includes chunks that turned out empty and aren't stored in the world
+    in_flight: HashSet<IVec3>,
+    queue: Vec<IVec3>, // Missing chunks, highest priority last
+    world_dir: PathBuf,
+    params: TerrainParams,
+    terrain: u64,       // Fingerprint of the current terrain
+    store: RegionStore, // Regions of the current terrain, in a directory of `world_dir`
+    epoch: u64, // Bumped by `reset` so results for the old terrain get dropped
+    last_center: Option<IVec3>,
+    last_forward: Vec3,
+    last_view_distance: i32,
+}
+
+impl ChunkStreamer {
+    pub fn new(view_distance: i32, world_dir: impl Into<PathBuf>, terrain: &TerrainParams) -> Self {
+        let world_dir = world_dir.into();
+        Self {
+            view_distance,
+            pool: WorkerPool::with_available_threads("chunk loader", Self::load),
+            saver: WorkerPool::new("chunk saver", 1, Self::save),
+            saving: HashMap::new(),
+            merging: HashMap::new(),
+            saves_in_flight: 0,
+            retries: Vec::new(),
+            next_batch: 0,
+            loaded: HashSet::new(),
+            in_flight: HashSet::new(),
+            queue: Vec::new(),
+            store: Self::terrain_store(&world_dir, terrain),
+            params: *terrain,
+            terrain: terrain.fingerprint(),
+            world_dir,
+            epoch: 0,
+            last_center: None,
+            last_forward: Vec3::ZERO,
+            last_view_distance: view_distance,
+        }
+    }
+
+    // Edits are saved per terrain, so they only come back on the terrain they were made on
+    fn terrain_store(world_dir: &std::path::Path, terrain: &TerrainParams) -> RegionStore {
+        RegionStore::new(world_dir.join(format!("terrain-{:016x}", terrain.fingerprint())))
+    }
+
+    // Saved chunks take precedence; anything unreadable is generated again
+    fn saved_or_generated(store: &RegionStore, terrain: &TerrainGenerator, coord: IVec3) -> Option<Chunk> {
+        match store.load_chunk(coord) {
+            Ok(Some(chunk)) => Some(chunk),
+            Ok(None) => terrain.generate_chunk(coord),
+            Err(e) => {
+                log::error!("Failed to load chunk {}: {}", coord, e);
+                terrain.generate_chunk(coord)
+            }
+        }
+    }
+
+    fn load(job: LoadJob) -> LoadResult {
+        LoadResult {
+            coord: job.coord,
+            epoch: job.epoch,
+            chunk: Self::saved_or_generated(&job.store, &job.terrain, job.coord),
+        }
+    }
+
+    fn save(job: SaveJob) -> SaveResult {
+        let mut chunks = job.chunks;
+        if let Some((terrain, edits)) = job.merge {
+            for (coord, edits) in edits {
+                let mut chunk = Self::saved_or_generated(&job.store, &terrain, coord).unwrap_or_default();
+                apply_edits(&mut chunk, &edits);
+                chunks.push((coord, Arc::new(chunk)));
+            }
+        }
+        let result = job.store.save_chunks(chunks.iter().map(|(coord, chunk)| (*coord, chunk.as_ref())));
+        SaveResult {
+            batch: job.batch,
+            terrain: job.terrain,
+            store: job.store,
+            chunks,
+            result,
+        }
+    }
+
+    fn submit_save(&mut self, terrain: u64, store: RegionStore, chunks: Vec<(IVec3, Arc<Chunk>)>) {
+        if chunks.is_empty() {
+            return;
+        }
+        self.next_batch += 1;
+        for (coord, chunk) in &chunks {
+            self.saving.insert((terrain, *coord), (self.next_batch, chunk.clone()));
+        }
+        self.saves_in_flight += 1;
+        self.saver.submit(SaveJob {
+            batch: self.next_batch,
+            terrain,
+            store,
+            chunks,
+            merge: None,
+        });
+    }
+
+    // Saves the edits to chunks that haven't loaded on top of what the current terrain has
+    // there, taking them out of the world. Until that's written those chunks don't load.
+    fn save_pending(&mut self, world: &mut VoxelWorld) {
+        let pending = world.take_pending_edits();
+        if pending.is_empty() {
+            return;
+        }
+        self.next_batch += 1;
+        let mut chunks = Vec::new();
+        let mut edits = Vec::new();
+        for (coord, chunk_edits) in pending {
+            let key = (self.terrain, coord);
+            // A chunk that failed to save is only up to date in memory
+            match self.saving.get(&key) {
+                Some((_, saved)) => {
+                    let mut chunk = saved.as_ref().clone();
+                    apply_edits(&mut chunk, &chunk_edits);
+                    let chunk = Arc::new(chunk);
+                    self.saving.insert(key, (self.next_batch, chunk.clone()));
+                    chunks.push((coord, chunk));
+                }
+                None => {
+                    self.merging.insert(key, self.next_batch);
+                    edits.push((coord, chunk_edits));
+                }
+            }
+        }
+        self.saves_in_flight += 1;
+        self.saver.submit(SaveJob {
+            batch: self.next_batch,
+            terrain: self.terrain,
+            store: self.store.clone(),
+            chunks,
+            merge: Some((TerrainGenerator::new(self.params), edits)),
+        });
+    }
+
+    // Forgets the chunks a save wrote, unless a later save has them again. When the save
+    // failed, chunks that are still loaded for the same terrain count as modified again and
+    // the others stay in memory to be written again after a while.
+    fn finish_save(&mut self, world: &mut VoxelWorld, result: SaveResult) -> io::Result<()> {
+        self.saves_in_flight -= 1;
+        let saved = result.result.is_ok();
+        let mut failed = Vec::new();
+        for (coord, chunk) in result.chunks {
+            let key = (result.terrain, coord);
+            let merged = self.merging.get(&key) == Some(&result.batch);
+            if merged {
+                // The chunk can load now; requests for it were turned away until here
+                self.merging.remove(&key);
+                self.last_center = None;
+            } else if self.saving.get(&key).is_none_or(|(batch, _)| *batch != result.batch) {
+                continue;
+            }
+            if saved {
+                self.saving.remove(&key);
+            } else if !merged && result.terrain == self.terrain && world.chunk(coord).is_some() {
+                self.saving.remove(&key);
+                world.mark_modified(coord);
+            } else {
+                self.saving.insert(key, (result.batch, chunk.clone()));
+                failed.push((coord, chunk));
+            }
+        }
+        if !failed.is_empty() {
+            self.retries.push(Retry {
+                at: Instant::now() + RETRY_DELAY,
+                batch: result.batch,
+                terrain: result.terrain,
+                store: result.store,
+                chunks: failed,
+            });
+        }
+        result.result
+    }
+
+    // Writes failed saves again once they're due, leaving out chunks saved again since
+    fn retry_saves(&mut self) {
+        let now = Instant::now();
+        let (due, waiting) = std::mem::take(&mut self.retries).into_iter().partition(|retry| retry.at <= now);
+        self.retries = waiting;
+        for retry in due {
+            let Retry { batch, terrain, store, chunks, .. } = retry;
+            let chunks = chunks
+                .into_iter()
+                .filter(|(coord, _)| {
+                    let key = (terrain, *coord);
+                    self.saving.get(&key).is_some_and(|(saved_in, _)| *saved_in == batch)
+                })
+                .collect();
+            self.submit_save(terrain, store, chunks);
+        }
+    }
+
+    fn in_range(coord: IVec3, center: IVec3, distance: i32) -> bool {
+        let offset = coord - center;
+        offset.x * offset.x + offset.z * offset.z <= distance * distance && offset.y.abs() <= VERTICAL_DISTANCE
+    }
+
+    pub fn update(&mut self, world: &mut VoxelWorld, terrain: &TerrainGenerator, camera: &Camera) {
+        while let Some(result) = self.saver.try_recv() {
+            let count = result.chunks.len();
+            if let Err(e) = self.finish_save(world, result) {
+                log::error!("Failed to save {} chunks, trying again: {}", count, e);
+            }
+        }
+        self.retry_saves();
+
+        while let Some(result) = self.pool.try_recv() {
+            if result.epoch != self.epoch || !self.in_flight.remove(&result.coord) {
+                continue;
+            }
+            self.loaded.insert(result.coord);
+            // Saved chunks can come back all air once every block in them was broken
+            match result.chunk.filter(|chunk| !chunk.is_empty()) {
+                Some(chunk) => {
+                    world.insert_chunk(result.coord, chunk);
+                }
+                // Whatever was placed there while it loaded is all the chunk holds
+                None => world.keep_pending(result.coord),
+            }
+        }
+
+        // Edits and imports can land in chunks that aren't loaded yet, even far out of range.
+        // Those get loaded too, so the edits end up on top of the real voxels and get saved.
+        for coord in world.pending_chunks() {
+            if self.loaded.contains(&coord) {
+                world.keep_pending(coord);
+            } else if !self.in_flight.contains(&coord) {
+                self.request(world, coord, terrain);
+            }
+        }
+
+        let center = chunk_coord(camera.position.floor().as_ivec3());
+        let forward = (camera.target - camera.position).normalize_or_zero();
+        let moved = self.last_center != Some(center)
+            || self.last_view_distance != self.view_distance
+            || forward.dot(self.last_forward) < 0.95;
+        if moved {
+            // A chunk's worth of slack so walking back and forth over a border doesn't thrash
+            let keep = self.view_distance + 1;
+            let far: Vec<IVec3> = self
+                .loaded
+                .iter()
+                .copied()
+                .filter(|coord| !Self::in_range(*coord, center, keep))
+                .collect();
+            let unloaded = far.into_iter().filter_map(|coord| self.unload(world, coord)).collect();
+            self.submit_save(self.terrain, self.store.clone(), unloaded);
+            let wanted = |coord: IVec3| Self::in_range(coord, center, keep) || world.has_pending_edits(coord);
+            self.in_flight.retain(|coord| wanted(*coord));
+            self.pool.retain_queued(|job| wanted(job.coord));
+
+            self.rebuild_queue(center, forward);
+            self.last_center = Some(center);
+            self.last_forward = forward;
+            self.last_view_distance = self.view_distance;
+        }
+
+        while self.in_flight.len() < MAX_IN_FLIGHT {
+            let Some(coord) = self.queue.pop() else { break };
+            if self.loaded.contains(&coord) || self.in_flight.contains(&coord) {
+                continue;
+            }
+            self.request(world, coord, terrain);
+        }
+    }
+
+    // Chunks still waiting to be saved come back from memory, since the region file doesn't
+    // have them yet
+    fn request(&mut self, world: &mut VoxelWorld, coord: IVec3, terrain: &TerrainGenerator) {
+        if self.merging.contains_key(&(self.terrain, coord)) {
+            return;
+        }
+        if let Some((_, chunk)) = self.saving.get(&(self.terrain, coord)) {
+            world.insert_chunk(coord, chunk.as_ref().clone());
+            self.loaded.insert(coord);
+            return;
+        }
+        self.in_flight.insert(coord);
+        self.pool.submit(LoadJob {
+            coord,
+            epoch: self.epoch,
+            terrain: terrain.clone(),
+            store: self.store.clone(),
+        });
+    }
+
+    fn rebuild_queue(&mut self, center: IVec3, forward: Vec3) {
+        let (r, h) = (self.view_distance, VERTICAL_DISTANCE);
+        let mut missing = Vec::new();
+        for y in -h..=h {
+            for z in -r..=r {
+                for x in -r..=r {
+                    let coord = center + IVec3::new(x, y, z);
+                    if Self::in_range(coord, center, r)
+                        && !self.loaded.contains(&coord)
+                        && !self.in_flight.contains(&coord)
+                    {
+                        missing.push(coord);
+                    }
+                }
+            }
+        }
+
+        // Chunks straight ahead count as up to half as far away as they are
+        let priority = |coord: &IVec3| {
+            let offset = (*coord - center).as_vec3();
+            let facing = offset.normalize_or_zero().dot(forward);
+            offset.length() * (1.0 - 0.5 * facing)
+        };
+        missing.sort_by(|a, b| priority(b).total_cmp(&priority(a)));
+        self.queue = missing;
+    }
+
+    // Returns the chunk if it has edits that need saving
+    fn unload(&mut self, world: &mut VoxelWorld, coord: IVec3) -> Option<(IVec3, Arc<Chunk>)> {
+        self.loaded.remove(&coord);
+        let modified = world.is_modified(coord);
+        let mut chunk = world.remove_chunk(coord).filter(|_| modified)?;
+        chunk.compact();
+        Some((coord, Arc::new(chunk)))
+    }
+
+    // Writes out every loaded chunk with unsaved edits in the background, dropping the
+    // palette entries the edits left unused. Chunks that fail to save become modified again.
+    pub fn save_all(&mut self, world: &mut VoxelWorld) {
+        let mut chunks = Vec::new();
+        for coord in world.take_modified() {
+            if let Some(chunk) = world.chunk_mut(coord) {
+                chunk.compact();
+                chunks.push((coord, Arc::new(chunk.clone())));
+            }
+        }
+        self.submit_save(self.terrain, self.store.clone(), chunks);
+    }
+
+    // Saves everything, including edits to chunks that haven't loaded, and waits for all the
+    // writes to finish, for before exiting
+    pub fn save_and_wait(&mut self, world: &mut VoxelWorld) -> io::Result<()> {
+        self.save_all(world);
+        self.save_pending(world);
+        let mut outcome = Ok(());
+        while self.saves_in_flight > 0 {
+            let Some(result) = self.saver.recv() else { break };
+            if let Err(e) = self.finish_save(world, result) {
+                outcome = Err(e);
+            }
+        }
+        outcome
+    }
+
+    // Saves the edits and drops every chunk, so the area loads again for the new terrain.
+    // Nothing saved is deleted; going back to the old settings brings back the old edits.
+    pub fn reset(&mut self, world: &mut VoxelWorld, terrain: &TerrainParams) {
+        self.save_all(world);
+        self.save_pending(world);
+        self.params = *terrain;
+        self.store = Self::terrain_store(&self.world_dir, terrain);
+        self.terrain = terrain.fingerprint();
+        world.clear();
+        self.loaded.clear();
+        self.in_flight.clear();
+        self.queue.clear();
+        self.pool.retain_queued(|_| false);
+        self.epoch += 1;
+        self.last_center = None;
+    }
+
+    // Whether the chunk has been loaded, empty or not
+    pub fn is_loaded(&self, coord: IVec3) -> bool {
+        self.loaded.contains(&coord)
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.loaded.len()
+    }
+
+    // Chunks in range that are still being generated or waiting to be
+    pub fn pending(&self) -> usize {
+        self.in_flight.len() + self.queue.len()
+    }
+}
+
+// Sets voxels by chunk-local position, then drops the palette entries left unused
+fn apply_edits(chunk: &mut Chunk, edits: &[(IVec3, BlockId)]) {
+    for (local, block) in edits {
+        chunk.set(local.x as usize, local.y as usize, local.z as usize, *block);
+    }
+    chunk.compact();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain::TerrainParams;
+    use crate::voxel::{AIR, CHUNK_SIZE};
+    use std::time::{Duration, Instant};
+
+    fn world_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("voxxele-streamer-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn update_until(
+        streamer: &mut ChunkStreamer,
+        world: &mut VoxelWorld,
+        terrain: &TerrainGenerator,
+        camera: &Camera,
+        done: impl Fn(&ChunkStreamer, &VoxelWorld) -> bool,
+    ) {
+        let start = Instant::now();
+        loop {
+            streamer.update(world, terrain, camera);
+            if done(streamer, world) {
+                break;
+            }
+            assert!(start.elapsed() < Duration::from_secs(30), "chunks never finished loading");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn edits_to_unloaded_chunks_survive_loading() {
+        let terrain = TerrainGenerator::new(TerrainParams::default());
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 0.1);
+        let dir = world_dir("edits");
+        let mut streamer = ChunkStreamer::new(1, &dir, &terrain.params);
+        let mut world = VoxelWorld::new();
+
+        // One edit in range that is about to load, and one far away, as from an import
+        let near = IVec3::new(5, -40, 5);
+        let far = IVec3::new(20 * CHUNK_SIZE as i32 + 3, -40, 0);
+        streamer.update(&mut world, &terrain, &camera);
+        world.set_voxel(near, 9);
+        world.set_voxel(far, 9);
+
+        update_until(&mut streamer, &mut world, &terrain, &camera, |streamer, world| {
+            streamer.pending() == 0 && world.pending_chunks().is_empty()
+        });
+        for pos in [near, far] {
+            let coord = chunk_coord(pos);
+            assert!(streamer.is_loaded(coord));
+            assert_eq!(world.get_voxel(pos), 9);
+            assert!(world.is_modified(coord));
+            // Everything else in the chunk comes from the terrain
+            let other = pos + IVec3::Y;
+            assert_eq!(world.get_voxel(other), terrain.generate_chunk(coord).map_or(AIR, |_| terrain.block_at(other)));
+        }
+
+        // Moving away unloads and saves the far chunk with the edit in it
+        let camera = Camera::new(Vec3::new(0.0, 0.0, 100.0), Vec3::new(0.0, 0.0, 99.0), 0.1);
+        update_until(&mut streamer, &mut world, &terrain, &camera, |streamer, _| streamer.pending() == 0);
+        assert!(!streamer.is_loaded(chunk_coord(far)));
+        streamer.save_and_wait(&mut world).unwrap();
+        assert_eq!(streamer.store.load_chunk(chunk_coord(far)).unwrap().unwrap().get(3, 24, 0), 9);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn terrain_changes_keep_saved_edits() {
+        let camera = Camera::new(Vec3::ZERO, Vec3::NEG_Z, 0.1);
+        let first = TerrainGenerator::new(TerrainParams::default());
+        let second = TerrainGenerator::new(TerrainParams {
+            seed: 99,
+            ..TerrainParams::default()
+        });
+        let dir = world_dir("reset");
+        let mut streamer = ChunkStreamer::new(1, &dir, &first.params);
+        let mut world = VoxelWorld::new();
+        let pos = IVec3::new(2, -40, 2);
+
+        update_until(&mut streamer, &mut world, &first, &camera, |streamer, _| streamer.pending() == 0);
+        world.set_voxel(pos, 9);
+
+        // The edit is saved for the first terrain and doesn't show up on the second
+        streamer.reset(&mut world, &second.params);
+        update_until(&mut streamer, &mut world, &second, &camera, |streamer, _| streamer.pending() == 0);
+        assert_ne!(world.get_voxel(pos), 9);
+
+        streamer.reset(&mut world, &first.params);
+        update_until(&mut streamer, &mut world, &first, &camera, |streamer, _| streamer.pending() == 0);
+        assert_eq!(world.get_voxel(pos), 9);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn terrain_changes_keep_edits_to_unloaded_chunks() {
+        let first = TerrainGenerator::new(TerrainParams::default());
+        let second = TerrainGenerator::new(TerrainParams {
+            seed: 99,
+            ..TerrainParams::default()
+        });
+        let dir = world_dir("pending");
+        let mut streamer = ChunkStreamer::new(1, &dir, &first.params);
+        let mut world = VoxelWorld::new();
+        let camera = Camera::new(Vec3::ZERO, Vec3::NEG_Z, 0.1);
+        update_until(&mut streamer, &mut world, &first, &camera, |streamer, _| streamer.pending() == 0);
+
+        // An edit far out of range, switched away from before its chunk can load, and
+        // straight back again while it may still be written
+        let pos = IVec3::new(20 * CHUNK_SIZE as i32 + 3, -40, 0);
+        let coord = chunk_coord(pos);
+        world.set_voxel(pos, 9);
+        streamer.reset(&mut world, &second.params);
+        assert_eq!(world.chunk_count(), 0);
+        streamer.reset(&mut world, &first.params);
+
+        let there = Camera::new(pos.as_vec3(), pos.as_vec3() + Vec3::NEG_Z, 0.1);
+        update_until(&mut streamer, &mut world, &first, &there, |streamer, _| streamer.is_loaded(coord));
+        assert_eq!(world.get_voxel(pos), 9);
+        let other = pos + IVec3::Y;
+        assert_eq!(world.get_voxel(other), first.generate_chunk(coord).map_or(AIR, |_| first.block_at(other)));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn failed_saves_keep_the_edits() {
+        let terrain = TerrainGenerator::new(TerrainParams::default());
+        let camera = Camera::new(Vec3::ZERO, Vec3::NEG_Z, 0.1);
+        let away = Camera::new(Vec3::new(0.0, 0.0, 200.0), Vec3::new(0.0, 0.0, 199.0), 0.1);
+        let dir = world_dir("failing");
+        let mut streamer = ChunkStreamer::new(1, &dir, &terrain.params);
+        let mut world = VoxelWorld::new();
+        let pos = IVec3::new(2, -40, 2);
+        let coord = chunk_coord(pos);
+
+        update_until(&mut streamer, &mut world, &terrain, &camera, |streamer, _| streamer.pending() == 0);
+        world.set_voxel(pos, 9);
+
+        // With a file in place of the world directory every write fails, so the unloaded
+        // chunk only lives on in memory and comes back from there
+        std::fs::write(&dir, "not a directory").unwrap();
+        assert!(streamer.save_and_wait(&mut world).is_err());
+        assert!(world.is_modified(coord));
+        assert!(streamer.retries.is_empty());
+        update_until(&mut streamer, &mut world, &terrain, &away, |streamer, _| {
+            streamer.pending() == 0 && streamer.saves_in_flight == 0
+        });
+        assert!(!streamer.is_loaded(coord));
+        assert!(!streamer.retries.is_empty());
+        update_until(&mut streamer, &mut world, &terrain, &camera, |streamer, _| streamer.is_loaded(coord));
+        assert_eq!(world.get_voxel(pos), 9);
+
+        // Once the directory can be written the chunk is saved again, loaded or not
+        std::fs::remove_file(&dir).unwrap();
+        update_until(&mut streamer, &mut world, &terrain, &away, |streamer, _| {
+            streamer.saving.is_empty() && streamer.retries.is_empty() && streamer.saves_in_flight == 0
+        });
+        assert_eq!(streamer.store.load_chunk(coord).unwrap().unwrap().get(2, 24, 2), 9);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
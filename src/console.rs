@@ -0,0 +1,102 @@
+// console.rs
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+const MAX_LINES: usize = 1000;
+
+type Lines = Arc<Mutex<VecDeque<(log::Level, String)>>>;
+
+// Keeps the log for showing in the editor. Our own messages are kept from info up, other
+// crates' from warnings up, whatever RUST_LOG asks to print.
+struct ConsoleLogger {
+    inner: env_logger::Logger,
+    lines: Lines,
+}
+
+impl ConsoleLogger {
+    fn keeps(&self, metadata: &log::Metadata) -> bool {
+        match metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            true => metadata.level() <= log::Level::Info,
+            false => metadata.level() <= log::Level::Warn,
+        }
+    }
+}
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata) || self.keeps(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if self.keeps(record.metadata()) {
+            let mut lines = self.lines.lock().unwrap();
+            if lines.len() == MAX_LINES {
+                lines.pop_front();
+            }
+            lines.push_back((record.level(), format!("[{}] {}", record.target(), record.args())));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+pub struct Console {
+    lines: Lines,
+    pub level: log::Level, // Least severe level shown
+    pub filter: String,
+}
+
+impl Console {
+    // Takes over from env_logger, still printing what it would have
+    pub fn install() -> Self {
+        let lines = Lines::default();
+        let inner = env_logger::Builder::from_default_env().build();
+        let max_level = inner.filter().max(log::LevelFilter::Info);
+        let logger = ConsoleLogger {
+            inner,
+            lines: lines.clone(),
+        };
+        match log::set_boxed_logger(Box::new(logger)) {
+            Ok(()) => log::set_max_level(max_level),
+            Err(_) => log::warn!("A logger is already set, the console stays empty"),
+        }
+        Self {
+            lines,
+            level: log::Level::Info,
+            filter: String::new(),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for level in [log::Level::Error, log::Level::Warn, log::Level::Info] {
+                ui.selectable_value(&mut self.level, level, level.as_str());
+            }
+            ui.label("Filter:");
+            ui.text_edit_singleline(&mut self.filter);
+            if ui.button("Clear").clicked() {
+                self.lines.lock().unwrap().clear();
+            }
+        });
+        ui.separator();
+
+        let lines = self.lines.lock().unwrap();
+        for (level, text) in lines.iter() {
+            if *level > self.level || !text.contains(self.filter.as_str()) {
+                continue;
+            }
+            let color = match level {
+                log::Level::Error => ui.visuals().error_fg_color,
+                log::Level::Warn => ui.visuals().warn_fg_color,
+                _ => ui.visuals().text_color(),
+            };
+            ui.label(egui::RichText::new(text).monospace().color(color));
+        }
+    }
+}
@@ -0,0 +1,256 @@
+// editor_layout.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+// Panels of the editor, each shown as a tab in one of the docks
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tab {
+    Outliner,
+    Inspector,
+    Rendering,
+    World,
+    Import,
+    Textures,
+    Console,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 7] = [
+        Tab::Outliner,
+        Tab::Inspector,
+        Tab::Rendering,
+        Tab::World,
+        Tab::Import,
+        Tab::Textures,
+        Tab::Console,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tab::Outliner => "Outliner",
+            Tab::Inspector => "Inspector",
+            Tab::Rendering => "Rendering",
+            Tab::World => "World",
+            Tab::Import => "Import",
+            Tab::Textures => "Textures",
+            Tab::Console => "Console",
+        }
+    }
+
+    // Where the tab goes when it's opened from the menu
+    fn default_dock(self) -> Dock {
+        match self {
+            Tab::Outliner | Tab::World => Dock::Left,
+            Tab::Inspector | Tab::Rendering | Tab::Import | Tab::Textures => Dock::Right,
+            Tab::Console => Dock::Bottom,
+        }
+    }
+}
+
+// The panels around the scene, plus free floating windows holding one tab each
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Dock {
+    Left,
+    Right,
+    Bottom,
+    Window,
+}
+
+impl Dock {
+    const ALL: [Dock; 4] = [Dock::Left, Dock::Right, Dock::Bottom, Dock::Window];
+
+    fn name(self) -> &'static str {
+        match self {
+            Dock::Left => "Left",
+            Dock::Right => "Right",
+            Dock::Bottom => "Bottom",
+            Dock::Window => "Window",
+        }
+    }
+}
+
+enum LayoutChange {
+    Show(Dock, Tab),
+    Move(Tab, Dock),
+    Close(Tab),
+}
+
+// Which tab is docked where. Tabs are dragged between the tab bars of the docks, or moved
+// with their context menu or the View menu.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditorLayout {
+    tabs: Vec<(Tab, Dock)>, // Open tabs in tab bar order, closed ones are left out
+    shown: HashMap<Dock, Tab>, // The tab in front in each panel
+}
+
+// What the layout file holds. egui's memory has the panel sizes, window positions and
+// which sections are expanded.
+#[derive(Serialize, Deserialize)]
+struct LayoutFile {
+    layout: EditorLayout,
+    memory: egui::Memory,
+}
+
+impl EditorLayout {
+    pub fn new() -> Self {
+        Self {
+            tabs: Tab::ALL.iter().map(|tab| (*tab, tab.default_dock())).collect(),
+            shown: HashMap::new(),
+        }
+    }
+
+    fn tabs_in(&self, dock: Dock) -> Vec<Tab> {
+        self.tabs.iter().filter(|(_, d)| *d == dock).map(|(tab, _)| *tab).collect()
+    }
+
+    fn dock_of(&self, tab: Tab) -> Option<Dock> {
+        self.tabs.iter().find(|(t, _)| *t == tab).map(|(_, dock)| *dock)
+    }
+
+    fn apply(&mut self, change: LayoutChange) {
+        match change {
+            LayoutChange::Show(dock, tab) => {
+                self.shown.insert(dock, tab);
+            }
+            // Moved tabs go to the end of the tab bar, in front
+            LayoutChange::Move(tab, dock) => {
+                self.tabs.retain(|(t, _)| *t != tab);
+                self.tabs.push((tab, dock));
+                self.shown.insert(dock, tab);
+            }
+            LayoutChange::Close(tab) => self.tabs.retain(|(t, _)| *t != tab),
+        }
+    }
+
+    // Draws the docks around the space left for the scene, calling `add_tab` for the
+    // contents of every visible tab. Has to run before the central panel.
+    pub fn show(&mut self, ctx: &egui::Context, mut add_tab: impl FnMut(&mut egui::Ui, Tab)) {
+        let mut change = None;
+        for dock in [Dock::Left, Dock::Right, Dock::Bottom] {
+            let tabs = self.tabs_in(dock);
+            let Some(first) = tabs.first().copied() else { continue };
+            let shown = self.shown.get(&dock).copied().filter(|tab| tabs.contains(tab)).unwrap_or(first);
+            let contents = |ui: &mut egui::Ui| {
+                let (_, dropped) = ui.dnd_drop_zone::<Tab, _>(egui::Frame::none(), |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        for tab in &tabs {
+                            let id = egui::Id::new(("dock_tab", *tab));
+                            let response =
+                                ui.dnd_drag_source(id, *tab, |ui| ui.selectable_label(*tab == shown, tab.name())).inner;
+                            if response.clicked() {
+                                change = Some(LayoutChange::Show(dock, *tab));
+                            }
+                            response.context_menu(|ui| {
+                                if let Some(c) = dock_menu(ui, *tab, Some(dock)) {
+                                    change = Some(c);
+                                }
+                            });
+                        }
+                    });
+                });
+                if let Some(tab) = dropped {
+                    change = Some(LayoutChange::Move(*tab, dock));
+                }
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .id_source(("dock_scroll", dock))
+                    .auto_shrink(false)
+                    .show(ui, |ui| add_tab(ui, shown));
+            };
+            match dock {
+                Dock::Left => egui::SidePanel::left("left_dock").default_width(280.0).show(ctx, contents),
+                Dock::Right => egui::SidePanel::right("right_dock").default_width(320.0).show(ctx, contents),
+                _ => egui::TopBottomPanel::bottom("bottom_dock")
+                    .resizable(true)
+                    .default_height(160.0)
+                    .show(ctx, contents),
+            };
+        }
+
+        for tab in self.tabs_in(Dock::Window) {
+            let mut open = true;
+            egui::Window::new(tab.name())
+                .id(egui::Id::new(("dock_window", tab)))
+                .open(&mut open)
+                .resizable(true)
+                .vscroll(true)
+                .show(ctx, |ui| add_tab(ui, tab));
+            if !open {
+                change = Some(LayoutChange::Close(tab));
+            }
+        }
+
+        if let Some(change) = change {
+            self.apply(change);
+        }
+    }
+
+    // Entries for the View menu: every tab with where it's docked
+    pub fn view_menu(&mut self, ui: &mut egui::Ui) {
+        let mut change = None;
+        for tab in Tab::ALL {
+            let dock = self.dock_of(tab);
+            ui.menu_button(tab.name(), |ui| change = dock_menu(ui, tab, dock));
+        }
+        if let Some(change) = change {
+            self.apply(change);
+        }
+    }
+
+    // Back to the default docks, and forgets egui's panel sizes and window positions
+    pub fn reset(&mut self, ctx: &egui::Context) {
+        *self = Self::new();
+        ctx.memory_mut(|memory| {
+            let options = memory.options.clone();
+            *memory = egui::Memory::default();
+            memory.options = options;
+        });
+    }
+
+    // In the user's config directory, None on platforms without one
+    pub fn file_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("layout.ron"))
+    }
+
+    // Reads the layout and restores egui's memory along with it
+    pub fn load(ctx: &egui::Context) -> io::Result<Self> {
+        let path = Self::file_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        let file: LayoutFile = ron::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        ctx.memory_mut(|memory| *memory = file.memory);
+        Ok(file.layout)
+    }
+
+    pub fn save(&self, ctx: &egui::Context) -> io::Result<()> {
+        let path = Self::file_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = LayoutFile {
+            layout: self.clone(),
+            memory: ctx.memory(|memory| memory.clone()),
+        };
+        let text = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        std::fs::write(path, text)
+    }
+}
+
+// Choices for where a tab goes, `current` being where it is now
+fn dock_menu(ui: &mut egui::Ui, tab: Tab, current: Option<Dock>) -> Option<LayoutChange> {
+    let mut change = None;
+    for dock in Dock::ALL {
+        if ui.radio(current == Some(dock), dock.name()).clicked() {
+            change = Some(LayoutChange::Move(tab, dock));
+            ui.close_menu();
+        }
+    }
+    if ui.radio(current.is_none(), "Closed").clicked() {
+        change = Some(LayoutChange::Close(tab));
+        ui.close_menu();
+    }
+    change
+}
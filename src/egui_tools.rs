@@ -44,14 +44,25 @@ impl EguiRenderer {
         }
     }
 
-    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) {
-        self.state.on_window_event(window, event);
+    // Makes a wgpu texture drawable with egui::Image
+    pub fn register_native_texture(
+        &mut self,
+        device: &Device,
+        view: &TextureView,
+        filter: wgpu::FilterMode,
+    ) -> egui::TextureId {
+        self.renderer.register_native_texture(device, view, filter)
     }
 
-    pub fn ppp(&mut self, v: f32) {
-        self.state.egui_ctx().set_pixels_per_point(v);
+    pub fn free_native_texture(&mut self, id: egui::TextureId) {
+        self.renderer.free_texture(&id);
+    }
+
+    pub fn handle_input(&mut self, window: &Window, event: &WindowEvent) {
+        let _ = self.state.on_window_event(window, event);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &mut self,
         device: &Device,
@@ -67,7 +78,7 @@ impl EguiRenderer {
             .set_pixels_per_point(screen_descriptor.pixels_per_point);
 
         let raw_input = self.state.take_egui_input(window);
-        let full_output = self.state.egui_ctx().run(raw_input, |ui| {
+        let full_output = self.state.egui_ctx().run(raw_input, |_ui| {
             run_ui(self.state.egui_ctx());
         });
 
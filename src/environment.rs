@@ -0,0 +1,220 @@
+// environment.rs
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec2, Vec3};
+
+// Edge length of the largest level of the baked cube map. The sky has no sharp details,
+// so a small map is enough.
+pub const ENVIRONMENT_SIZE: u32 = 32;
+pub const ENVIRONMENT_MIPS: u32 = ENVIRONMENT_SIZE.ilog2() + 1;
+const SPECULAR_SAMPLES: u32 = 64;
+
+// Sky gradient lighting the scene from every direction, in linear color
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sky {
+    pub zenith: [f32; 3],
+    pub horizon: [f32; 3],
+    pub ground: [f32; 3],
+}
+
+impl Sky {
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let (zenith, horizon, ground) = (Vec3::from(self.zenith), Vec3::from(self.horizon), Vec3::from(self.ground));
+        if direction.y >= 0.0 {
+            horizon.lerp(zenith, direction.y.powf(0.4))
+        } else {
+            horizon.lerp(ground, (-direction.y * 5.0).min(1.0))
+        }
+    }
+
+    // Cube map faces in wgpu's order (+x, -x, +y, -y, +z, -z) for every mip level, as
+    // Rgba16Float texels. Level n is blurred for roughness n / (levels - 1) by sampling
+    // the GGX distribution around each texel's direction.
+    pub fn bake_specular(&self) -> Vec<Vec<u16>> {
+        (0..ENVIRONMENT_MIPS)
+            .map(|mip| {
+                let size = ENVIRONMENT_SIZE >> mip;
+                let roughness = mip as f32 / (ENVIRONMENT_MIPS - 1) as f32;
+                let mut texels = Vec::with_capacity((size * size * 6 * 4) as usize);
+                for face in 0..6 {
+                    for y in 0..size {
+                        for x in 0..size {
+                            let uv = (Vec2::new(x as f32, y as f32) + 0.5) / size as f32 * 2.0 - 1.0;
+                            let color = self.prefiltered(cube_direction(face, uv), roughness);
+                            texels.extend(color.extend(1.0).to_array().map(f16_bits));
+                        }
+                    }
+                }
+                texels
+            })
+            .collect()
+    }
+
+    fn prefiltered(&self, normal: Vec3, roughness: f32) -> Vec3 {
+        if roughness == 0.0 {
+            return self.radiance(normal);
+        }
+        // The view is taken to be along the normal, as usual for prefiltered maps
+        let alpha = roughness * roughness;
+        let (tangent, bitangent) = normal.any_orthonormal_pair();
+        let (mut sum, mut weight) = (Vec3::ZERO, 0.0);
+        for i in 0..SPECULAR_SAMPLES {
+            let xi = hammersley(i, SPECULAR_SAMPLES);
+            let phi = 2.0 * std::f32::consts::PI * xi.x;
+            let cos_theta = ((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y)).sqrt();
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let halfway = tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + normal * cos_theta;
+            let light = 2.0 * normal.dot(halfway) * halfway - normal;
+            let n_dot_l = normal.dot(light);
+            if n_dot_l > 0.0 {
+                sum += self.radiance(light) * n_dot_l;
+                weight += n_dot_l;
+            }
+        }
+        sum / weight.max(1e-4)
+    }
+
+    // Irradiance as nine spherical harmonics coefficients, already convolved with the cosine
+    // lobe and divided by π, so that they give the light reflected by a white matte surface
+    pub fn irradiance(&self) -> [Vec3; 9] {
+        let (width, height) = (64, 32);
+        let mut coefficients = [Vec3::ZERO; 9];
+        for y in 0..height {
+            let theta = (y as f32 + 0.5) / height as f32 * std::f32::consts::PI;
+            // Solid angle of one cell of the latitude-longitude grid
+            let solid_angle = theta.sin() * (std::f32::consts::PI / height as f32) * (2.0 * std::f32::consts::PI / width as f32);
+            for x in 0..width {
+                let phi = (x as f32 + 0.5) / width as f32 * 2.0 * std::f32::consts::PI;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                let radiance = self.radiance(direction);
+                for (coefficient, basis) in coefficients.iter_mut().zip(sh_basis(direction)) {
+                    *coefficient += radiance * basis * solid_angle;
+                }
+            }
+        }
+        // Cosine lobe convolution per band: π, 2π/3 and π/4, then the division by π
+        for (i, coefficient) in coefficients.iter_mut().enumerate() {
+            *coefficient *= match i {
+                0 => 1.0,
+                1..=3 => 2.0 / 3.0,
+                _ => 0.25,
+            };
+        }
+        coefficients
+    }
+}
+
+// Image based lighting settings. The sky is only baked again when it changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    pub enabled: bool,
+    pub intensity: f32,
+    pub sky: Sky,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            intensity: 1.0,
+            sky: Sky {
+                zenith: [0.25, 0.45, 0.8],
+                horizon: [0.7, 0.75, 0.8],
+                ground: [0.2, 0.18, 0.15],
+            },
+        }
+    }
+
+    pub fn to_uniform(&self, irradiance: &[Vec3; 9]) -> EnvironmentUniform {
+        EnvironmentUniform {
+            irradiance: irradiance.map(|c| c.extend(0.0).to_array()),
+            params: [
+                self.enabled as u32 as f32,
+                self.intensity,
+                (ENVIRONMENT_MIPS - 1) as f32,
+                0.0,
+            ],
+        }
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Light materials with the sky");
+        ui.label("Only physically based materials use it; the others keep the flat ambient light.");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            egui::Grid::new("environment_settings").num_columns(2).show(ui, |ui| {
+                ui.label("Intensity");
+                ui.add(egui::DragValue::new(&mut self.intensity).range(0.0..=10.0).speed(0.01));
+                ui.end_row();
+                ui.label("Zenith");
+                ui.color_edit_button_rgb(&mut self.sky.zenith);
+                ui.end_row();
+                ui.label("Horizon");
+                ui.color_edit_button_rgb(&mut self.sky.horizon);
+                ui.end_row();
+                ui.label("Ground");
+                ui.color_edit_button_rgb(&mut self.sky.ground);
+                ui.end_row();
+            });
+        });
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct EnvironmentUniform {
+    irradiance: [[f32; 4]; 9],
+    params: [f32; 4], // Enabled, intensity, index of the roughest mip level
+}
+
+// Direction through a texel of a cube map face, `uv` from -1 to 1 with v pointing down
+fn cube_direction(face: u32, uv: Vec2) -> Vec3 {
+    let (u, v) = (uv.x, uv.y);
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    }
+    .normalize()
+}
+
+// Evenly spread points in the unit square
+fn hammersley(i: u32, count: u32) -> Vec2 {
+    Vec2::new(i as f32 / count as f32, i.reverse_bits() as f32 * 2.328_306_4e-10)
+}
+
+// The first nine real spherical harmonics, in the order the shader expects
+fn sh_basis(d: Vec3) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+// Half precision bits of a float. Values too small for a normal half become zero and values
+// too large the largest half.
+fn f16_bits(value: f32) -> u16 {
+    if value.is_nan() {
+        return 0x7e00;
+    }
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = ((bits >> 13) & 0x3ff) as u16;
+    if exponent <= 0 {
+        sign
+    } else if exponent >= 31 {
+        sign | 0x7bff
+    } else {
+        sign | (exponent as u16) << 10 | mantissa
+    }
+}
@@ -0,0 +1,309 @@
+// gizmo.rs
+
+use crate::camera::Camera;
+use crate::ray::Ray;
+use crate::scene::{NodeId, Scene};
+use crate::vertex::Vertex;
+use glam::{Quat, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GizmoSpace {
+    World,
+    Local,
+}
+
+const AXIS_COLORS: [[f32; 3]; 3] = [[1.0, 0.2, 0.2], [0.2, 1.0, 0.2], [0.3, 0.4, 1.0]];
+const HIGHLIGHT_COLOR: [f32; 3] = [1.0, 1.0, 0.2];
+const RING_SEGMENTS: usize = 48;
+
+// Handle positions in world space for the selected node
+struct GizmoFrame {
+    origin: Vec3,
+    axes: [Vec3; 3],
+    size: f32,
+}
+
+struct Drag {
+    node: NodeId,
+    axis: usize,
+    frame: GizmoFrame,
+    start_param: f32,   // Distance along the axis for move and scale
+    start_vector: Vec3, // Direction from the center within the ring's plane for rotate
+    start_translation: Vec3,
+    start_rotation: Quat,
+    start_scale: Vec3,
+}
+
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    pub space: GizmoSpace,
+    pub snap: bool,
+    pub translate_snap: f32,
+    pub rotate_snap_degrees: f32,
+    pub scale_snap: f32,
+    hovered: Option<usize>,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            space: GizmoSpace::World,
+            snap: false,
+            translate_snap: 0.25,
+            rotate_snap_degrees: 15.0,
+            scale_snap: 0.1,
+            hovered: None,
+            drag: None,
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    fn frame(&self, scene: &Scene, id: NodeId, camera: &Camera) -> Option<GizmoFrame> {
+        scene.node(id)?;
+        let (_, rotation, origin) = scene.world_matrix(id).to_scale_rotation_translation();
+
+        // Scale handles always follow the node's own axes, since a scale along
+        // arbitrary world axes can't be expressed by the node's transform
+        let axes = if self.space == GizmoSpace::Local || self.mode == GizmoMode::Scale {
+            [rotation * Vec3::X, rotation * Vec3::Y, rotation * Vec3::Z]
+        } else {
+            [Vec3::X, Vec3::Y, Vec3::Z]
+        };
+
+        // Keep the gizmo roughly the same size on screen
+        let size = camera.apparent_distance(origin) * 0.15;
+        Some(GizmoFrame { origin, axes, size })
+    }
+
+    // Returns the handle under the ray, preferring the one closest to the camera
+    fn pick_axis(&self, frame: &GizmoFrame, ray: &Ray) -> Option<usize> {
+        let threshold = frame.size * 0.08;
+        let mut best: Option<(usize, f32)> = None;
+
+        for (i, axis) in frame.axes.iter().enumerate() {
+            let hit = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    ray.closest_to_line(frame.origin, *axis).and_then(|(t, s)| {
+                        let on_handle = t > 0.0 && (0.0..=frame.size).contains(&s);
+                        let distance = ray.at(t).distance(frame.origin + *axis * s);
+                        (on_handle && distance < threshold).then_some(t)
+                    })
+                }
+                GizmoMode::Rotate => ray.intersect_plane(frame.origin, *axis).filter(|t| {
+                    let radius = ray.at(*t).distance(frame.origin);
+                    (radius - frame.size).abs() < threshold
+                }),
+            };
+
+            if let Some(t) = hit {
+                if best.is_none_or(|(_, best_t)| t < best_t) {
+                    best = Some((i, t));
+                }
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+
+    // Distance along the axis for translate/scale, angle around it for rotate
+    fn drag_param(&self, frame: &GizmoFrame, axis: usize, ray: &Ray) -> Option<(f32, Vec3)> {
+        let axis_dir = frame.axes[axis];
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => ray
+                .closest_to_line(frame.origin, axis_dir)
+                .map(|(_, s)| (s, Vec3::ZERO)),
+            GizmoMode::Rotate => ray
+                .intersect_plane(frame.origin, axis_dir)
+                .map(|t| (0.0, (ray.at(t) - frame.origin).normalize_or_zero())),
+        }
+    }
+
+    pub fn hover(&mut self, ray: &Ray, scene: &Scene, selected: Option<NodeId>, camera: &Camera) {
+        if self.drag.is_none() {
+            self.hovered = selected
+                .and_then(|id| self.frame(scene, id, camera))
+                .and_then(|frame| self.pick_axis(&frame, ray));
+        }
+    }
+
+    // Grabs a handle under the ray. Returns false if the ray missed the gizmo.
+    pub fn begin_drag(&mut self, ray: &Ray, scene: &Scene, selected: Option<NodeId>, camera: &Camera) -> bool {
+        let Some(id) = selected else { return false };
+        let Some(frame) = self.frame(scene, id, camera) else { return false };
+        let Some(axis) = self.pick_axis(&frame, ray) else { return false };
+        let Some((start_param, start_vector)) = self.drag_param(&frame, axis, ray) else {
+            return false;
+        };
+        let transform = scene.node(id).unwrap().transform;
+
+        self.hovered = Some(axis);
+        self.drag = Some(Drag {
+            node: id,
+            axis,
+            frame,
+            start_param,
+            start_vector,
+            start_translation: transform.translation,
+            start_rotation: transform.rotation,
+            start_scale: transform.scale,
+        });
+        true
+    }
+
+    pub fn drag(&mut self, ray: &Ray, scene: &mut Scene) {
+        let Some(drag) = &self.drag else { return };
+        let Some((param, vector)) = self.drag_param(&drag.frame, drag.axis, ray) else {
+            return;
+        };
+        let axis = drag.frame.axes[drag.axis];
+
+        // Edits happen in world space and are brought back into the parent's space
+        let parent_matrix = scene
+            .node(drag.node)
+            .and_then(|n| n.parent())
+            .map(|p| scene.world_matrix(p))
+            .unwrap_or_default();
+        let Some(node) = scene.node_mut(drag.node) else {
+            self.drag = None;
+            return;
+        };
+
+        match self.mode {
+            GizmoMode::Translate => {
+                let mut delta = param - drag.start_param;
+                if self.snap && self.translate_snap > 0.0 {
+                    delta = (delta / self.translate_snap).round() * self.translate_snap;
+                }
+                let local_delta = parent_matrix.inverse().transform_vector3(axis * delta);
+                node.transform.translation = drag.start_translation + local_delta;
+            }
+            GizmoMode::Rotate => {
+                if vector == Vec3::ZERO || drag.start_vector == Vec3::ZERO {
+                    return;
+                }
+                let mut angle = drag
+                    .start_vector
+                    .cross(vector)
+                    .dot(axis)
+                    .atan2(drag.start_vector.dot(vector));
+                if self.snap && self.rotate_snap_degrees > 0.0 {
+                    let step = self.rotate_snap_degrees.to_radians();
+                    angle = (angle / step).round() * step;
+                }
+                let (_, parent_rotation, _) = parent_matrix.to_scale_rotation_translation();
+                let world_rotation = Quat::from_axis_angle(axis, angle);
+                node.transform.rotation =
+                    (parent_rotation.inverse() * world_rotation * parent_rotation * drag.start_rotation)
+                        .normalize();
+            }
+            GizmoMode::Scale => {
+                if drag.start_param.abs() < 1e-4 {
+                    return;
+                }
+                let factor = param / drag.start_param;
+                let mut scale = drag.start_scale[drag.axis] * factor;
+                if self.snap && self.scale_snap > 0.0 {
+                    scale = (scale / self.scale_snap).round() * self.scale_snap;
+                }
+                node.transform.scale[drag.axis] = scale.max(0.01);
+            }
+        }
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    // Line list geometry for the gizmo of the selected node, in world space
+    pub fn lines(&self, scene: &Scene, selected: Option<NodeId>, camera: &Camera) -> Vec<Vertex> {
+        let mut lines = Vec::new();
+        let Some(frame) = selected.and_then(|id| self.frame(scene, id, camera)) else {
+            return lines;
+        };
+        let active = self.drag.as_ref().map(|d| d.axis).or(self.hovered);
+
+        for (i, axis) in frame.axes.iter().enumerate() {
+            let color = if active == Some(i) { HIGHLIGHT_COLOR } else { AXIS_COLORS[i] };
+            let tip = frame.origin + *axis * frame.size;
+            // Two directions perpendicular to the axis, used for arrow heads, boxes and rings
+            let side_a = frame.axes[(i + 1) % 3];
+            let side_b = frame.axes[(i + 2) % 3];
+
+            match self.mode {
+                GizmoMode::Translate => {
+                    push_line(&mut lines, frame.origin, tip, color);
+                    let head = frame.size * 0.1;
+                    let base = tip - *axis * head * 2.0;
+                    for side in [side_a, -side_a, side_b, -side_b] {
+                        push_line(&mut lines, tip, base + side * head, color);
+                    }
+                }
+                GizmoMode::Scale => {
+                    push_line(&mut lines, frame.origin, tip, color);
+                    let half = frame.size * 0.06;
+                    let corners = [
+                        tip + (side_a + side_b) * half,
+                        tip + (side_a - side_b) * half,
+                        tip + (-side_a - side_b) * half,
+                        tip + (-side_a + side_b) * half,
+                    ];
+                    for c in 0..4 {
+                        push_line(&mut lines, corners[c], corners[(c + 1) % 4], color);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    for s in 0..RING_SEGMENTS {
+                        let point = |k: usize| {
+                            let angle = k as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                            frame.origin + (side_a * angle.cos() + side_b * angle.sin()) * frame.size
+                        };
+                        push_line(&mut lines, point(s), point(s + 1), color);
+                    }
+                }
+            }
+        }
+        lines
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.mode, GizmoMode::Translate, "Move");
+            ui.selectable_value(&mut self.mode, GizmoMode::Rotate, "Rotate");
+            ui.selectable_value(&mut self.mode, GizmoMode::Scale, "Scale");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Space:");
+            ui.selectable_value(&mut self.space, GizmoSpace::World, "World");
+            ui.selectable_value(&mut self.space, GizmoSpace::Local, "Local");
+        });
+        ui.checkbox(&mut self.snap, "Snapping");
+        ui.add_enabled_ui(self.snap, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.translate_snap).speed(0.01).prefix("move: "));
+                ui.add(
+                    egui::DragValue::new(&mut self.rotate_snap_degrees)
+                        .speed(0.5)
+                        .prefix("rotate: ")
+                        .suffix("°"),
+                );
+                ui.add(egui::DragValue::new(&mut self.scale_snap).speed(0.01).prefix("scale: "));
+            });
+        });
+    }
+}
+
+fn push_line(lines: &mut Vec<Vertex>, from: Vec3, to: Vec3, color: [f32; 3]) {
+    lines.push(Vertex::new(from.to_array(), color));
+    lines.push(Vertex::new(to.to_array(), color));
+}
@@ -0,0 +1,283 @@
+// gltf_model.rs
+
+use crate::mesh::{Mesh, MeshId};
+use crate::renderer::SceneRenderer;
+use crate::scene::{Material, MaterialTextures, Node, NodeId, Scene, ShadingModel, Transform};
+use crate::texture::TextureId;
+use crate::vertex::Vertex;
+use egui_wgpu::wgpu;
+use glam::{Quat, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+
+// One drawable part of a glTF mesh: triangles sharing a material
+struct Primitive {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    material: Option<usize>,
+}
+
+// A glTF or GLB file with its buffers and images loaded, ready to be added to a scene
+pub struct GltfModel {
+    pub name: String,
+    document: gltf::Document,
+    images: Vec<gltf::image::Data>,
+    meshes: Vec<Vec<Primitive>>, // Per glTF mesh
+}
+
+// What an import added to the renderer and scene
+pub struct GltfImport {
+    pub root: NodeId,
+    pub meshes: Vec<(String, MeshId)>,
+    pub textures: usize,
+}
+
+impl GltfModel {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let (document, buffers, images) =
+            gltf::import(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let meshes = document
+            .meshes()
+            .map(|mesh| mesh.primitives().filter_map(|p| read_primitive(&p, &buffers)).collect())
+            .collect();
+        let name = path.file_stem().map_or("Model".to_string(), |s| s.to_string_lossy().into_owned());
+        Ok(Self {
+            name,
+            document,
+            images,
+            meshes,
+        })
+    }
+
+    // Uploads the meshes and textures, and adds the file's scene below a new node with
+    // the given transform
+    pub fn add_to_scene(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut SceneRenderer,
+        scene: &mut Scene,
+        transform: Transform,
+    ) -> GltfImport {
+        // Images can be used as color in one material and as data in another, which need
+        // different texture formats
+        let mut textures: HashMap<(usize, bool), TextureId> = HashMap::new();
+        let mut texture = |info: Option<gltf::Texture>, srgb: bool| {
+            let index = info?.source().index();
+            let image = self.images.get(index)?;
+            Some(*textures.entry((index, srgb)).or_insert_with(|| {
+                let name = format!("{} image {}", self.name, index);
+                let pixels = to_rgba8(image);
+                renderer.create_texture(device, queue, &name, image.width, image.height, &pixels, srgb)
+            }))
+        };
+
+        let materials: Vec<Material> = self
+            .document
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                let normal = material.normal_texture();
+                let occlusion = material.occlusion_texture();
+                let emissive_strength = material.emissive_strength().unwrap_or(1.0);
+                Material {
+                    shading: ShadingModel::MetallicRoughness,
+                    color: pbr.base_color_factor(),
+                    metallic: pbr.metallic_factor(),
+                    roughness: pbr.roughness_factor(),
+                    emissive: material.emissive_factor().map(|c| c * emissive_strength),
+                    normal_scale: normal.as_ref().map_or(1.0, |n| n.scale()),
+                    occlusion_strength: occlusion.as_ref().map_or(1.0, |o| o.strength()),
+                    alpha_cutoff: match material.alpha_mode() {
+                        gltf::material::AlphaMode::Mask => material.alpha_cutoff().unwrap_or(0.5),
+                        _ => 0.0,
+                    },
+                    double_sided: material.double_sided(),
+                    textures: MaterialTextures {
+                        base_color: texture(pbr.base_color_texture().map(|t| t.texture()), true),
+                        metallic_roughness: texture(pbr.metallic_roughness_texture().map(|t| t.texture()), false),
+                        normal: texture(normal.map(|t| t.texture()), false),
+                        occlusion: texture(occlusion.map(|t| t.texture()), false),
+                        emissive: texture(material.emissive_texture().map(|t| t.texture()), true),
+                    },
+                    ..Material::default()
+                }
+            })
+            .collect();
+        let texture_count = textures.len();
+
+        // glTF's default for primitives without a material: plain white, fully metallic and rough
+        let default_material = Material {
+            shading: ShadingModel::MetallicRoughness,
+            metallic: 1.0,
+            roughness: 1.0,
+            ..Material::default()
+        };
+
+        let mut added_meshes = Vec::new();
+        let parts: Vec<Vec<(MeshId, Material)>> = self
+            .document
+            .meshes()
+            .zip(&self.meshes)
+            .map(|(mesh, primitives)| {
+                let name = mesh.name().map_or_else(|| format!("{} mesh {}", self.name, mesh.index()), str::to_string);
+                primitives
+                    .iter()
+                    .enumerate()
+                    .map(|(i, primitive)| {
+                        let label = if primitives.len() > 1 { format!("{} {}", name, i + 1) } else { name.clone() };
+                        let id = renderer.add_mesh(Mesh::new(device, &label, &primitive.vertices, &primitive.indices));
+                        added_meshes.push((label, id));
+                        let material = primitive.material.and_then(|m| materials.get(m));
+                        (id, material.unwrap_or(&default_material).clone())
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let root = scene.add_node(Node::new(&self.name, None, transform), None);
+        let roots: Vec<gltf::Node> = match self.document.default_scene().or_else(|| self.document.scenes().next()) {
+            Some(gltf_scene) => gltf_scene.nodes().collect(),
+            // Without a scene, every node that isn't a child of another one
+            None => {
+                let children: HashSet<usize> =
+                    self.document.nodes().flat_map(|n| n.children().map(|c| c.index())).collect();
+                self.document.nodes().filter(|n| !children.contains(&n.index())).collect()
+            }
+        };
+        for node in roots {
+            add_node(node, root, scene, &parts, 0);
+        }
+
+        GltfImport {
+            root,
+            meshes: added_meshes,
+            textures: texture_count,
+        }
+    }
+}
+
+fn add_node(node: gltf::Node, parent: NodeId, scene: &mut Scene, parts: &[Vec<(MeshId, Material)>], depth: usize) {
+    // Guards against cycles in malformed files
+    if depth > 64 {
+        return;
+    }
+    let (translation, rotation, scale) = node.transform().decomposed();
+    let transform = Transform {
+        translation: Vec3::from(translation),
+        rotation: Quat::from_array(rotation),
+        scale: Vec3::from(scale),
+    };
+    let name = node.name().map_or_else(|| format!("Node {}", node.index()), str::to_string);
+    let node_parts = node.mesh().and_then(|mesh| parts.get(mesh.index())).map_or(&[][..], |p| p.as_slice());
+
+    // Scene nodes draw one mesh each, so a mesh in several parts gets a child node per part
+    let mut scene_node = Node::new(&name, None, transform);
+    if let [(mesh, material)] = node_parts {
+        scene_node.mesh = Some(*mesh);
+        scene_node.material = material.clone();
+    }
+    let id = scene.add_node(scene_node, Some(parent));
+    if node_parts.len() > 1 {
+        for (i, (mesh, material)) in node_parts.iter().enumerate() {
+            let mut part = Node::new(format!("{} part {}", name, i + 1), Some(*mesh), Transform::default());
+            part.material = material.clone();
+            scene.add_node(part, Some(id));
+        }
+    }
+
+    for child in node.children() {
+        add_node(child, id, scene, parts, depth + 1);
+    }
+}
+
+// Reads a triangle list primitive, leaving out the ones drawn as points or lines
+fn read_primitive(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Option<Primitive> {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        return None;
+    }
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+    let positions: Vec<[f32; 3]> = reader.read_positions()?.collect();
+    let mut indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    indices.truncate(indices.len() / 3 * 3);
+    if indices.iter().any(|i| *i as usize >= positions.len()) {
+        return None;
+    }
+
+    let normals: Vec<[f32; 3]> = match reader.read_normals() {
+        Some(normals) => normals.collect(),
+        None => smooth_normals(&positions, &indices),
+    };
+    let uvs: Vec<[f32; 2]> = reader.read_tex_coords(0).map_or_else(Vec::new, |uvs| uvs.into_f32().collect());
+    let colors: Vec<[f32; 3]> = reader.read_colors(0).map_or_else(Vec::new, |colors| colors.into_rgb_f32().collect());
+
+    let vertices = positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| {
+            Vertex::new(*position, colors.get(i).copied().unwrap_or([1.0; 3]))
+                .with_normal(normals.get(i).copied().unwrap_or([0.0, 1.0, 0.0]))
+                .with_uv(uvs.get(i).copied().unwrap_or([0.0; 2]))
+        })
+        .collect();
+
+    Some(Primitive {
+        vertices,
+        indices,
+        material: primitive.material().index(),
+    })
+}
+
+// Vertex normals averaged from the triangles around each vertex, weighted by their area
+fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(positions[triangle[i] as usize]));
+        let normal = (b - a).cross(c - a);
+        for i in triangle {
+            normals[*i as usize] += normal;
+        }
+    }
+    normals.iter().map(|n| n.normalize_or(Vec3::Y).to_array()).collect()
+}
+
+// Expands any of the image formats glTF files decode to into RGBA8
+fn to_rgba8(image: &gltf::image::Data) -> Vec<u8> {
+    use gltf::image::Format;
+    let (channels, size) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    // 16 bit channels keep their high byte, float ones are clamped to 0..1
+    let channel = |bytes: &[u8]| match size {
+        1 => bytes[0],
+        2 => bytes[1],
+        _ => (f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0).round() as u8,
+    };
+    image
+        .pixels
+        .chunks_exact(channels * size)
+        .flat_map(|texel| {
+            let c = |i: usize| channel(&texel[i * size..(i + 1) * size]);
+            match channels {
+                1 => [c(0), c(0), c(0), 255],
+                2 => [c(0), c(1), 0, 255],
+                3 => [c(0), c(1), c(2), 255],
+                _ => [c(0), c(1), c(2), c(3)],
+            }
+        })
+        .collect()
+}
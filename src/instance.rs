@@ -0,0 +1,90 @@
+// instance.rs
+
+use crate::scene::Transform;
+use bytemuck::{Pod, Zeroable};
+use egui_wgpu::wgpu;
+
+// Handle to a batch of instances of one mesh, owned by the renderer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceBatchId(pub usize);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Instance {
+    pub transform: Transform,
+    pub color: [f32; 4],
+}
+
+impl Instance {
+    pub fn new(transform: Transform, color: [f32; 4]) -> Self {
+        Self { transform, color }
+    }
+
+    pub fn to_raw(self) -> InstanceRaw {
+        InstanceRaw {
+            model: self.transform.matrix().to_cols_array_2d(),
+            color: self.color,
+        }
+    }
+}
+
+// Per-instance vertex data. A mat4 doesn't fit in one attribute, so it takes four slots.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct InstanceRaw {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+
+impl InstanceRaw {
+    // Locations start after the room left for per-vertex attributes
+    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4, 9 => Float32x4
+    ];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+// Growable GPU buffer of instances, reused between updates while it is large enough
+pub struct InstanceBuffer {
+    pub buffer: wgpu::Buffer,
+    pub count: u32,
+    capacity: usize,
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &wgpu::Device, instances: &[InstanceRaw]) -> Self {
+        let capacity = instances.len().max(1).next_power_of_two();
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: true,
+        });
+        buffer.slice(..).get_mapped_range_mut()[..std::mem::size_of_val(instances)]
+            .copy_from_slice(bytemuck::cast_slice(instances));
+        buffer.unmap();
+
+        Self {
+            buffer,
+            count: instances.len() as u32,
+            capacity,
+        }
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[InstanceRaw]) {
+        if instances.len() > self.capacity {
+            *self = Self::new(device, instances);
+            return;
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        self.count = instances.len() as u32;
+    }
+}
@@ -1,28 +1,77 @@
+mod blocks;
+mod bounds;
 mod egui_tools;
 mod camera;
+mod chunk_streamer;
+mod console;
+mod editor_layout;
+mod environment;
+mod gizmo;
+mod gltf_model;
+mod instance;
+mod lighting;
+mod lights;
+mod lod;
+mod mesh;
+mod mesh_jobs;
+mod mesher;
+mod physics;
+mod picking;
+mod ray;
+mod region;
+mod renderer;
+mod scene;
+mod scene_editor;
+mod shadows;
+mod terrain;
+mod texture;
+mod texture_viewer;
 mod vertex;
+mod viewport;
+mod vox;
+mod voxel;
+mod voxel_editor;
+mod worker_pool;
 
 use crate::egui_tools::EguiRenderer;
+use blocks::BlockRegistry;
 use camera::Camera;
+use chunk_streamer::ChunkStreamer;
+use console::Console;
+use editor_layout::{EditorLayout, Tab};
+use gizmo::Gizmo;
+use gltf_model::GltfModel;
+use instance::Instance;
+use lights::Light;
+use lod::ChunkLods;
+use mesh::{Mesh, MeshId};
+use mesh_jobs::MeshWorkers;
+use mesher::MeshingMode;
+use physics::{MoveInput, MovementMode, Player};
+use renderer::SceneRenderer;
+use scene::{Node, Scene, ShadingModel, Transform};
+use scene_editor::SceneEditor;
+use texture::TextureId;
+use terrain::{TerrainGenerator, TerrainParams};
+use texture_viewer::TextureViewer;
 use vertex::Vertex;
+use viewport::ViewportLayout;
+use vox::VoxFile;
+use voxel::{chunk_coord, VoxelWorld, CHUNK_SIZE};
+use voxel_editor::VoxelEditor;
 use egui_wgpu::wgpu::{InstanceDescriptor, PowerPreference, RequestAdapterOptions, TextureFormat};
 use egui_wgpu::{wgpu, ScreenDescriptor};
-use glam::{Mat4, Vec3};
-use std::collections::HashMap;
+use glam::{IVec3, Quat, Vec2, Vec3};
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Instant;
 use winit::dpi::PhysicalSize;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, MouseButton, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::keyboard::{Key, ModifiersState, NamedKey};
-use wgpu::util::DeviceExt;
-
-// Rendering styles enum
-enum RenderingStyle {
-    Polygon,
-    Cube,
-}
 
 pub async fn run() {
+    let mut console = Console::install();
     let event_loop = EventLoop::new().unwrap();
 
     let builder = winit::window::WindowBuilder::new().with_title("Voxxele");
@@ -30,9 +79,9 @@ pub async fn run() {
     let window = Arc::new(window);
     let initial_width = 1360;
     let initial_height = 768;
-    window.request_inner_size(PhysicalSize::new(initial_width, initial_height));
+    let _ = window.request_inner_size(PhysicalSize::new(initial_width, initial_height));
 
-    let mut camera = Camera::new(Vec3::new(0.0, 0.0, 2.0), Vec3::ZERO, 0.1);
+    let mut camera = Camera::new(Vec3::new(0.0, 1.0, 4.0), Vec3::ZERO, 0.1);
 
     // Create the wgpu instance and surface
     let instance = egui_wgpu::wgpu::Instance::new(InstanceDescriptor::default());
@@ -84,157 +133,125 @@ pub async fn run() {
 
     surface.configure(&device, &config);
 
-    // Load shaders
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Main Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-    });
-    
-    let challenge_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("Challenge Shader"),
-        source: wgpu::ShaderSource::Wgsl(include_str!("challenge_shader.wgsl").into()),
-    });
-    
-    // Pipeline compilation options
-    let mut constants = HashMap::new();
-    constants.insert("MY_CONSTANT".to_string(), 1.0); // Example constant value, replace as needed
-
-    let compilation_options = wgpu::PipelineCompilationOptions {
-        constants: &constants, // Pipeline-overridable constants
-        zero_initialize_workgroup_memory: true, // Set based on your requirements
-    };
-
-    // Group vertex and fragment state creation together
-    let vertex_state_main = wgpu::VertexState {
-        module: &shader,
-        entry_point: "vs_main",
-        buffers: &[Vertex::desc()], // Use the Vertex description
-        compilation_options: compilation_options.clone(), // Added compilation options
-    };
-
-    let fragment_state_main = wgpu::FragmentState {
-        module: &shader,
-        entry_point: "fs_main", // Entry point in your fragment shader
-        targets: &[Some(wgpu::ColorTargetState {
-            format: config.format,
-            blend: Some(wgpu::BlendState {
-                color: wgpu::BlendComponent::REPLACE,
-                alpha: wgpu::BlendComponent::REPLACE,
-            }),
-            write_mask: wgpu::ColorWrites::ALL,
-        })],
-        compilation_options: compilation_options.clone(),
-    };
-
-    let vertex_state_challenge = wgpu::VertexState {
-        module: &challenge_shader,
-        entry_point: "vs_main",
-        buffers: &[Vertex::desc()], // Use the Vertex description
-        compilation_options: compilation_options.clone(),
-    };
-
-    let fragment_state_challenge = wgpu::FragmentState {
-        module: &challenge_shader,
-        entry_point: "fs_main", // Entry point in your fragment shader
-        targets: &[Some(wgpu::ColorTargetState {
-            format: config.format,
-            blend: Some(wgpu::BlendState {
-                color: wgpu::BlendComponent::REPLACE,
-                alpha: wgpu::BlendComponent::REPLACE,
-            }),
-            write_mask: wgpu::ColorWrites::ALL,
-        })],
-        compilation_options: compilation_options.clone(),
-    };
-
-    // Create render pipeline layout
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("Render Pipeline Layout"),
-        bind_group_layouts: &[],
-        push_constant_ranges: &[],
-    });
-
-    // Create the main render pipeline
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
-        vertex: vertex_state_main,
-        fragment: Some(fragment_state_main),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            polygon_mode: wgpu::PolygonMode::Fill,
-            unclipped_depth: false,
-            conservative: false,
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-    });
-
-    // Create the challenge render pipeline
-    let challenge_render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Challenge Render Pipeline"),
-        layout: Some(&render_pipeline_layout),
-        vertex: vertex_state_challenge,
-        fragment: Some(fragment_state_challenge),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            polygon_mode: wgpu::PolygonMode::Fill,
-            unclipped_depth: false,
-            conservative: false,
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
-    });
+    let mut scene_renderer = SceneRenderer::new(&device, &queue, &config);
+    let mut depth_view = SceneRenderer::create_depth_view(&device, config.width, config.height);
+    let mut viewports = ViewportLayout::new(camera.target);
 
     let mut sides: u16 = 5; 
-    let mut rendering_style = RenderingStyle::Polygon; // Default to polygon
     let mut previous_sides = sides;
 
-    // Generate polygon vertices and indices
+    // Generate the meshes shared by the scene nodes
     let (vertices, indices) = Vertex::generate_polygon(sides, 0.5);
-
-    // Create the vertex buffer
-    let mut vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(&vertices),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
-
-    // Create the index buffer
-    let mut index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Index Buffer"),
-        contents: bytemuck::cast_slice(&indices),
-        usage: wgpu::BufferUsages::INDEX,
-    });
-
-    let mut num_indices = indices.len() as u32;
+    let polygon_mesh = scene_renderer.add_mesh(Mesh::new(&device, "Polygon", &vertices, &indices));
+    let (vertices, indices) = Vertex::generate_cube();
+    let cube_mesh = scene_renderer.add_mesh(Mesh::new(&device, "Cube", &vertices, &indices));
+    let (vertices, indices) = Vertex::generate_sphere(32, 16, 0.5);
+    let sphere_mesh = scene_renderer.add_mesh(Mesh::new(&device, "Sphere", &vertices, &indices));
+
+    // Build a small scene: a cube with a polygon attached to it, and a second cube next to them
+    let mut scene = Scene::new();
+    let cube = scene.add_node(Node::new("Cube", Some(cube_mesh), Transform::default()), None);
+    let mut polygon = Node::new(
+        "Polygon",
+        Some(polygon_mesh),
+        Transform::from_translation(Vec3::new(0.0, 0.0, 0.51)),
+    );
+    polygon.material.color = [1.0, 1.0, 0.0, 1.0];
+    scene.add_node(polygon, Some(cube));
+    let mut second_cube = Node::new(
+        "Second Cube",
+        Some(cube_mesh),
+        Transform {
+            translation: Vec3::new(1.5, 0.0, -1.0),
+            rotation: Quat::from_rotation_y(0.6),
+            scale: Vec3::splat(0.5),
+        },
+    );
+    second_cube.material.color = [0.6, 0.8, 1.0, 1.0];
+    scene.add_node(second_cube, Some(cube));
+    // A polished metal sphere on the other side, shaded with the physically based material model
+    let mut sphere = Node::new("Sphere", Some(sphere_mesh), Transform::from_translation(Vec3::new(-1.5, 0.0, -1.0)));
+    sphere.material.shading = ShadingModel::MetallicRoughness;
+    sphere.material.color = [0.95, 0.64, 0.54, 1.0];
+    sphere.material.metallic = 1.0;
+    sphere.material.roughness = 0.3;
+    scene.add_node(sphere, None);
+
+    // A sun, a warm point light beside the cubes and a spot light shining down on them
+    let mut sun = Light::directional("Sun", Vec3::new(-0.4, -0.8, -0.3));
+    sun.color = [1.0, 0.96, 0.9];
+    scene.lights.lights.push(sun);
+    let mut lamp = Light::point("Lamp", Vec3::new(-1.5, 1.0, 1.5), 8.0);
+    lamp.color = [1.0, 0.7, 0.4];
+    lamp.intensity = 4.0;
+    scene.lights.lights.push(lamp);
+    let mut spot = Light::spot("Spot", Vec3::new(1.5, 3.0, -1.0), Vec3::NEG_Y, 25.0);
+    spot.color = [0.5, 0.7, 1.0];
+    spot.intensity = 8.0;
+    scene.lights.lights.push(spot);
+
+    // A grid of instanced cubes below the scene, sized from the UI
+    let mut instance_count: u32 = 0;
+    let mut previous_instance_count = instance_count;
+    let instance_batch = scene_renderer.add_instance_batch(&device, cube_mesh, &[]);
+
+    // Block types come from the data file when it's there, the copy built into the binary otherwise
+    let blocks = Arc::new(BlockRegistry::load("assets/blocks.ron").unwrap_or_else(|e| {
+        log::warn!("Couldn't load assets/blocks.ron, using the built-in block types: {}", e);
+        BlockRegistry::builtin()
+    }));
+    scene_renderer.set_block_textures(&device, &queue, blocks.texture_layers());
+
+    let mut terrain = TerrainGenerator::new(TerrainParams::default());
+    let mut voxel_world = VoxelWorld::new();
+    // Edited chunks are saved next to the executable's working directory
+    let mut chunk_streamer = ChunkStreamer::new(4, "world", &terrain.params);
+    let mut terrain_changed = false;
+    let mut meshing_mode = MeshingMode::Greedy;
+    let mut previous_meshing_mode = meshing_mode;
+    let mut mesh_workers = MeshWorkers::with_available_threads(blocks.clone());
+    let mut chunk_upload_budget: usize = 4;
+    let mut chunk_lods = ChunkLods::new();
+    let chunk_light_budget: usize = 2;
+
+    let mut mesh_choices = vec![
+        ("Cube".to_string(), cube_mesh),
+        ("Polygon".to_string(), polygon_mesh),
+        ("Sphere".to_string(), sphere_mesh),
+    ];
+    let mut scene_editor = SceneEditor::new();
+    let mut gizmo = Gizmo::new();
+    let mut voxel_editor = VoxelEditor::new();
+    let mut vox_path = String::from("model.vox");
+    let mut vox_status = String::new();
+    let mut gltf_path = String::from("model.glb");
+    let mut gltf_status = String::new();
+    let mut texture_path = String::from("texture.png");
+    let mut texture_srgb = true;
+    let mut texture_status = String::new();
+    let mut texture_viewer = TextureViewer::new();
+    let mut cursor_position = Vec2::ZERO;
+    let mut modifiers = ModifiersState::empty();
+    let mut player = Player::new();
+    let mut held_keys: HashSet<String> = HashSet::new(); // Lowercase characters, " " for space
+    let mut last_frame = Instant::now();
 
     let mut egui_renderer = EguiRenderer::new(&device, config.format, None, 1, &window);
+    // Panel sizes and docked tabs from the last session, the defaults on the first run
+    let mut editor_layout = EditorLayout::load(egui_renderer.context()).unwrap_or_else(|e| {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Failed to load the editor layout: {}", e);
+        }
+        EditorLayout::new()
+    });
 
     let mut close_requested = false;
-    let mut modifiers = ModifiersState::default();
 
     let mut scale_factor = 1.0;
 
     let mut active_shader = "main";
 
-    event_loop.run(move |event, elwt| {
+    let _ = event_loop.run(move |event, elwt| {
         elwt.set_control_flow(ControlFlow::Poll);
 
         match event {
@@ -245,42 +262,174 @@ pub async fn run() {
                     WindowEvent::CloseRequested => {
                         close_requested = true;
                     }
-                    WindowEvent::ModifiersChanged(new) => {
-                        modifiers = new.state();
-                    }
                     WindowEvent::KeyboardInput {
                         event: kb_event, ..
                     } => {
                         if kb_event.logical_key == Key::Named(NamedKey::Escape) {
                             close_requested = true;
                         }
+
+                        let key = match &kb_event.logical_key {
+                            Key::Character(c) => Some(c.to_lowercase()),
+                            Key::Named(NamedKey::Space) => Some(" ".to_string()),
+                            _ => None,
+                        };
+                        if let Some(key) = key {
+                            if kb_event.state == ElementState::Released {
+                                held_keys.remove(&key);
+                            } else if !egui_renderer.context().wants_keyboard_input() {
+                                held_keys.insert(key);
+                            }
+                        }
+
+                        // Fly the camera with WASD unless egui is using the keyboard. Walking
+                        // reads the held keys every frame instead.
+                        let flying = player.mode == MovementMode::Fly;
+                        if kb_event.state == ElementState::Pressed
+                            && !egui_renderer.context().wants_keyboard_input()
+                        {
+                            if let Key::Character(c) = &kb_event.logical_key {
+                                match c.as_str() {
+                                    "z" if modifiers.control_key() && modifiers.shift_key() => {
+                                        voxel_editor.redo(&mut voxel_world)
+                                    }
+                                    "z" if modifiers.control_key() => voxel_editor.undo(&mut voxel_world),
+                                    "y" if modifiers.control_key() => voxel_editor.redo(&mut voxel_world),
+                                    "w" if flying => viewports.focused_camera(&mut camera).move_forward(),
+                                    "s" if flying => viewports.focused_camera(&mut camera).move_backward(),
+                                    "a" if flying => viewports.focused_camera(&mut camera).strafe_left(),
+                                    "d" if flying => viewports.focused_camera(&mut camera).strafe_right(),
+                                    "f" if !kb_event.repeat => {
+                                        if flying {
+                                            player.start_walking(&voxel_world, &blocks, &camera);
+                                        } else {
+                                            player.mode = MovementMode::Fly;
+                                        }
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                    }
+                    WindowEvent::ModifiersChanged(new_modifiers) => modifiers = new_modifiers.state(),
+                    // Key releases go elsewhere while unfocused
+                    WindowEvent::Focused(false) => held_keys.clear(),
+                    WindowEvent::CursorMoved { position, .. } => {
+                        cursor_position = Vec2::new(position.x as f32, position.y as f32);
+                        let window_size = Vec2::new(config.width as f32, config.height as f32);
+                        let (view_camera, cursor, viewport) = viewports.pointer(&camera, cursor_position, window_size);
+                        let ray = view_camera.screen_ray(cursor, viewport);
+                        if gizmo.is_dragging() {
+                            gizmo.drag(&ray, &mut scene);
+                        } else {
+                            gizmo.hover(&ray, &scene, scene_editor.selected, view_camera);
+                        }
                     }
-                    WindowEvent::Resized(new_size) => {
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    } => match state {
+                        ElementState::Pressed
+                            if viewports.takes_pointer(egui_renderer.context().wants_pointer_input()) =>
+                        {
+                            let window_size = Vec2::new(config.width as f32, config.height as f32);
+                            let (view_camera, cursor, viewport) = viewports.pointer(&camera, cursor_position, window_size);
+                            let ray = view_camera.screen_ray(cursor, viewport);
+                            // Handles of the current selection take priority over objects behind them
+                            if voxel_editor.enabled {
+                                voxel_editor.apply(&mut voxel_world);
+                            } else if !gizmo.begin_drag(&ray, &scene, scene_editor.selected, view_camera) {
+                                scene_editor.selected =
+                                    picking::pick(&scene, scene_renderer.meshes(), &ray).map(|(id, _)| id);
+                            }
+                        }
+                        ElementState::Released => gizmo.end_drag(),
+                        _ => {}
+                    },
+                    // A minimized window reports a zero size, which the surface can't be configured with
+                    WindowEvent::Resized(new_size) if new_size.width > 0 && new_size.height > 0 => {
                         config.width = new_size.width;
                         config.height = new_size.height;
                         surface.configure(&device, &config);
+                        depth_view = SceneRenderer::create_depth_view(&device, config.width, config.height);
                     }
                     WindowEvent::RedrawRequested => {
-                        if sides != previous_sides || matches!(rendering_style, RenderingStyle::Cube) {
-                            let (new_vertices, new_indices) = match rendering_style {
-                                RenderingStyle::Polygon => Vertex::generate_polygon(sides, 0.5),
-                                RenderingStyle::Cube => Vertex::generate_cube(),  // Call generate_cube here
+                        if sides != previous_sides {
+                            let (new_vertices, new_indices) = Vertex::generate_polygon(sides, 0.5);
+                            scene_renderer.replace_mesh(
+                                polygon_mesh,
+                                Mesh::new(&device, "Polygon", &new_vertices, &new_indices),
+                            );
+                            previous_sides = sides; // Update the previous_sides value
+                        }
+
+                        let now = Instant::now();
+                        // Capped so a long stall doesn't launch the player through the ground
+                        let dt = (now - last_frame).as_secs_f32().min(0.1);
+                        last_frame = now;
+
+                        // Hold still until the chunk the player stands in has loaded
+                        let player_chunk = chunk_coord(player.position.floor().as_ivec3());
+                        if player.mode == MovementMode::Walk && chunk_streamer.is_loaded(player_chunk) {
+                            let forward = (camera.target - camera.position).with_y(0.0).normalize_or_zero();
+                            let right = forward.cross(Vec3::Y);
+                            let axis = |positive: &str, negative: &str| {
+                                held_keys.contains(positive) as i32 as f32 - held_keys.contains(negative) as i32 as f32
                             };
-                            
-                            vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: Some("Vertex Buffer"),
-                                contents: bytemuck::cast_slice(&new_vertices),
-                                usage: wgpu::BufferUsages::VERTEX,
-                            });
-                    
-                            index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                                label: Some("Index Buffer"),
-                                contents: bytemuck::cast_slice(&new_indices),
-                                usage: wgpu::BufferUsages::INDEX,
+                            let direction = forward * axis("w", "s") + right * axis("d", "a");
+                            let input = MoveInput {
+                                direction: Vec2::new(direction.x, direction.z).normalize_or_zero(),
+                                jump: held_keys.contains(" "),
+                            };
+                            player.update(&voxel_world, &blocks, &input, dt);
+                            player.attach_camera(&mut camera);
+                        }
+
+                        // Wait for slider drags to finish, regenerating on every step would stall
+                        if terrain_changed && !egui_renderer.context().input(|i| i.pointer.any_down()) {
+                            chunk_streamer.reset(&mut voxel_world, &terrain.params);
+                            terrain_changed = false;
+                        }
+                        chunk_streamer.update(&mut voxel_world, &terrain, &camera);
+                        // Nothing past the loaded chunks needs drawing
+                        camera.zfar = ((chunk_streamer.view_distance + 1) * CHUNK_SIZE as i32) as f32;
+
+                        if previous_meshing_mode != meshing_mode {
+                            let coords: Vec<IVec3> = voxel_world.chunks().map(|(coord, _)| coord).collect();
+                            for coord in coords {
+                                voxel_world.mark_dirty(coord);
+                            }
+                            previous_meshing_mode = meshing_mode;
+                        }
+
+                        lighting::update(&mut voxel_world, &blocks, chunk_light_budget);
+                        chunk_lods.update(&mut voxel_world, camera.position);
+
+                        // Mesh changed chunks in the background and upload a few finished ones per frame.
+                        // Unlit chunks are skipped; lighting them flags them again.
+                        for coord in voxel_world.take_dirty() {
+                            if voxel_world.needs_light(coord) {
+                                continue;
+                            }
+                            let chunk = chunk_lods.padded_chunk(&voxel_world, coord);
+                            mesh_workers.submit(coord, chunk, meshing_mode, chunk_lods.level(coord));
+                        }
+                        for result in mesh_workers.drain(chunk_upload_budget) {
+                            let mesh = (!result.data.is_empty()).then(|| {
+                                Mesh::new_gpu_only(&device, "Chunk", &result.data.vertices, &result.data.indices)
                             });
-                    
-                            num_indices = new_indices.len() as u32;
-                            previous_sides = sides; // Update the previous_sides value
+                            scene_renderer.set_chunk_mesh(result.coord, mesh, result.level);
+                        }
+
+                        if instance_count != previous_instance_count {
+                            scene_renderer.update_instance_batch(
+                                &device,
+                                &queue,
+                                instance_batch,
+                                &instance_grid(instance_count),
+                            );
+                            previous_instance_count = instance_count;
                         }
                     
                         let surface_texture = surface
@@ -301,39 +450,58 @@ pub async fn run() {
                             pixels_per_point: window.scale_factor() as f32 * scale_factor,
                         };
                 
-                        // Use the main render pipeline
-                        {
-                            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                                label: Some("Render Pass"),
-                                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                                    view: &surface_view,
-                                    resolve_target: None,
-                                    ops: wgpu::Operations {
-                                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                                            r: 0.1,
-                                            g: 0.2,
-                                            b: 0.3,
-                                            a: 1.0,
-                                        }),
-                                        store: wgpu::StoreOp::Store,
-                                    },
-                                })],
-                                depth_stencil_attachment: None,
-                                // Adding missing fields with default values
-                                occlusion_query_set: None, // Default value, as occlusion queries aren't used
-                                timestamp_writes: None,    // Default value, as no timestamps are written
+                        scene_renderer.set_outlined(scene_editor.selected);
+                        viewports.prepare(&device, &mut egui_renderer, config.format);
+
+                        // Only target voxels when the cursor isn't over the UI
+                        let window_size = Vec2::new(config.width as f32, config.height as f32);
+                        let (view_camera, cursor, viewport) = viewports.pointer(&camera, cursor_position, window_size);
+                        let cursor_ray = viewports
+                            .takes_pointer(egui_renderer.context().is_pointer_over_area())
+                            .then(|| view_camera.screen_ray(cursor, viewport));
+                        voxel_editor.update_target(&voxel_world, cursor_ray.as_ref());
+
+                        let mut shared_lines = voxel_editor.lines(&blocks);
+                        let frozen_frustums = viewports.cullings().map(|(_, culling)| culling);
+                        shared_lines.extend(scene_renderer.frustum_lines(frozen_frustums));
+                        shared_lines.extend(scene.lights.lines());
+
+                        // The scene goes to the panels' render targets when there are any, the window otherwise
+                        let scene_targets: Vec<_> = if viewports.has_targets() {
+                            viewports
+                                .targets(&camera)
+                                .map(|(target, view_camera, culling)| {
+                                    (&target.view, &target.depth_view, view_camera, target.aspect(), culling)
+                                })
+                                .collect()
+                        } else {
+                            let aspect = window_size.x / window_size.y;
+                            vec![(&surface_view, &depth_view, &camera, aspect, viewports.window_culling())]
+                        };
+                        for (scene_view, scene_depth_view, view_camera, aspect, culling) in scene_targets {
+                            let mut overlay_lines = gizmo.lines(&scene, scene_editor.selected, view_camera);
+                            overlay_lines.extend_from_slice(&shared_lines);
+                            scene_renderer.set_overlay_lines(&device, &queue, &overlay_lines);
+                            // Buffer writes land when work is submitted, so every view submits its own
+                            let mut scene_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                                label: Some("Scene Encoder"),
                             });
-                        
-                            match active_shader {
-                                "main" => render_pass.set_pipeline(&render_pipeline),
-                                "challenge" => render_pass.set_pipeline(&challenge_render_pipeline),
-                                _ => render_pass.set_pipeline(&render_pipeline), // Default fallback
-                            }
-                            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-                            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                            render_pass.draw_indexed(0..num_indices, 0, 0..1);
-                        }                        
+                            scene_renderer.render(
+                                &device,
+                                &queue,
+                                &mut scene_encoder,
+                                scene_view,
+                                scene_depth_view,
+                                &scene,
+                                view_camera,
+                                aspect,
+                                active_shader,
+                                culling,
+                            );
+                            queue.submit(Some(scene_encoder.finish()));
+                        }
                 
+                        texture_viewer.prepare(&device, &mut egui_renderer, scene_renderer.textures());
                         egui_renderer.draw(
                             &device,
                             &queue,
@@ -342,13 +510,55 @@ pub async fn run() {
                             &surface_view,
                             screen_descriptor,
                             |ctx| {
-                                egui::Window::new("UI Window")
-                                    .resizable(true)
-                                    .vscroll(true)
-                                    .default_open(true)
-                                    .show(ctx, |ui| {
-                                        ui.label("Vertex and Shader control");
-    
+                                egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
+                                    egui::menu::bar(ui, |ui| {
+                                        ui.menu_button("File", |ui| {
+                                            if ui.button("Save world").clicked() {
+                                                chunk_streamer.save_all(&mut voxel_world);
+                                                ui.close_menu();
+                                            }
+                                            if ui.button("Save layout").clicked() {
+                                                if let Err(e) = editor_layout.save(ctx) {
+                                                    log::error!("Failed to save the editor layout: {}", e);
+                                                }
+                                                ui.close_menu();
+                                            }
+                                            ui.separator();
+                                            if ui.button("Quit").clicked() {
+                                                close_requested = true;
+                                            }
+                                        });
+                                        ui.menu_button("View", |ui| {
+                                            editor_layout.view_menu(ui);
+                                            ui.separator();
+                                            viewports.settings_ui(ui, camera.target);
+                                            ui.separator();
+                                            if ui.button("Reset layout").clicked() {
+                                                editor_layout.reset(ctx);
+                                                ui.close_menu();
+                                            }
+                                        });
+                                    });
+                                });
+
+                                editor_layout.show(ctx, |ui, tab| match tab {
+                                    Tab::Outliner => {
+                                        let mesh_names: Vec<(&str, MeshId)> =
+                                            mesh_choices.iter().map(|(name, mesh)| (name.as_str(), *mesh)).collect();
+                                        scene_editor.outliner_ui(ui, &mut scene, &mesh_names);
+                                    }
+                                    Tab::Inspector => {
+                                        let mesh_names: Vec<(&str, MeshId)> =
+                                            mesh_choices.iter().map(|(name, mesh)| (name.as_str(), *mesh)).collect();
+                                        let texture_names: Vec<(&str, TextureId)> = scene_renderer
+                                            .textures()
+                                            .iter()
+                                            .enumerate()
+                                            .map(|(i, texture)| (texture.name.as_str(), TextureId(i)))
+                                            .collect();
+                                        scene_editor.inspector_ui(ui, &mut scene, &mesh_names, &texture_names);
+                                    }
+                                    Tab::Rendering => {
                                         if ui.button("Switch Shader").clicked() {
                                             if active_shader == "main" {
                                                 active_shader = "challenge"; // Switch to challenge shader
@@ -356,37 +566,37 @@ pub async fn run() {
                                                 active_shader = "main"; // Switch back to main shader
                                             }
                                         }
-    
+                                        ui.horizontal(|ui| {
+                                            ui.label(format!("Polygon sides: {}", sides));
+                                            if ui.button("-").clicked() {
+                                                sides = (sides - 1).max(3); // Ensure a minimum of 3 sides
+                                            }
+                                            if ui.button("+").clicked() {
+                                                sides = (sides + 1).min(12); // Set a max number of sides, for example, 12
+                                            }
+                                        });
+
+                                        ui.horizontal(|ui| {
+                                            ui.label("Instanced cubes:");
+                                            ui.add(egui::DragValue::new(&mut instance_count).range(0..=10000));
+                                        });
+
                                         ui.separator();
-    
-                                        // Add the UI component to adjust the number of sides for polygons
-                                        if let RenderingStyle::Polygon = rendering_style {
-                                            ui.horizontal(|ui| {
-                                                ui.label(format!("Polygon sides: {}", sides));
-                                                if ui.button("-").clicked() {
-                                                    sides = (sides - 1).max(3); // Ensure a minimum of 3 sides
-                                                }
-                                                if ui.button("+").clicked() {
-                                                    sides = (sides + 1).min(12); // Set a max number of sides, for example, 12
-                                                }
-                                            });
-                                        }
-    
-                                        // Add button to switch rendering style
+                                        ui.collapsing("Lights", |ui| scene.lights.settings_ui(ui));
+                                        ui.collapsing("Environment", |ui| scene_renderer.environment.settings_ui(ui));
+                                        ui.collapsing("Shadows", |ui| scene_renderer.shadows.settings_ui(ui));
+
                                         ui.separator();
-                                        if ui.button("Switch to Cube").clicked() {
-                                            rendering_style = match rendering_style {
-                                                RenderingStyle::Polygon => RenderingStyle::Cube,
-                                                RenderingStyle::Cube => RenderingStyle::Polygon,
-                                            };
-                                        }
-    
+                                        ui.collapsing("Culling", |ui| {
+                                            scene_renderer.culling_ui(ui, viewports.cullings())
+                                        });
+
+                                        ui.separator();
+                                        ui.collapsing("Gizmo", |ui| gizmo.settings_ui(ui));
+
                                         ui.separator();
                                         ui.horizontal(|ui| {
-                                            ui.label(format!(
-                                                "Pixels per point: {}",
-                                                ctx.pixels_per_point()
-                                            ));
+                                            ui.label(format!("Pixels per point: {}", ui.ctx().pixels_per_point()));
                                             if ui.button("-").clicked() {
                                                 scale_factor = (scale_factor - 0.1).max(0.3);
                                             }
@@ -394,7 +604,192 @@ pub async fn run() {
                                                 scale_factor = (scale_factor + 0.1).min(3.0);
                                             }
                                         });
-                                    });
+                                    }
+                                    Tab::World => {
+                                        ui.horizontal(|ui| {
+                                            ui.label("Voxel meshing:");
+                                            ui.selectable_value(&mut meshing_mode, MeshingMode::Culled, "Culled");
+                                            ui.selectable_value(&mut meshing_mode, MeshingMode::Greedy, "Greedy");
+                                        });
+                                        ui.horizontal(|ui| {
+                                            ui.label("Chunk uploads per frame:");
+                                            ui.add(egui::DragValue::new(&mut chunk_upload_budget).range(1..=64));
+                                        });
+                                        ui.label(format!("Chunks waiting for meshes: {}", mesh_workers.pending()));
+                                        ui.horizontal(|ui| {
+                                            ui.label("View distance:");
+                                            ui.add(
+                                                egui::DragValue::new(&mut chunk_streamer.view_distance)
+                                                    .range(1..=16)
+                                                    .suffix(" chunks"),
+                                            );
+                                        });
+                                        ui.label(format!(
+                                            "Chunks loaded: {}, waiting to load: {}",
+                                            chunk_streamer.loaded_count(),
+                                            chunk_streamer.pending()
+                                        ));
+                                        let stored = voxel_world.chunk_count();
+                                        let bits: u32 =
+                                            voxel_world.chunks().map(|(_, chunk)| chunk.bits_per_voxel()).sum();
+                                        ui.label(format!(
+                                            "Chunks stored: {}, {:.1} bits per voxel on average",
+                                            stored,
+                                            bits as f32 / stored.max(1) as f32
+                                        ));
+                                        if ui.button("Save world").clicked() {
+                                            chunk_streamer.save_all(&mut voxel_world);
+                                        }
+
+                                        ui.separator();
+                                        ui.collapsing("Movement", |ui| {
+                                            player.settings_ui(ui, &voxel_world, &blocks, &camera)
+                                        });
+
+                                        ui.separator();
+                                        ui.collapsing("Level of detail", |ui| {
+                                            chunk_lods.settings_ui(ui);
+                                            ui.checkbox(&mut scene_renderer.show_lod_levels, "Color chunks by level");
+                                        });
+
+                                        ui.separator();
+                                        ui.collapsing("Terrain", |ui| {
+                                            if terrain.settings_ui(ui) {
+                                                terrain_changed = true;
+                                            }
+                                        });
+
+                                        ui.separator();
+                                        ui.collapsing("Voxel editing", |ui| {
+                                            voxel_editor.settings_ui(ui, &mut voxel_world, &blocks);
+                                        });
+                                    }
+                                    Tab::Import => {
+                                        ui.collapsing("MagicaVoxel", |ui| {
+                                            ui.horizontal(|ui| {
+                                                ui.label("File:");
+                                                ui.text_edit_singleline(&mut vox_path);
+                                            });
+                                            ui.horizontal(|ui| {
+                                                if ui.button("Import into world").clicked() {
+                                                    // Placed with its lowest corner at the point the camera looks at
+                                                    vox_status = match VoxFile::load(&vox_path) {
+                                                        Ok(file) => {
+                                                            file.insert_into(&mut voxel_world, camera.target.floor().as_ivec3());
+                                                            format!("Imported {} models", file.models.len())
+                                                        }
+                                                        Err(e) => format!("Failed to import {}: {}", vox_path, e),
+                                                    };
+                                                }
+                                                if ui.button("Import as object").clicked() {
+                                                    vox_status = match VoxFile::load(&vox_path) {
+                                                        Ok(file) if file.voxels().is_empty() => {
+                                                            format!("{} has no voxels", vox_path)
+                                                        }
+                                                        Ok(file) => {
+                                                            let (vertices, indices) = file.mesh();
+                                                            let name = std::path::Path::new(&vox_path)
+                                                                .file_stem()
+                                                                .map_or("Model".to_string(), |s| s.to_string_lossy().into_owned());
+                                                            let mesh = scene_renderer
+                                                                .add_mesh(Mesh::new(&device, &name, &vertices, &indices));
+                                                            mesh_choices.push((name.clone(), mesh));
+                                                            let transform = Transform {
+                                                                translation: camera.target,
+                                                                rotation: Quat::IDENTITY,
+                                                                scale: Vec3::splat(0.1),
+                                                            };
+                                                            scene_editor.selected =
+                                                                Some(scene.add_node(Node::new(&name, Some(mesh), transform), None));
+                                                            format!("Added {} to the scene", name)
+                                                        }
+                                                        Err(e) => format!("Failed to import {}: {}", vox_path, e),
+                                                    };
+                                                }
+                                                if ui.button("Export world").clicked() {
+                                                    vox_status = match VoxFile::from_world(&voxel_world, &blocks)
+                                                        .and_then(|file| file.save(&vox_path).map(|_| file))
+                                                    {
+                                                        Ok(file) => format!("Exported {} models", file.models.len()),
+                                                        Err(e) => format!("Failed to export {}: {}", vox_path, e),
+                                                    };
+                                                }
+                                            });
+                                            if !vox_status.is_empty() {
+                                                ui.label(&vox_status);
+                                            }
+                                        });
+
+                                        ui.collapsing("glTF", |ui| {
+                                            ui.horizontal(|ui| {
+                                                ui.label("File:");
+                                                ui.text_edit_singleline(&mut gltf_path);
+                                            });
+                                            if ui.button("Import as object").clicked() {
+                                                // Placed at the point the camera looks at, in the file's own units
+                                                gltf_status = match GltfModel::load(&gltf_path) {
+                                                    Ok(model) => {
+                                                        let import = model.add_to_scene(
+                                                            &device,
+                                                            &queue,
+                                                            &mut scene_renderer,
+                                                            &mut scene,
+                                                            Transform::from_translation(camera.target),
+                                                        );
+                                                        let summary = format!(
+                                                            "Added {} with {} meshes and {} textures",
+                                                            model.name,
+                                                            import.meshes.len(),
+                                                            import.textures
+                                                        );
+                                                        mesh_choices.extend(import.meshes);
+                                                        scene_editor.selected = Some(import.root);
+                                                        summary
+                                                    }
+                                                    Err(e) => format!("Failed to import {}: {}", gltf_path, e),
+                                                };
+                                            }
+                                            if !gltf_status.is_empty() {
+                                                ui.label(&gltf_status);
+                                            }
+                                        });
+
+                                        ui.collapsing("Textures", |ui| {
+                                            ui.horizontal(|ui| {
+                                                ui.label("File:");
+                                                ui.text_edit_singleline(&mut texture_path);
+                                            });
+                                            ui.horizontal(|ui| {
+                                                ui.checkbox(&mut texture_srgb, "sRGB color");
+                                                if ui.button("Load").clicked() {
+                                                    // Shown in the viewer, and picked from the inspector's texture slots
+                                                    texture_status =
+                                                        match scene_renderer.load_texture(&device, &queue, &texture_path, texture_srgb) {
+                                                            Ok(id) => {
+                                                                texture_viewer.selected = Some(id.0);
+                                                                format!("Loaded {}", texture_path)
+                                                            }
+                                                            Err(e) => format!("Failed to load {}: {}", texture_path, e),
+                                                        };
+                                                }
+                                            });
+                                            if !texture_status.is_empty() {
+                                                ui.label(&texture_status);
+                                            }
+                                            ui.label("Material sampling");
+                                            scene_renderer.material_sampling.settings_ui(ui);
+                                        });
+                                    }
+                                    Tab::Textures => texture_viewer.ui(ui, scene_renderer.textures()),
+                                    Tab::Console => console.ui(ui),
+                                });
+
+                                // Fills whatever the other panels leave, so it has to come last
+                                if viewports.enabled {
+                                    egui::CentralPanel::default()
+                                        .frame(egui::Frame::none())
+                                        .show(ctx, |ui| viewports.ui(ui));
+                                }
                             },
                         );
                 
@@ -406,12 +801,39 @@ pub async fn run() {
                 }                
             }
 
-            Event::AboutToWait => {
-                if close_requested {
-                    elwt.exit()
+            Event::AboutToWait if close_requested => {
+                if let Err(e) = chunk_streamer.save_and_wait(&mut voxel_world) {
+                    log::error!("Failed to save the world: {}", e);
+                }
+                if let Err(e) = editor_layout.save(egui_renderer.context()) {
+                    log::error!("Failed to save the editor layout: {}", e);
                 }
+                elwt.exit();
             }
             _ => {}
         }
     });
 }
+
+// Cubes laid out on a square grid, colored by their position in it
+fn instance_grid(count: u32) -> Vec<Instance> {
+    let side = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let spacing = 0.3;
+    let offset = (side - 1) as f32 * spacing * 0.5;
+
+    (0..count)
+        .map(|i| {
+            let (x, z) = (i % side, i / side);
+            let transform = Transform {
+                translation: Vec3::new(x as f32 * spacing - offset, -1.5, z as f32 * spacing - offset),
+                rotation: Quat::IDENTITY,
+                scale: Vec3::splat(0.2),
+            };
+            let color = [x as f32 / side as f32, 0.5, z as f32 / side as f32, 1.0];
+            Instance::new(transform, color)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod headless_check;
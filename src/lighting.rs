@@ -0,0 +1,369 @@
+// lighting.rs
+
+use crate::blocks::BlockRegistry;
+use crate::voxel::{chunk_coord, VoxelWorld, CHUNK_SIZE, CHUNK_VOLUME};
+use glam::IVec3;
+use std::collections::VecDeque;
+
+pub const MAX_LIGHT: u8 = 15;
+
+const NEIGHBOURS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+// The two kinds of light, each flood filled on its own
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Channel {
+    Sky,   // Sunlight coming down from above the loaded world
+    Block, // Light given off by emissive blocks
+}
+
+impl Channel {
+    fn get(self, light: u8) -> u8 {
+        match self {
+            Channel::Sky => light >> 4,
+            Channel::Block => light & 0x0f,
+        }
+    }
+
+    fn with(self, light: u8, level: u8) -> u8 {
+        match self {
+            Channel::Sky => (light & 0x0f) | (level << 4),
+            Channel::Block => (light & 0xf0) | level,
+        }
+    }
+
+    // Level a neighbour in `direction` receives: one less per step, except full sunlight,
+    // which falls straight down without fading
+    fn spread(self, level: u8, direction: IVec3) -> u8 {
+        if self == Channel::Sky && level == MAX_LIGHT && direction == IVec3::NEG_Y {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+pub fn sky_light(light: u8) -> u8 {
+    Channel::Sky.get(light)
+}
+
+pub fn block_light(light: u8) -> u8 {
+    Channel::Block.get(light)
+}
+
+// Light is only stored in chunks that exist. Everything else reads as open sky, which
+// makes missing chunks above the loaded area act as the source of sunlight.
+fn stored(world: &VoxelWorld, pos: IVec3) -> bool {
+    world.chunk(chunk_coord(pos)).is_some()
+}
+
+// Floods light outwards from the queued voxels into every darker, see-through voxel
+fn propagate(world: &mut VoxelWorld, blocks: &BlockRegistry, channel: Channel, mut queue: VecDeque<IVec3>) {
+    while let Some(pos) = queue.pop_front() {
+        let level = channel.get(world.get_light(pos));
+        for direction in NEIGHBOURS {
+            let next = pos + direction;
+            let spread = channel.spread(level, direction);
+            if spread == 0 || !stored(world, next) || blocks.is_opaque(world.get_voxel(next)) {
+                continue;
+            }
+            let light = world.get_light(next);
+            if channel.get(light) < spread {
+                world.set_light(next, channel.with(light, spread));
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+// Darkens everything that got its light from the queued voxels, given with the level they
+// had. Voxels lit from somewhere else are collected in `relight` to fill the gap back in.
+fn remove(
+    world: &mut VoxelWorld,
+    channel: Channel,
+    mut queue: VecDeque<(IVec3, u8)>,
+    relight: &mut VecDeque<IVec3>,
+) {
+    while let Some((pos, level)) = queue.pop_front() {
+        for direction in NEIGHBOURS {
+            let next = pos + direction;
+            let light = world.get_light(next);
+            let neighbour = channel.get(light);
+            if neighbour == 0 {
+                continue;
+            }
+            if stored(world, next) && neighbour <= channel.spread(level, direction) {
+                world.set_light(next, channel.with(light, 0));
+                queue.push_back((next, neighbour));
+            } else {
+                relight.push_back(next);
+            }
+        }
+    }
+}
+
+// Sets up the light of a newly inserted chunk and updates its surroundings, which were
+// lit as if it was open sky until now
+fn light_chunk(world: &mut VoxelWorld, blocks: &BlockRegistry, coord: IVec3) {
+    let Some(chunk) = world.chunk(coord) else { return };
+    let voxels = chunk.to_blocks();
+    let size = CHUNK_SIZE as i32;
+    let origin = coord * size;
+    let index = |local: IVec3| (local.y as usize * CHUNK_SIZE + local.z as usize) * CHUNK_SIZE + local.x as usize;
+    let inside = |local: IVec3| local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(size)).all();
+
+    // Voxels on each face of the chunk (chunk-local), together with the one just outside it
+    let mut faces = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE * 6);
+    for axis in 0..3 {
+        for (near, far) in [(0, -1), (size - 1, size)] {
+            for u in 0..size {
+                for v in 0..size {
+                    let mut local = IVec3::ZERO;
+                    local[axis] = near;
+                    local[(axis + 1) % 3] = u;
+                    local[(axis + 2) % 3] = v;
+                    let mut beyond = local;
+                    beyond[axis] = far;
+                    faces.push((local, beyond));
+                }
+            }
+        }
+    }
+
+    // Take away the sunlight the neighbours got through here while the chunk was missing
+    world.fill_light(coord, 0);
+    let mut sky_relight = VecDeque::new();
+    let removed = faces.iter().map(|(local, _)| (origin + *local, MAX_LIGHT)).collect();
+    remove(world, Channel::Sky, removed, &mut sky_relight);
+
+    // Flood fill the inside on its own, fed by the light just outside it and its emissive
+    // blocks, which is a lot faster than going through the world voxel by voxel
+    let opaque: Vec<bool> = voxels.iter().map(|block| blocks.is_opaque(*block)).collect();
+    let mut light = vec![0u8; CHUNK_VOLUME];
+    for channel in [Channel::Sky, Channel::Block] {
+        let mut queue = VecDeque::new();
+        for (local, beyond) in &faces {
+            let level = channel.spread(channel.get(world.get_light(origin + *beyond)), *local - *beyond);
+            let i = index(*local);
+            if !opaque[i] && channel.get(light[i]) < level {
+                light[i] = channel.with(light[i], level);
+                queue.push_back(*local);
+            }
+        }
+        if channel == Channel::Block {
+            for (i, block) in voxels.iter().enumerate() {
+                if blocks.get(*block).is_some_and(|block| block.emissive) {
+                    light[i] = channel.with(light[i], MAX_LIGHT);
+                    let (x, z, y) = (i % CHUNK_SIZE, (i / CHUNK_SIZE) % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE));
+                    queue.push_back(IVec3::new(x as i32, y as i32, z as i32));
+                }
+            }
+        }
+
+        while let Some(local) = queue.pop_front() {
+            let level = channel.get(light[index(local)]);
+            for direction in NEIGHBOURS {
+                let next = local + direction;
+                let spread = channel.spread(level, direction);
+                if spread == 0 || !inside(next) || opaque[index(next)] {
+                    continue;
+                }
+                let i = index(next);
+                if channel.get(light[i]) < spread {
+                    light[i] = channel.with(light[i], spread);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    world.set_chunk_light(coord, light);
+
+    // Pass the chunk's light on to its neighbours, along with what the removal left to refill
+    let border: Vec<IVec3> = faces.iter().map(|(local, _)| origin + *local).collect();
+    sky_relight.extend(border.iter().copied());
+    propagate(world, blocks, Channel::Sky, sky_relight);
+    propagate(world, blocks, Channel::Block, border.into());
+}
+
+// Relights around a voxel whose block changed
+fn update_voxel(world: &mut VoxelWorld, blocks: &BlockRegistry, pos: IVec3) {
+    if !stored(world, pos) {
+        return;
+    }
+    let old = world.get_light(pos);
+    let block = world.get_voxel(pos);
+    let emissive = blocks.get(block).is_some_and(|block| block.emissive);
+    world.set_light(pos, Channel::Block.with(0, if emissive { MAX_LIGHT } else { 0 }));
+
+    for channel in [Channel::Sky, Channel::Block] {
+        let mut relight = VecDeque::new();
+        remove(world, channel, VecDeque::from([(pos, channel.get(old))]), &mut relight);
+        if channel == Channel::Block && emissive {
+            relight.push_back(pos);
+        }
+        // Light flows back in from the sides once the voxel is see-through
+        if !blocks.is_opaque(block) {
+            relight.extend(NEIGHBOURS.map(|direction| pos + direction));
+        }
+        propagate(world, blocks, channel, relight);
+    }
+}
+
+// Brings the world's light up to date with the chunks inserted and voxels edited since the
+// last call, lighting at most `chunk_budget` new chunks. Changed light marks meshes dirty.
+pub fn update(world: &mut VoxelWorld, blocks: &BlockRegistry, chunk_budget: usize) {
+    for coord in world.take_unlit(chunk_budget) {
+        light_chunk(world, blocks, coord);
+    }
+    for pos in world.take_light_edits() {
+        // Chunks still waiting to be lit get these edits with the rest
+        if !world.needs_light(chunk_coord(pos)) {
+            update_voxel(world, blocks, pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{BlockId, Chunk, AIR};
+
+    const STONE: BlockId = 1;
+    const LAMP: BlockId = 7;
+
+    fn fill(world: &mut VoxelWorld, min: IVec3, max: IVec3, block: BlockId) {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    world.set_voxel(IVec3::new(x, y, z), block);
+                }
+            }
+        }
+    }
+
+    fn sky(world: &VoxelWorld, pos: IVec3) -> u8 {
+        sky_light(world.get_light(pos))
+    }
+
+    fn block(world: &VoxelWorld, pos: IVec3) -> u8 {
+        block_light(world.get_light(pos))
+    }
+
+    // Chunk 0 with a stone floor at y = 0, a closed stone box from 2 to 8 and a roof on
+    // stilts over x and z 0 to 15 at y = 10
+    fn sheltered_world(blocks: &BlockRegistry) -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::default());
+        fill(&mut world, IVec3::new(0, 0, 0), IVec3::new(31, 0, 31), STONE);
+        fill(&mut world, IVec3::new(0, 10, 0), IVec3::new(15, 10, 15), STONE);
+        fill(&mut world, IVec3::splat(2), IVec3::splat(8), STONE);
+        fill(&mut world, IVec3::splat(3), IVec3::splat(7), AIR);
+        update(&mut world, blocks, usize::MAX);
+        world
+    }
+
+    #[test]
+    fn sky_light_is_blocked_by_roofs() {
+        let blocks = BlockRegistry::builtin();
+        let world = sheltered_world(&blocks);
+        assert_eq!(sky(&world, IVec3::new(24, 1, 24)), MAX_LIGHT);
+        assert_eq!(sky(&world, IVec3::new(24, 20, 24)), MAX_LIGHT);
+        assert_eq!(sky(&world, IVec3::new(16, 5, 12)), MAX_LIGHT);
+        // Under the roof it only comes in from the sides, nearest from x = 16
+        assert_eq!(sky(&world, IVec3::new(15, 5, 12)), MAX_LIGHT - 1);
+        assert_eq!(sky(&world, IVec3::new(12, 5, 12)), MAX_LIGHT - 4);
+        assert_eq!(sky(&world, IVec3::splat(5)), 0);
+        assert_eq!(block(&world, IVec3::new(24, 1, 24)), 0);
+    }
+
+    #[test]
+    fn block_light_falls_off_by_one_per_block() {
+        let blocks = BlockRegistry::builtin();
+        let mut world = VoxelWorld::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::default());
+        update(&mut world, &blocks, usize::MAX);
+        let lamp = IVec3::new(8, 16, 16);
+        world.set_voxel(lamp, LAMP);
+        update(&mut world, &blocks, usize::MAX);
+
+        assert_eq!(block(&world, lamp), MAX_LIGHT);
+        for distance in 1..=16 {
+            let expected = MAX_LIGHT.saturating_sub(distance as u8);
+            assert_eq!(block(&world, lamp + IVec3::X * distance), expected, "{}", distance);
+            assert_eq!(block(&world, lamp - IVec3::Y * distance), expected, "{}", distance);
+        }
+        assert_eq!(block(&world, lamp + IVec3::new(3, 2, -4)), MAX_LIGHT - 9);
+    }
+
+    #[test]
+    fn edits_relight_the_world() {
+        let blocks = BlockRegistry::builtin();
+        let mut world = sheltered_world(&blocks);
+        let inside = IVec3::new(5, 3, 5);
+
+        // A hole in the top of the box lets the light under the roof in
+        let hole = IVec3::new(5, 8, 5);
+        let under_roof = sky(&world, IVec3::new(5, 9, 5));
+        world.set_voxel(hole, AIR);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(sky(&world, hole), under_roof - 1);
+        assert_eq!(sky(&world, inside), under_roof - 6);
+
+        // And closing it darkens the box again
+        world.set_voxel(hole, STONE);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(sky(&world, inside), 0);
+
+        // A lamp lights it up until it's taken away
+        world.set_voxel(inside, LAMP);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(block(&world, IVec3::new(7, 3, 5)), MAX_LIGHT - 2);
+        assert_eq!(block(&world, IVec3::new(9, 3, 5)), 0);
+        world.set_voxel(inside, AIR);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(block(&world, IVec3::new(7, 3, 5)), 0);
+
+        // A block in the open shades the voxel below it from straight down sunlight
+        let below = IVec3::new(24, 19, 24);
+        world.set_voxel(below + IVec3::Y, STONE);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(sky(&world, below), MAX_LIGHT - 1);
+        world.set_voxel(below + IVec3::Y, AIR);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(sky(&world, below), MAX_LIGHT);
+    }
+
+    #[test]
+    fn light_crosses_chunk_borders() {
+        let blocks = BlockRegistry::builtin();
+        let mut world = VoxelWorld::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::default());
+        update(&mut world, &blocks, usize::MAX);
+        let lamp = IVec3::new(30, 16, 16);
+        world.set_voxel(lamp, LAMP);
+        update(&mut world, &blocks, usize::MAX);
+
+        // A chunk inserted next to the lamp gets its light
+        world.insert_chunk(IVec3::X, Chunk::default());
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(block(&world, IVec3::new(33, 16, 16)), MAX_LIGHT - 3);
+        assert_eq!(block(&world, IVec3::new(40, 16, 16)), MAX_LIGHT - 10);
+
+        // And loses it with the lamp
+        world.set_voxel(lamp, AIR);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(block(&world, IVec3::new(33, 16, 16)), 0);
+
+        // A roof in the chunk above shades the one below
+        let mut roof = Chunk::default();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                roof.set(x, 0, z, STONE);
+            }
+        }
+        world.insert_chunk(IVec3::new(0, 1, 0), roof);
+        update(&mut world, &blocks, usize::MAX);
+        assert_eq!(sky(&world, IVec3::new(16, 31, 16)), 0);
+        assert_eq!(sky(&world, IVec3::new(2, 31, 16)), MAX_LIGHT - 3);
+        assert_eq!(sky(&world, IVec3::new(40, 31, 16)), MAX_LIGHT);
+    }
+}
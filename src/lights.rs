@@ -0,0 +1,333 @@
+// lights.rs
+
+use crate::shadows::MAX_SHADOW_MAPS;
+use crate::vertex::Vertex;
+use bytemuck::{Pod, Zeroable};
+use glam::{Quat, Vec3};
+
+// Size of the light array in the shader's uniform; lights past it are left out
+pub const MAX_LIGHTS: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LightKind {
+    Directional, // Parallel rays, like the sun. Only the direction matters.
+    Point,       // Shines in every direction from its position
+    Spot,        // A cone around its direction, starting at its position
+}
+
+impl LightKind {
+    const ALL: [LightKind; 3] = [LightKind::Directional, LightKind::Point, LightKind::Spot];
+
+    fn name(self) -> &'static str {
+        match self {
+            LightKind::Directional => "Directional",
+            LightKind::Point => "Point",
+            LightKind::Spot => "Spot",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Light {
+    pub name: String,
+    pub kind: LightKind,
+    pub enabled: bool,
+    pub color: [f32; 3], // Linear
+    pub intensity: f32,
+    pub position: Vec3,
+    pub direction: Vec3, // The way the light travels
+    pub range: f32,      // Point and spot lights fade out to nothing at this distance
+    // Spot cone half angles in degrees, at full brightness inside the inner one
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub cast_shadows: bool, // Point lights never do
+}
+
+impl Light {
+    fn new(name: impl Into<String>, kind: LightKind) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            enabled: true,
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            position: Vec3::new(0.0, 3.0, 0.0),
+            direction: Vec3::NEG_Y,
+            range: 10.0,
+            inner_angle: 20.0,
+            outer_angle: 30.0,
+            cast_shadows: kind != LightKind::Point,
+        }
+    }
+
+    pub fn directional(name: impl Into<String>, direction: Vec3) -> Self {
+        Self {
+            direction: direction.normalize_or(Vec3::NEG_Y),
+            ..Self::new(name, LightKind::Directional)
+        }
+    }
+
+    pub fn point(name: impl Into<String>, position: Vec3, range: f32) -> Self {
+        Self {
+            position,
+            range,
+            ..Self::new(name, LightKind::Point)
+        }
+    }
+
+    pub fn spot(name: impl Into<String>, position: Vec3, direction: Vec3, outer_angle: f32) -> Self {
+        Self {
+            position,
+            direction: direction.normalize_or(Vec3::NEG_Y),
+            outer_angle,
+            inner_angle: outer_angle * 0.75,
+            ..Self::new(name, LightKind::Spot)
+        }
+    }
+
+    fn to_raw(&self) -> LightRaw {
+        let kind = match self.kind {
+            LightKind::Directional => 0.0,
+            LightKind::Point => 1.0,
+            LightKind::Spot => 2.0,
+        };
+        let outer = self.outer_angle.clamp(0.0, 89.0);
+        let inner = self.inner_angle.clamp(0.0, outer);
+        LightRaw {
+            position: self.position.extend(kind).to_array(),
+            direction: self.direction.normalize_or(Vec3::NEG_Y).extend(self.range.max(1e-3)).to_array(),
+            color: (Vec3::from(self.color) * self.intensity).extend(0.0).to_array(),
+            cone: [inner.to_radians().cos(), outer.to_radians().cos(), 0.0, 0.0],
+            shadow: [0.0; 4],
+        }
+    }
+
+    // Shadow map layers the light needs: one per cascade for directional lights, one for spot lights
+    pub fn shadow_map_count(&self, cascades: usize) -> usize {
+        match self.kind {
+            _ if !self.cast_shadows => 0,
+            LightKind::Directional => cascades,
+            LightKind::Point => 0,
+            LightKind::Spot => 1,
+        }
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("light_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.name);
+            ui.end_row();
+
+            ui.label("Type");
+            egui::ComboBox::from_id_source("light_kind")
+                .selected_text(self.kind.name())
+                .show_ui(ui, |ui| {
+                    for kind in LightKind::ALL {
+                        ui.selectable_value(&mut self.kind, kind, kind.name());
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Color");
+            ui.color_edit_button_rgb(&mut self.color);
+            ui.end_row();
+
+            ui.label("Intensity");
+            ui.add(egui::DragValue::new(&mut self.intensity).range(0.0..=100.0).speed(0.05));
+            ui.end_row();
+
+            if self.kind != LightKind::Directional {
+                ui.label("Position");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.position.x).speed(0.05).prefix("x: "));
+                    ui.add(egui::DragValue::new(&mut self.position.y).speed(0.05).prefix("y: "));
+                    ui.add(egui::DragValue::new(&mut self.position.z).speed(0.05).prefix("z: "));
+                });
+                ui.end_row();
+
+                ui.label("Range");
+                ui.add(egui::DragValue::new(&mut self.range).range(0.1..=1000.0).speed(0.1));
+                ui.end_row();
+            }
+
+            if self.kind != LightKind::Point {
+                // Edited as yaw and pitch, which can't produce a zero direction
+                let (mut yaw, mut pitch) = direction_angles(self.direction);
+                ui.label("Direction");
+                let changed = ui
+                    .horizontal(|ui| {
+                        let yaw_changed = ui.drag_angle(&mut yaw).changed();
+                        let pitch_changed = ui.drag_angle(&mut pitch).changed();
+                        yaw_changed || pitch_changed
+                    })
+                    .inner;
+                if changed {
+                    let pitch = pitch.clamp(-89f32.to_radians(), 89f32.to_radians());
+                    self.direction = Quat::from_rotation_y(yaw) * Quat::from_rotation_x(pitch) * Vec3::NEG_Z;
+                }
+                ui.end_row();
+            }
+
+            if self.kind == LightKind::Spot {
+                ui.label("Cone angles");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.inner_angle).range(0.0..=89.0).suffix("° inner"));
+                    ui.add(egui::DragValue::new(&mut self.outer_angle).range(0.0..=89.0).suffix("° outer"));
+                });
+                ui.end_row();
+            }
+
+            if self.kind != LightKind::Point {
+                ui.label("Shadows");
+                ui.checkbox(&mut self.cast_shadows, "Cast shadows");
+                ui.end_row();
+            }
+        });
+    }
+}
+
+// Yaw around y and pitch above the horizon of a direction, the inverse of how the UI builds it
+fn direction_angles(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize_or(Vec3::NEG_Z);
+    let yaw = (-direction.x).atan2(-direction.z);
+    let pitch = direction.y.clamp(-1.0, 1.0).asin();
+    (yaw, pitch)
+}
+
+// One light as the shader reads it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct LightRaw {
+    position: [f32; 4],  // w: 0 directional, 1 point, 2 spot
+    direction: [f32; 4], // w: range
+    color: [f32; 4],     // Premultiplied by the intensity
+    cone: [f32; 4],      // Cosines of the inner and outer spot angles
+    shadow: [f32; 4],    // First shadow map layer and layer count, no shadows with a count of 0
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct LightsUniform {
+    ambient: [f32; 4],
+    count: u32,
+    _padding: [u32; 3],
+    lights: [LightRaw; MAX_LIGHTS],
+}
+
+// The lights of a scene and the flat ambient term under them
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneLights {
+    pub ambient: [f32; 3],
+    pub lights: Vec<Light>,
+    selected: usize,
+}
+
+impl Default for SceneLights {
+    fn default() -> Self {
+        Self {
+            ambient: [0.08, 0.09, 0.12],
+            lights: Vec::new(),
+            selected: 0,
+        }
+    }
+}
+
+impl SceneLights {
+    // The lights the shaders see, in the order of the uniform
+    pub fn shaded(&self) -> impl Iterator<Item = &Light> {
+        self.lights.iter().filter(|l| l.enabled).take(MAX_LIGHTS)
+    }
+
+    // First shadow map layer of each shaded light. Layers are handed out in light order
+    // and lights that no longer fit go without shadows.
+    pub fn shadow_layers(&self, cascades: usize) -> Vec<Option<usize>> {
+        let mut next = 0;
+        self.shaded()
+            .map(|light| {
+                let count = light.shadow_map_count(cascades);
+                (count > 0 && next + count <= MAX_SHADOW_MAPS).then(|| {
+                    next += count;
+                    next - count
+                })
+            })
+            .collect()
+    }
+
+    // `cascades` is the number of maps per directional light, 0 when shadows are off
+    pub fn to_uniform(&self, cascades: usize) -> LightsUniform {
+        let mut uniform = LightsUniform::zeroed();
+        uniform.ambient = Vec3::from(self.ambient).extend(0.0).to_array();
+        let layers = self.shadow_layers(cascades);
+        for ((slot, light), layer) in uniform.lights.iter_mut().zip(self.shaded()).zip(layers) {
+            *slot = light.to_raw();
+            if let Some(layer) = layer {
+                slot.shadow = [layer as f32, light.shadow_map_count(cascades) as f32, 0.0, 0.0];
+            }
+            uniform.count += 1;
+        }
+        uniform
+    }
+
+    // Markers for the enabled point and spot lights: a small cross at each, plus a line
+    // along a spot light's direction
+    pub fn lines(&self) -> Vec<Vertex> {
+        let mut lines = Vec::new();
+        for light in self.lights.iter().filter(|l| l.enabled && l.kind != LightKind::Directional) {
+            let color = Vec3::from(light.color).min(Vec3::ONE).to_array();
+            for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+                lines.push(Vertex::new((light.position - axis * 0.15).to_array(), color));
+                lines.push(Vertex::new((light.position + axis * 0.15).to_array(), color));
+            }
+            if light.kind == LightKind::Spot {
+                let end = light.position + light.direction.normalize_or(Vec3::NEG_Y) * light.range.min(2.0);
+                lines.push(Vertex::new(light.position.to_array(), color));
+                lines.push(Vertex::new(end.to_array(), color));
+            }
+        }
+        lines
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Ambient");
+            ui.color_edit_button_rgb(&mut self.ambient);
+        });
+
+        ui.horizontal(|ui| {
+            for kind in LightKind::ALL {
+                if ui.button(format!("Add {}", kind.name().to_lowercase())).clicked() {
+                    self.lights.push(Light::new(format!("{} light", kind.name()), kind));
+                    self.selected = self.lights.len() - 1;
+                }
+            }
+        });
+        if self.lights.iter().filter(|l| l.enabled).count() > MAX_LIGHTS {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!("Only the first {} enabled lights are used", MAX_LIGHTS),
+            );
+        }
+
+        let mut removed = None;
+        for (i, light) in self.lights.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut light.enabled, "");
+                if ui.selectable_label(self.selected == i, &light.name).clicked() {
+                    self.selected = i;
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            self.lights.remove(i);
+        }
+
+        self.selected = self.selected.min(self.lights.len().saturating_sub(1));
+        if let Some(light) = self.lights.get_mut(self.selected) {
+            ui.separator();
+            light.settings_ui(ui);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+// lod.rs
+
+use crate::mesher::PaddedChunk;
+use crate::voxel::{chunk_coord, VoxelWorld};
+use glam::{IVec3, Vec3};
+use std::collections::HashMap;
+
+pub const MAX_LEVEL: u32 = 2;
+
+// Directions in which neighbouring chunks can have another level of detail. Levels
+// depend on horizontal distance only, so chunks stacked on each other always match.
+const SIDES: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+// Picks a level of detail per loaded chunk from its distance to the camera. Level n is
+// meshed at 1/2^n resolution.
+pub struct ChunkLods {
+    pub enabled: bool,
+    pub detail_distance: i32, // Chunks this far from the camera are meshed at full resolution
+    levels: HashMap<IVec3, u32>,
+}
+
+impl ChunkLods {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            detail_distance: 3,
+            levels: HashMap::new(),
+        }
+    }
+
+    fn level_for(&self, coord: IVec3, center: IVec3) -> u32 {
+        if !self.enabled {
+            return 0;
+        }
+        let distance = (coord.x - center.x).abs().max((coord.z - center.z).abs());
+        // Each level reaches twice as far as the one before it
+        let mut level = 0;
+        let mut reach = self.detail_distance.max(1);
+        while distance > reach && level < MAX_LEVEL {
+            level += 1;
+            reach *= 2;
+        }
+        level
+    }
+
+    // Assigns levels for the camera's current position and marks the chunks whose mesh
+    // no longer fits dirty: those that changed level, and their neighbours, which need
+    // walls on the side they share
+    pub fn update(&mut self, world: &mut VoxelWorld, camera_position: Vec3) {
+        let center = chunk_coord(camera_position.floor().as_ivec3());
+        let mut levels = HashMap::with_capacity(self.levels.len());
+        let mut changed = Vec::new();
+        for (coord, _) in world.chunks() {
+            let level = self.level_for(coord, center);
+            if self.levels.get(&coord).is_some_and(|previous| *previous != level) {
+                changed.push(coord);
+            }
+            levels.insert(coord, level);
+        }
+        self.levels = levels;
+
+        for coord in changed {
+            world.mark_dirty(coord);
+            for side in SIDES {
+                if self.levels.contains_key(&(coord + side)) {
+                    world.mark_dirty(coord + side);
+                }
+            }
+        }
+    }
+
+    pub fn level(&self, coord: IVec3) -> u32 {
+        self.levels.get(&coord).copied().unwrap_or(0)
+    }
+
+    // Meshing input for a chunk at its level, walled off towards neighbours at other levels
+    pub fn padded_chunk(&self, world: &VoxelWorld, coord: IVec3) -> PaddedChunk {
+        let level = self.level(coord);
+        let mut chunk = PaddedChunk::downsampled(world, coord, level);
+        for side in SIDES {
+            if self.levels.get(&(coord + side)).is_some_and(|neighbour| *neighbour != level) {
+                chunk.clear_border(side);
+            }
+        }
+        chunk
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Lower detail for distant chunks");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Full detail up to:");
+                ui.add(egui::DragValue::new(&mut self.detail_distance).range(1..=16).suffix(" chunks"));
+            });
+        });
+    }
+}
@@ -1,8 +1,5 @@
 use winit_egui_wgpu::run;
-mod camera;
-mod vertex;
 //mod ui;
-mod egui_tools;
 
 
 fn main() {
@@ -0,0 +1,83 @@
+// mesh.rs
+
+use crate::bounds::Aabb;
+use crate::ray::Ray;
+use crate::vertex::MeshVertex;
+use egui_wgpu::wgpu;
+use glam::Vec3;
+use std::ops::Range;
+use wgpu::util::DeviceExt;
+
+// Handle into the renderer's mesh list, shared by every scene node drawing the same geometry
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshId(pub usize);
+
+pub struct Mesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub num_indices: u32,
+    pub bounds: Aabb,
+    // CPU copies used for ray casting, empty for meshes that can't be picked
+    pub positions: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new<V: MeshVertex>(device: &wgpu::Device, label: &str, vertices: &[V], indices: &[u32]) -> Self {
+        Self {
+            positions: vertices.iter().map(|v| v.position()).collect(),
+            indices: indices.to_vec(),
+            ..Self::new_gpu_only(device, label, vertices, indices)
+        }
+    }
+
+    // Without the CPU copies, for geometry that is never ray cast such as voxel chunks
+    pub fn new_gpu_only<V: MeshVertex>(device: &wgpu::Device, label: &str, vertices: &[V], indices: &[u32]) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", label)),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", label)),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+            bounds: Aabb::from_points(vertices.iter().map(|v| v.position())),
+            positions: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.draw_instanced(render_pass, 0..1);
+    }
+
+    // Per-instance data has to be bound to vertex buffer slot 1 beforehand
+    pub fn draw_instanced<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.num_indices, 0, instances);
+    }
+
+    // Closest hit in the mesh's local space, as the distance along the ray
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        ray.intersect_aabb(&self.bounds)?;
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|tri| {
+                ray.intersect_triangle(
+                    self.positions[tri[0] as usize],
+                    self.positions[tri[1] as usize],
+                    self.positions[tri[2] as usize],
+                )
+            })
+            .min_by(f32::total_cmp)
+    }
+}
@@ -0,0 +1,80 @@
+// mesh_jobs.rs
+
+use crate::blocks::BlockRegistry;
+use crate::mesher::{self, ChunkMeshData, MeshingMode, PaddedChunk};
+use crate::worker_pool::WorkerPool;
+use glam::IVec3;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+struct MeshJob {
+    coord: IVec3,
+    generation: u64,
+    chunk: PaddedChunk,
+    mode: MeshingMode,
+    level: u32,
+}
+
+pub struct MeshResult {
+    pub coord: IVec3,
+    pub data: ChunkMeshData,
+    pub level: u32, // LOD level the chunk was meshed at
+    generation: u64,
+}
+
+// Meshes chunks on worker threads. Each submission gets a generation number; submitting a
+// chunk again drops its queued job, and results from older generations are discarded when
+// they arrive, so an edited chunk never flickers back to a stale mesh.
+pub struct MeshWorkers {
+    pool: WorkerPool<MeshJob, MeshResult>,
+    latest: HashMap<IVec3, u64>, // Newest generation submitted per chunk, until its result arrives
+    next_generation: u64,
+}
+
+impl MeshWorkers {
+    pub fn with_available_threads(blocks: Arc<BlockRegistry>) -> Self {
+        Self {
+            pool: WorkerPool::with_available_threads("chunk mesher", move |job: MeshJob| MeshResult {
+                coord: job.coord,
+                data: mesher::mesh_chunk(&job.chunk, job.mode, &blocks),
+                level: job.level,
+                generation: job.generation,
+            }),
+            latest: HashMap::new(),
+            next_generation: 0,
+        }
+    }
+
+    pub fn submit(&mut self, coord: IVec3, chunk: PaddedChunk, mode: MeshingMode, level: u32) {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.latest.insert(coord, generation);
+
+        self.pool.retain_queued(|job| job.coord != coord);
+        self.pool.submit(MeshJob {
+            coord,
+            generation,
+            chunk,
+            mode,
+            level,
+        });
+    }
+
+    // Up to `budget` finished meshes, leaving the rest queued for later frames
+    pub fn drain(&mut self, budget: usize) -> Vec<MeshResult> {
+        let mut finished = Vec::new();
+        while finished.len() < budget {
+            let Some(result) = self.pool.try_recv() else { break };
+            if self.latest.get(&result.coord) == Some(&result.generation) {
+                self.latest.remove(&result.coord);
+                finished.push(result);
+            }
+        }
+        finished
+    }
+
+    // Chunks submitted whose up-to-date mesh hasn't been drained yet
+    pub fn pending(&self) -> usize {
+        self.latest.len()
+    }
+}
@@ -0,0 +1,419 @@
+// mesher.rs
+
+use crate::blocks::{face_index, BlockRegistry, FaceMaterial};
+use crate::vertex::VoxelVertex;
+use crate::lighting::{block_light, sky_light, MAX_LIGHT};
+use crate::voxel::{BlockId, VoxelWorld, AIR, CHUNK_SIZE, UNLIT};
+use glam::IVec3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MeshingMode {
+    Culled, // One quad per visible voxel face
+    Greedy, // Visible faces with the same block and lighting merged into larger quads
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChunkMeshData {
+    pub vertices: Vec<VoxelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+// A chunk together with a one cell border taken from its neighbours, so faces and
+// ambient occlusion at chunk edges can be resolved without access to the world.
+// Owns its data so it can be handed to another thread.
+#[derive(Debug, Clone)]
+pub struct PaddedChunk {
+    blocks: Vec<BlockId>,
+    light: Vec<u8>,
+    size: usize, // Cells per side, without the border
+    scale: i32,  // Voxels per cell side
+}
+
+impl PaddedChunk {
+    pub fn from_world(world: &VoxelWorld, coord: IVec3) -> Self {
+        let origin = coord * CHUNK_SIZE as i32;
+        let padded = CHUNK_SIZE + 2;
+        let mut blocks = vec![AIR; padded * padded * padded];
+        let mut light = vec![UNLIT; padded * padded * padded];
+
+        if let Some(chunk) = world.chunk(coord) {
+            let chunk_light = world.chunk_light(coord);
+            for (i, block) in chunk.to_blocks().into_iter().enumerate() {
+                let (x, z, y) = (i % CHUNK_SIZE, (i / CHUNK_SIZE) % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE));
+                let index = Self::padded_index(CHUNK_SIZE, x as i32, y as i32, z as i32);
+                blocks[index] = block;
+                if let Some(chunk_light) = chunk_light {
+                    light[index] = chunk_light[i];
+                }
+            }
+        }
+
+        // Only the outer shell comes from the neighbouring chunks
+        for y in -1..=CHUNK_SIZE as i32 {
+            for z in -1..=CHUNK_SIZE as i32 {
+                for x in -1..=CHUNK_SIZE as i32 {
+                    let inside = |v: i32| (0..CHUNK_SIZE as i32).contains(&v);
+                    if inside(x) && inside(y) && inside(z) {
+                        continue;
+                    }
+                    let index = Self::padded_index(CHUNK_SIZE, x, y, z);
+                    blocks[index] = world.get_voxel(origin + IVec3::new(x, y, z));
+                    light[index] = world.get_light(origin + IVec3::new(x, y, z));
+                }
+            }
+        }
+
+        Self {
+            blocks,
+            light,
+            size: CHUNK_SIZE,
+            scale: 1,
+        }
+    }
+
+    // The chunk at 1/2^level resolution. A cell stays air unless at least half its voxels
+    // are filled, and otherwise takes the most common block of its highest filled layer, so
+    // thin surface layers like grass survive. Its light is the brightest of its voxels.
+    pub fn downsampled(world: &VoxelWorld, coord: IVec3, level: u32) -> Self {
+        if level == 0 {
+            return Self::from_world(world, coord);
+        }
+        let scale = 1 << level;
+        let size = CHUNK_SIZE >> level;
+        let padded = size + 2;
+        let origin = coord * CHUNK_SIZE as i32;
+        let mut blocks = vec![AIR; padded * padded * padded];
+        let mut light = vec![0; padded * padded * padded];
+        let mut counts: Vec<(BlockId, i32, usize)> = Vec::new(); // Block, highest layer, count
+        // Cells inside the chunk read from a copy of its voxels, border cells from the world
+        let own = world.chunk(coord).map(|chunk| chunk.to_blocks());
+        let own_light = world.chunk_light(coord);
+        let inside = |v: i32| (0..size as i32).contains(&v);
+        let own_index =
+            |local: IVec3| (local.y as usize * CHUNK_SIZE + local.z as usize) * CHUNK_SIZE + local.x as usize;
+
+        for y in -1..=size as i32 {
+            for z in -1..=size as i32 {
+                for x in -1..=size as i32 {
+                    counts.clear();
+                    let (mut sky, mut block_light) = (0, 0);
+                    let cell = IVec3::new(x, y, z);
+                    let interior = inside(x) && inside(y) && inside(z);
+                    for dy in 0..scale {
+                        for dz in 0..scale {
+                            for dx in 0..scale {
+                                let local = cell * scale + IVec3::new(dx, dy, dz);
+                                let block = match &own {
+                                    Some(own) if interior => own[own_index(local)],
+                                    None if interior => AIR,
+                                    _ => world.get_voxel(origin + local),
+                                };
+                                let voxel_light = match own_light {
+                                    Some(own_light) if interior => own_light[own_index(local)],
+                                    _ => world.get_light(origin + local),
+                                };
+                                sky = sky.max(voxel_light & 0xf0);
+                                block_light = block_light.max(voxel_light & 0x0f);
+                                if block == AIR {
+                                    continue;
+                                }
+                                match counts.iter_mut().find(|(b, ..)| *b == block) {
+                                    Some((_, top, count)) => {
+                                        *top = (*top).max(dy);
+                                        *count += 1;
+                                    }
+                                    None => counts.push((block, dy, 1)),
+                                }
+                            }
+                        }
+                    }
+                    light[Self::padded_index(size, x, y, z)] = sky | block_light;
+                    let filled: usize = counts.iter().map(|(.., count)| count).sum();
+                    if filled * 2 >= (scale * scale * scale) as usize {
+                        let (block, ..) = counts.iter().max_by_key(|(_, top, count)| (*top, *count)).unwrap();
+                        blocks[Self::padded_index(size, x, y, z)] = *block;
+                    }
+                }
+            }
+        }
+
+        Self {
+            blocks,
+            light,
+            size,
+            scale,
+        }
+    }
+
+    fn padded_index(size: usize, x: i32, y: i32, z: i32) -> usize {
+        let padded = size + 2;
+        ((y + 1) as usize * padded + (z + 1) as usize) * padded + (x + 1) as usize
+    }
+
+    // Cell position inside the chunk, valid from -1 to the cell count on each axis
+    pub fn get(&self, pos: IVec3) -> BlockId {
+        self.blocks[Self::padded_index(self.size, pos.x, pos.y, pos.z)]
+    }
+
+    pub fn get_light(&self, pos: IVec3) -> u8 {
+        self.light[Self::padded_index(self.size, pos.x, pos.y, pos.z)]
+    }
+
+    // Empties the border on one side, so the chunk gets a closed wall there. Covers the
+    // cracks against a neighbour meshed at another level of detail, whose surface doesn't
+    // line up with this one.
+    pub fn clear_border(&mut self, side: IVec3) {
+        let edge = self.size as i32;
+        for y in -1..=edge {
+            for z in -1..=edge {
+                for x in -1..=edge {
+                    let pos = IVec3::new(x, y, z);
+                    let on_side = (0..3).any(|a| {
+                        (side[a] < 0 && pos[a] == -1) || (side[a] > 0 && pos[a] == edge)
+                    });
+                    if on_side {
+                        let index = Self::padded_index(self.size, x, y, z);
+                        self.blocks[index] = AIR;
+                        // Lit like the voxels next to it, so the wall doesn't stand out
+                        self.light[index] = self.light[Self::padded_index(self.size, x - side.x, y - side.y, z - side.z)];
+                    }
+                }
+            }
+        }
+    }
+}
+
+// What a single face in a slice looks like. Faces only merge if all of this matches.
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct FaceKey {
+    block: BlockId,
+    ao: [u8; 4],
+    light: [[u8; 2]; 4], // Sky and block light per corner, in quarter levels
+}
+
+// Vertex ambient occlusion from the two side neighbours and the corner between them
+fn vertex_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as u8 + side2 as u8 + corner as u8)
+    }
+}
+
+pub fn mesh_chunk(chunk: &PaddedChunk, mode: MeshingMode, blocks: &BlockRegistry) -> ChunkMeshData {
+    let opaque = |pos: IVec3| blocks.is_opaque(chunk.get(pos));
+    let mut mesh = ChunkMeshData::default();
+    let size = chunk.size as i32;
+    let mut mask: Vec<Option<FaceKey>> = vec![None; chunk.size * chunk.size];
+
+    for axis in 0..3 {
+        // The face lies in the plane spanned by u and v; u x v points along +axis
+        let u_axis = (axis + 1) % 3;
+        let v_axis = (axis + 2) % 3;
+        let unit = |a: usize| IVec3::AXES[a];
+
+        for positive in [false, true] {
+            let normal = if positive { unit(axis) } else { -unit(axis) };
+
+            for slice in 0..size {
+                // Collect the visible faces of this slice
+                for v in 0..size {
+                    for u in 0..size {
+                        let pos = unit(axis) * slice + unit(u_axis) * u + unit(v_axis) * v;
+                        let block = chunk.get(pos);
+                        let front = pos + normal;
+                        // Faces between two see-through blocks of the same kind are left out too
+                        let neighbour = chunk.get(front);
+                        let visible = block != AIR && !opaque(front) && neighbour != block;
+                        mask[(v * size + u) as usize] = visible.then(|| {
+                            let mut ao = [0; 4];
+                            let mut light = [[0; 2]; 4];
+                            for (corner, (du, dv)) in [(-1, -1), (1, -1), (1, 1), (-1, 1)].into_iter().enumerate() {
+                                let side1 = front + unit(u_axis) * du;
+                                let side2 = front + unit(v_axis) * dv;
+                                let diagonal = side1 + unit(v_axis) * dv;
+                                let (opaque1, opaque2) = (opaque(side1), opaque(side2));
+                                ao[corner] = vertex_ao(opaque1, opaque2, opaque(diagonal));
+
+                                // Smooth lighting: the average over the see-through voxels around the
+                                // corner, leaving out the diagonal when both sides block it
+                                let open = [true, !opaque1, !opaque2, !(opaque(diagonal) || opaque1 && opaque2)];
+                                let (mut sum, mut count) = ([0; 2], 0);
+                                for (sample, _) in [front, side1, side2, diagonal].into_iter().zip(open).filter(|(_, o)| *o) {
+                                    let level = chunk.get_light(sample);
+                                    sum[0] += sky_light(level) as u32;
+                                    sum[1] += block_light(level) as u32;
+                                    count += 1;
+                                }
+                                light[corner] = sum.map(|s| ((s * 4 + count / 2) / count) as u8);
+                            }
+                            FaceKey { block, ao, light }
+                        });
+                    }
+                }
+
+                // Turn the mask into quads, growing each one as far as identical faces allow
+                for v in 0..size {
+                    let mut u = 0;
+                    while u < size {
+                        let Some(key) = mask[(v * size + u) as usize] else {
+                            u += 1;
+                            continue;
+                        };
+
+                        let (mut width, mut height) = (1, 1);
+                        if mode == MeshingMode::Greedy {
+                            while u + width < size && mask[(v * size + u + width) as usize] == Some(key) {
+                                width += 1;
+                            }
+                            'grow: while v + height < size {
+                                for du in 0..width {
+                                    if mask[((v + height) * size + u + du) as usize] != Some(key) {
+                                        break 'grow;
+                                    }
+                                }
+                                height += 1;
+                            }
+                        }
+
+                        for dv in 0..height {
+                            for du in 0..width {
+                                mask[((v + dv) * size + u + du) as usize] = None;
+                            }
+                        }
+
+                        let plane = if positive { slice + 1 } else { slice };
+                        let origin = unit(axis) * plane + unit(u_axis) * u + unit(v_axis) * v;
+                        let corners = [
+                            origin,
+                            origin + unit(u_axis) * width,
+                            origin + unit(u_axis) * width + unit(v_axis) * height,
+                            origin + unit(v_axis) * height,
+                        ]
+                        .map(|corner| corner * chunk.scale);
+                        let material = blocks.face(key.block, face_index(axis, positive));
+                        push_quad(&mut mesh, corners, axis, normal, key, material, positive);
+
+                        u += width;
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+fn push_quad(
+    mesh: &mut ChunkMeshData,
+    corners: [IVec3; 4],
+    axis: usize,
+    normal: IVec3,
+    key: FaceKey,
+    material: FaceMaterial,
+    positive: bool,
+) {
+    let base = mesh.vertices.len() as u32;
+    for ((corner, ao), light) in corners.iter().zip(key.ao).zip(key.light) {
+        // Texture v runs downwards on the sides so they're the right way up
+        let uv = match axis {
+            0 => [corner.z, -corner.y],
+            1 => [corner.x, corner.z],
+            _ => [corner.x, -corner.y],
+        };
+        mesh.vertices.push(VoxelVertex {
+            position: corner.as_vec3().to_array(),
+            normal: normal.as_vec3().to_array(),
+            color: material.color,
+            ao: ao as f32 / 3.0,
+            uv: uv.map(|c| c as f32),
+            layer: material.layer,
+            emissive: material.emissive as u32,
+            light: light.map(|level| level as f32 / (MAX_LIGHT as f32 * 4.0)),
+        });
+    }
+
+    // Split along the diagonal that keeps the occlusion gradient symmetric
+    let ao = key.ao;
+    let quad: [u32; 6] = if ao[0] as u32 + ao[2] as u32 >= ao[1] as u32 + ao[3] as u32 {
+        [0, 1, 2, 0, 2, 3]
+    } else {
+        [1, 2, 3, 1, 3, 0]
+    };
+
+    // Corners go counter-clockwise seen from +axis, so faces pointing the other way flip
+    if positive {
+        mesh.indices.extend(quad.iter().map(|i| base + i));
+    } else {
+        mesh.indices.extend(quad.iter().rev().map(|i| base + i));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::Chunk;
+
+    const STONE: BlockId = 1;
+    const DIRT: BlockId = 2;
+    const SAND: BlockId = 4;
+
+    // Stone in the bottom half of chunk 0, a dirt chunk on -x and a sand chunk on +y, each
+    // with its own light
+    fn world_with_neighbours() -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+        let mut chunk = Chunk::default();
+        for y in 0..CHUNK_SIZE / 2 {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    chunk.set(x, y, z, STONE);
+                }
+            }
+        }
+        world.insert_chunk(IVec3::ZERO, chunk);
+        world.insert_chunk(IVec3::NEG_X, Chunk::filled(DIRT));
+        world.insert_chunk(IVec3::Y, Chunk::filled(SAND));
+        world.fill_light(IVec3::ZERO, 0x35);
+        world.fill_light(IVec3::NEG_X, 0x02);
+        world.fill_light(IVec3::Y, 0xf0);
+        world
+    }
+
+    #[test]
+    fn downsampling_reads_the_chunk_and_its_neighbours() {
+        let world = world_with_neighbours();
+        for level in 1..=3 {
+            let chunk = PaddedChunk::downsampled(&world, IVec3::ZERO, level);
+            let size = (CHUNK_SIZE >> level) as i32;
+            let (half, top) = (size / 2, size - 1);
+
+            assert_eq!(chunk.get(IVec3::new(0, 0, 0)), STONE);
+            assert_eq!(chunk.get(IVec3::new(top, half - 1, top)), STONE);
+            assert_eq!(chunk.get(IVec3::new(0, half, 0)), AIR);
+            assert_eq!(chunk.get_light(IVec3::new(half, half, half)), 0x35);
+
+            assert_eq!(chunk.get(IVec3::new(-1, half, 0)), DIRT);
+            assert_eq!(chunk.get_light(IVec3::new(-1, half, 0)), 0x02);
+            assert_eq!(chunk.get(IVec3::new(0, size, 0)), SAND);
+            assert_eq!(chunk.get_light(IVec3::new(0, size, 0)), 0xf0);
+            assert_eq!(chunk.get(IVec3::new(size, 0, 0)), AIR);
+            assert_eq!(chunk.get_light(IVec3::new(size, 0, 0)), UNLIT);
+        }
+    }
+
+    #[test]
+    fn level_zero_matches_the_full_chunk() {
+        let world = world_with_neighbours();
+        let full = PaddedChunk::from_world(&world, IVec3::ZERO);
+        let downsampled = PaddedChunk::downsampled(&world, IVec3::ZERO, 0);
+        assert_eq!(full.blocks, downsampled.blocks);
+        assert_eq!(full.light, downsampled.light);
+        assert_eq!(full.get(IVec3::new(-1, 0, 0)), DIRT);
+    }
+}
@@ -0,0 +1,38 @@
+// Selection outline: a flat colored silhouette, masked by the stencil buffer
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+struct ObjectUniform {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    normal: mat4x4<f32>,
+    material: vec4<f32>, // Specular strength, shininess
+    pbr: vec4<f32>,      // Metallic, roughness, normal scale, occlusion strength
+    emissive: vec4<f32>, // Emitted color, alpha cutoff
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * object.model * vec4<f32>(model.position, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return object.color;
+}
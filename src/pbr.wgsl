@@ -0,0 +1,323 @@
+// Physically based shading for metallic-roughness materials, following glTF's material model
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+struct ObjectUniform {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    normal: mat4x4<f32>,
+    material: vec4<f32>, // Specular strength, shininess
+    pbr: vec4<f32>,      // Metallic, roughness, normal scale, occlusion strength
+    emissive: vec4<f32>, // Emitted color, alpha cutoff
+};
+
+const MAX_LIGHTS: u32 = 16u;
+const MAX_SHADOW_MAPS: u32 = 8u;
+const DIRECTIONAL_LIGHT: f32 = 0.0;
+const SPOT_LIGHT: f32 = 2.0;
+const PI: f32 = 3.14159265;
+
+struct Light {
+    position: vec4<f32>,  // w: 0 directional, 1 point, 2 spot
+    direction: vec4<f32>, // w: range
+    color: vec4<f32>,
+    cone: vec4<f32>,      // Cosines of the inner and outer spot angles
+    shadow: vec4<f32>,    // First shadow map layer and layer count, no shadows with a count of 0
+};
+
+struct Lights {
+    ambient: vec4<f32>,
+    count: u32,
+    lights: array<Light, MAX_LIGHTS>,
+};
+
+struct Shadows {
+    matrices: array<mat4x4<f32>, MAX_SHADOW_MAPS>,
+    texels: array<vec4<f32>, 2>, // World size of a texel per map; per unit of distance for spot lights
+    splits: vec4<f32>,           // Distance from the camera where each cascade ends
+    view: vec4<f32>,             // Camera forward, cascade count
+    params: vec4<f32>,           // Depth bias, normal bias in texels, filter radius, tint cascades
+};
+
+struct Environment {
+    irradiance: array<vec4<f32>, 9>, // Spherical harmonics, convolved and divided by π
+    params: vec4<f32>,               // Enabled, intensity, index of the roughest mip level
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(0) @binding(1)
+var<uniform> lights: Lights;
+
+@group(0) @binding(2)
+var<uniform> shadows: Shadows;
+
+@group(0) @binding(3)
+var shadow_maps: texture_depth_2d_array;
+
+@group(0) @binding(4)
+var shadow_sampler: sampler_comparison;
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniform;
+
+@group(2) @binding(0)
+var base_color_texture: texture_2d<f32>;
+@group(2) @binding(1)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var normal_texture: texture_2d<f32>;
+@group(2) @binding(3)
+var occlusion_texture: texture_2d<f32>;
+@group(2) @binding(4)
+var emissive_texture: texture_2d<f32>;
+@group(2) @binding(5)
+var material_sampler: sampler;
+
+@group(3) @binding(0)
+var environment_map: texture_cube<f32>;
+@group(3) @binding(1)
+var environment_sampler: sampler;
+@group(3) @binding(2)
+var<uniform> environment: Environment;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    let world_position = object.model * vec4<f32>(model.position, 1.0);
+    var out: VertexOutput;
+    out.color = model.color * object.color.rgb;
+    out.world_position = world_position.xyz;
+    out.normal = (object.normal * vec4<f32>(model.normal, 0.0)).xyz;
+    out.uv = model.uv;
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+// Fragment shader
+
+// Cascade of the sun's shadow a point falls in, the cascade count past the last one
+fn cascade_index(position: vec3<f32>) -> u32 {
+    let depth = dot(position - camera.position.xyz, shadows.view.xyz);
+    let count = u32(shadows.view.w);
+    for (var i = 0u; i < count; i++) {
+        if depth < shadows.splits[i] {
+            return i;
+        }
+    }
+    return count;
+}
+
+// How much of a light reaches a point, from 0 in full shadow to 1. The point is pushed out
+// along the surface normal by a few shadow map texels before the lookup, and the comparisons
+// around it are averaged for soft edges.
+fn shadow(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if light.shadow.y < 1.0 {
+        return 1.0;
+    }
+    var layer = u32(light.shadow.x);
+    var texel: f32;
+    if light.position.w == DIRECTIONAL_LIGHT {
+        let cascade = cascade_index(position);
+        if cascade >= u32(light.shadow.y) {
+            return 1.0;
+        }
+        layer += cascade;
+        texel = shadows.texels[layer / 4u][layer % 4u];
+    } else {
+        texel = shadows.texels[layer / 4u][layer % 4u] * distance(light.position.xyz, position);
+    }
+
+    let clip = shadows.matrices[layer] * vec4<f32>(position + normal * texel * shadows.params.y, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if clip.w <= 0.0 || any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let radius = i32(shadows.params.z);
+    let size = vec2<f32>(textureDimensions(shadow_maps));
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) / size;
+            lit += textureSampleCompareLevel(shadow_maps, shadow_sampler, uv + offset, layer, ndc.z - shadows.params.x);
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// Debug colors for the shadow cascades
+fn cascade_tint(position: vec3<f32>) -> vec3<f32> {
+    var tints = array<vec3<f32>, 5>(
+        vec3<f32>(1.0, 0.5, 0.5),
+        vec3<f32>(0.5, 1.0, 0.5),
+        vec3<f32>(0.5, 0.6, 1.0),
+        vec3<f32>(1.0, 1.0, 0.4),
+        vec3<f32>(1.0, 1.0, 1.0),
+    );
+    return tints[min(cascade_index(position), 4u)];
+}
+
+// Tilts the surface normal by a tangent space normal, with the tangent frame worked out
+// from how the position and uvs change across the pixel. Meshes without uvs keep their normal.
+fn apply_normal_map(normal: vec3<f32>, position: vec3<f32>, uv: vec2<f32>, mapped: vec3<f32>) -> vec3<f32> {
+    let uv_dx = dpdx(uv);
+    let uv_dy = dpdy(uv);
+    let determinant = uv_dx.x * uv_dy.y - uv_dy.x * uv_dx.y;
+    let t = (uv_dy.y * dpdx(position) - uv_dx.y * dpdy(position)) / determinant;
+    let tangent = t - normal * dot(normal, t);
+    if abs(determinant) < 1e-12 || dot(tangent, tangent) < 1e-12 {
+        return normal;
+    }
+    let tangent_frame = mat3x3<f32>(normalize(tangent), cross(normal, normalize(tangent)), normal);
+    return normalize(tangent_frame * mapped);
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * d * d);
+}
+
+// Height-correlated Smith masking, with the 1 / (4 n·l n·v) of the BRDF folded in
+fn visibility_smith(n_dot_l: f32, n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let view = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+    let light = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+    return 0.5 / max(view + light, 1e-5);
+}
+
+fn fresnel_schlick(f0: vec3<f32>, v_dot_h: f32) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+// Analytic fit of the split sum's scale and bias for the environment's specular light
+fn environment_brdf(f0: vec3<f32>, roughness: f32, n_dot_v: f32) -> vec3<f32> {
+    let r = roughness * vec4<f32>(-1.0, -0.0275, -0.572, 0.022) + vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    let ab = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return f0 * ab.x + ab.y;
+}
+
+fn irradiance(n: vec3<f32>) -> vec3<f32> {
+    let c = environment.irradiance;
+    return c[0].rgb * 0.282095
+        + c[1].rgb * 0.488603 * n.y
+        + c[2].rgb * 0.488603 * n.z
+        + c[3].rgb * 0.488603 * n.x
+        + c[4].rgb * 1.092548 * n.x * n.y
+        + c[5].rgb * 1.092548 * n.y * n.z
+        + c[6].rgb * 0.315392 * (3.0 * n.z * n.z - 1.0)
+        + c[7].rgb * 1.092548 * n.x * n.z
+        + c[8].rgb * 0.546274 * (n.x * n.x - n.y * n.y);
+}
+
+// Cook-Torrance reflection of one light. Light colors are scaled by π so that a white
+// matte surface facing a light looks the same as with the Blinn-Phong shader.
+fn shade(light: Light, position: vec3<f32>, normal: vec3<f32>, view: vec3<f32>, albedo: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    var to_light = -light.direction.xyz;
+    var attenuation = 1.0;
+    if light.position.w != DIRECTIONAL_LIGHT {
+        let offset = light.position.xyz - position;
+        let distance = length(offset);
+        to_light = offset / max(distance, 1e-4);
+        let range = light.direction.w;
+        let fade = clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+        attenuation = fade * fade / (1.0 + distance * distance);
+        if light.position.w == SPOT_LIGHT {
+            let cos_angle = dot(-to_light, normalize(light.direction.xyz));
+            attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+        }
+    }
+
+    let n_dot_l = max(dot(normal, to_light), 0.0);
+    if n_dot_l <= 0.0 || attenuation <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let halfway = normalize(to_light + view);
+    let n_dot_v = max(dot(normal, view), 1e-4);
+    let n_dot_h = max(dot(normal, halfway), 0.0);
+    let alpha = roughness * roughness;
+
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let fresnel = fresnel_schlick(f0, max(dot(view, halfway), 0.0));
+    let specular = fresnel * distribution_ggx(n_dot_h, alpha) * visibility_smith(n_dot_l, n_dot_v, alpha);
+    let diffuse = (vec3<f32>(1.0) - fresnel) * (1.0 - metallic) * albedo / PI;
+    return (diffuse + specular) * PI * light.color.rgb * attenuation * n_dot_l;
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // Everything is sampled before the cut-out discard, while control flow is still uniform
+    let base_texel = textureSample(base_color_texture, material_sampler, in.uv);
+    let metallic_roughness = textureSample(metallic_roughness_texture, material_sampler, in.uv);
+    let normal_texel = textureSample(normal_texture, material_sampler, in.uv);
+    let occlusion_texel = textureSample(occlusion_texture, material_sampler, in.uv);
+    let emissive_texel = textureSample(emissive_texture, material_sampler, in.uv);
+
+    // Back faces only get drawn for double-sided materials, and face the other way
+    var geometric_normal = normalize(in.normal);
+    if !front_facing {
+        geometric_normal = -geometric_normal;
+    }
+    let mapped = normalize((normal_texel.xyz * 2.0 - 1.0) * vec3<f32>(object.pbr.z, object.pbr.z, 1.0));
+    let normal = apply_normal_map(geometric_normal, in.world_position, in.uv, mapped);
+
+    let alpha = base_texel.a * object.color.a;
+    if alpha < object.emissive.w {
+        discard;
+    }
+
+    let albedo = in.color * base_texel.rgb;
+    let metallic = clamp(object.pbr.x * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(object.pbr.y * metallic_roughness.g, 0.045, 1.0);
+    let occlusion = mix(1.0, occlusion_texel.r, object.pbr.w);
+    let view = normalize(camera.position.xyz - in.world_position);
+
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < min(lights.count, MAX_LIGHTS); i++) {
+        let light = lights.lights[i];
+        color += shade(light, in.world_position, normal, view, albedo, metallic, roughness)
+            * shadow(light, in.world_position, geometric_normal);
+    }
+
+    if environment.params.x > 0.5 {
+        let n_dot_v = max(dot(normal, view), 1e-4);
+        let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+        let reflected = reflect(-view, normal);
+        let prefiltered = textureSampleLevel(environment_map, environment_sampler, reflected, roughness * environment.params.z).rgb;
+        let specular = prefiltered * environment_brdf(f0, roughness, n_dot_v);
+        let diffuse = irradiance(normal) * albedo * (1.0 - metallic);
+        color += (diffuse + specular) * environment.params.y * occlusion;
+    } else {
+        color += lights.ambient.rgb * albedo * occlusion;
+    }
+
+    color += object.emissive.rgb * emissive_texel.rgb;
+    if shadows.params.w > 0.5 {
+        color *= cascade_tint(in.world_position);
+    }
+    return vec4<f32>(color, 1.0);
+}
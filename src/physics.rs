@@ -0,0 +1,349 @@
+// physics.rs
+
+use crate::blocks::BlockRegistry;
+use crate::bounds::Aabb;
+use crate::camera::Camera;
+use crate::voxel::VoxelWorld;
+use glam::{Vec2, Vec3};
+
+const HALF_WIDTH: f32 = 0.3;
+const HEIGHT: f32 = 1.8;
+const EYE_HEIGHT: f32 = 1.62;
+const TERMINAL_SPEED: f32 = 60.0;
+// Gap kept between the player and the blocks they touch, so the box never ends up
+// exactly on a voxel boundary and rounding can't push it inside
+const SKIN: f32 = 1e-3;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MovementMode {
+    Fly,  // Free camera movement, straight through blocks
+    Walk, // Collides with blocks and falls
+}
+
+// Movement wanted this frame, in world space
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MoveInput {
+    pub direction: Vec2, // Horizontal (x, z), at most unit length
+    pub jump: bool,
+}
+
+// Furthest the box can move along one axis, up to `distance`, before touching a solid voxel
+pub fn sweep(world: &VoxelWorld, blocks: &BlockRegistry, aabb: &Aabb, axis: usize, distance: f32) -> f32 {
+    if distance == 0.0 {
+        return 0.0;
+    }
+    let (a1, a2) = ((axis + 1) % 3, (axis + 2) % 3);
+    let cells = |min: f32, max: f32| (min + SKIN).floor() as i32..=(max - SKIN).floor() as i32;
+
+    // Layers of voxels in front of the moving face, nearest first
+    let (face, step) = if distance > 0.0 { (aabb.max[axis], 1) } else { (aabb.min[axis], -1) };
+    let first = if step > 0 { (face - SKIN).floor() as i32 + 1 } else { (face + SKIN).floor() as i32 - 1 };
+    let last = (face + distance).floor() as i32;
+
+    let mut layer = first;
+    while (layer - last) * step <= 0 {
+        for u in cells(aabb.min[a1], aabb.max[a1]) {
+            for v in cells(aabb.min[a2], aabb.max[a2]) {
+                let mut pos = [0; 3];
+                pos[axis] = layer;
+                pos[a1] = u;
+                pos[a2] = v;
+                if blocks.is_solid(world.get_voxel(pos.into())) {
+                    // Stop just short of the voxel's near side
+                    let contact = if step > 0 { layer as f32 - face - SKIN } else { layer as f32 + 1.0 - face + SKIN };
+                    return if step > 0 { contact.clamp(0.0, distance) } else { contact.clamp(distance, 0.0) };
+                }
+            }
+        }
+        layer += step;
+    }
+    distance
+}
+
+fn overlaps_blocks(world: &VoxelWorld, blocks: &BlockRegistry, aabb: &Aabb) -> bool {
+    let min = (aabb.min + SKIN).floor().as_ivec3();
+    let max = (aabb.max - SKIN).floor().as_ivec3();
+    (min.y..=max.y).any(|y| {
+        (min.z..=max.z)
+            .any(|z| (min.x..=max.x).any(|x| blocks.is_solid(world.get_voxel(glam::IVec3::new(x, y, z)))))
+    })
+}
+
+// Moves along y, x and z in turn, stopping at blocks on each. Returns the new box and
+// which axes were blocked.
+fn slide(world: &VoxelWorld, blocks: &BlockRegistry, mut aabb: Aabb, delta: Vec3) -> (Aabb, [bool; 3]) {
+    let mut blocked = [false; 3];
+    for axis in [1, 0, 2] {
+        let moved = sweep(world, blocks, &aabb, axis, delta[axis]);
+        blocked[axis] = moved != delta[axis];
+        let mut offset = Vec3::ZERO;
+        offset[axis] = moved;
+        aabb = aabb.translated(offset);
+    }
+    (aabb, blocked)
+}
+
+// The walking body the camera rides on. `position` is the center of the player's feet.
+pub struct Player {
+    pub mode: MovementMode,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub on_ground: bool,
+    pub walk_speed: f32,
+    pub jump_speed: f32,
+    pub gravity: f32,
+    pub step_height: f32, // Ledges up to this high are walked up without jumping
+}
+
+impl Player {
+    pub fn new() -> Self {
+        Self {
+            mode: MovementMode::Fly,
+            position: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+            on_ground: false,
+            walk_speed: 5.0,
+            jump_speed: 8.5,
+            gravity: 28.0,
+            step_height: 1.0,
+        }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::new(
+            self.position - Vec3::new(HALF_WIDTH, 0.0, HALF_WIDTH),
+            self.position + Vec3::new(HALF_WIDTH, HEIGHT, HALF_WIDTH),
+        )
+    }
+
+    pub fn eye(&self) -> Vec3 {
+        self.position + Vec3::Y * EYE_HEIGHT
+    }
+
+    // Puts the player under the camera, lifted out of any blocks it's inside of
+    pub fn start_walking(&mut self, world: &VoxelWorld, blocks: &BlockRegistry, camera: &Camera) {
+        self.mode = MovementMode::Walk;
+        self.position = camera.position - Vec3::Y * EYE_HEIGHT;
+        self.velocity = Vec3::ZERO;
+        self.on_ground = false;
+        for _ in 0..256 {
+            if !overlaps_blocks(world, blocks, &self.aabb()) {
+                break;
+            }
+            self.position.y = self.position.y.floor() + 1.0 + SKIN;
+        }
+    }
+
+    pub fn update(&mut self, world: &VoxelWorld, blocks: &BlockRegistry, input: &MoveInput, dt: f32) {
+        if self.mode != MovementMode::Walk {
+            return;
+        }
+        let wish = input.direction.clamp_length_max(1.0) * self.walk_speed;
+        self.velocity.x = wish.x;
+        self.velocity.z = wish.y;
+        if input.jump && self.on_ground {
+            self.velocity.y = self.jump_speed;
+        }
+        self.velocity.y = (self.velocity.y - self.gravity * dt).max(-TERMINAL_SPEED);
+
+        // Horizontal movement, stepping up onto a ledge if that gets further
+        let start = self.aabb();
+        let horizontal = Vec3::new(self.velocity.x, 0.0, self.velocity.z) * dt;
+        let (mut aabb, blocked) = slide(world, blocks, start, horizontal);
+        if self.on_ground && self.step_height > 0.0 && (blocked[0] || blocked[2]) {
+            let up = sweep(world, blocks, &start, 1, self.step_height);
+            let (raised, _) = slide(world, blocks, start.translated(Vec3::Y * up), horizontal);
+            let down = sweep(world, blocks, &raised, 1, -up);
+            let stepped = raised.translated(Vec3::Y * down);
+            let progress = |b: &Aabb| (b.min - start.min).with_y(0.0).length_squared();
+            if progress(&stepped) > progress(&aabb) + SKIN {
+                aabb = stepped;
+            }
+        }
+
+        let fall = self.velocity.y * dt;
+        let moved = sweep(world, blocks, &aabb, 1, fall);
+        aabb = aabb.translated(Vec3::Y * moved);
+        self.on_ground = fall < 0.0 && moved > fall;
+        if moved != fall {
+            self.velocity.y = 0.0;
+        }
+
+        self.position = Vec3::new(aabb.center().x, aabb.min.y, aabb.center().z);
+    }
+
+    // Moves the camera to the eyes, keeping its view direction
+    pub fn attach_camera(&self, camera: &mut Camera) {
+        let direction = camera.target - camera.position;
+        camera.position = self.eye();
+        camera.target = camera.position + direction;
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui, world: &VoxelWorld, blocks: &BlockRegistry, camera: &Camera) {
+        ui.horizontal(|ui| {
+            ui.label("Mode (F):");
+            if ui.selectable_label(self.mode == MovementMode::Fly, "Fly").clicked() {
+                self.mode = MovementMode::Fly;
+            }
+            if ui.selectable_label(self.mode == MovementMode::Walk, "Walk").clicked() && self.mode != MovementMode::Walk {
+                self.start_walking(world, blocks, camera);
+            }
+        });
+        egui::Grid::new("player_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Walk speed");
+            ui.add(egui::DragValue::new(&mut self.walk_speed).range(0.5..=50.0).speed(0.1));
+            ui.end_row();
+            ui.label("Jump speed");
+            ui.add(egui::DragValue::new(&mut self.jump_speed).range(0.0..=50.0).speed(0.1));
+            ui.end_row();
+            ui.label("Gravity");
+            ui.add(egui::DragValue::new(&mut self.gravity).range(0.0..=100.0).speed(0.1));
+            ui.end_row();
+            ui.label("Step height");
+            ui.add(egui::DragValue::new(&mut self.step_height).range(0.0..=2.0).speed(0.05));
+            ui.end_row();
+        });
+        if self.mode == MovementMode::Walk {
+            ui.label(if self.on_ground { "On the ground" } else { "In the air" });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::IVec3;
+
+    const STONE: u16 = 1;
+    const DT: f32 = 1.0 / 60.0;
+
+    // A stone floor whose top is at y = 1
+    fn floor() -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+        for x in -8..8 {
+            for z in -8..8 {
+                world.set_voxel(IVec3::new(x, 0, z), STONE);
+            }
+        }
+        world
+    }
+
+    fn fill(world: &mut VoxelWorld, min: IVec3, max: IVec3, block: u16) {
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    world.set_voxel(IVec3::new(x, y, z), block);
+                }
+            }
+        }
+    }
+
+    fn walker(position: Vec3) -> Player {
+        let mut player = Player::new();
+        player.mode = MovementMode::Walk;
+        player.position = position;
+        player
+    }
+
+    fn run(player: &mut Player, world: &VoxelWorld, blocks: &BlockRegistry, direction: Vec2, seconds: f32) {
+        let input = MoveInput { direction, jump: false };
+        for _ in 0..(seconds / DT) as usize {
+            player.update(world, blocks, &input, DT);
+        }
+    }
+
+    #[test]
+    fn lands_on_the_floor() {
+        let (world, blocks) = (floor(), BlockRegistry::builtin());
+        let mut player = walker(Vec3::new(0.5, 4.0, 0.5));
+        run(&mut player, &world, &blocks, Vec2::ZERO, 0.1);
+        assert!(!player.on_ground);
+        run(&mut player, &world, &blocks, Vec2::ZERO, 2.0);
+        assert!(player.on_ground);
+        assert!((player.position.y - 1.0).abs() < 0.01, "{}", player.position.y);
+        assert_eq!(player.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn walls_stop_movement_on_x_and_z() {
+        let blocks = BlockRegistry::builtin();
+        let mut world = floor();
+        fill(&mut world, IVec3::new(3, 1, -8), IVec3::new(3, 3, 7), STONE);
+        fill(&mut world, IVec3::new(-8, 1, -3), IVec3::new(7, 3, -3), STONE);
+
+        let mut player = walker(Vec3::new(0.5, 1.0 + SKIN, 0.5));
+        run(&mut player, &world, &blocks, Vec2::X, 2.0);
+        assert!((player.position.x - (3.0 - HALF_WIDTH)).abs() < 0.01, "{}", player.position.x);
+
+        let mut player = walker(Vec3::new(0.5, 1.0 + SKIN, 0.5));
+        run(&mut player, &world, &blocks, -Vec2::Y, 2.0);
+        assert!((player.position.z - (-2.0 + HALF_WIDTH)).abs() < 0.01, "{}", player.position.z);
+        assert!(player.on_ground);
+    }
+
+    #[test]
+    fn steps_up_one_block_but_not_two() {
+        let blocks = BlockRegistry::builtin();
+        let mut world = floor();
+        fill(&mut world, IVec3::new(2, 1, -8), IVec3::new(7, 1, -1), STONE);
+        fill(&mut world, IVec3::new(2, 1, 0), IVec3::new(7, 2, 7), STONE);
+
+        let mut player = walker(Vec3::new(0.5, 1.0 + SKIN, -4.5));
+        run(&mut player, &world, &blocks, Vec2::ZERO, 0.1);
+        run(&mut player, &world, &blocks, Vec2::X, 0.5);
+        assert!(player.position.x > 2.5, "{}", player.position.x);
+        assert!((player.position.y - 2.0).abs() < 0.01, "{}", player.position.y);
+
+        let mut player = walker(Vec3::new(0.5, 1.0 + SKIN, 4.5));
+        run(&mut player, &world, &blocks, Vec2::ZERO, 0.1);
+        run(&mut player, &world, &blocks, Vec2::X, 1.0);
+        assert!((player.position.x - (2.0 - HALF_WIDTH)).abs() < 0.01, "{}", player.position.x);
+        assert!((player.position.y - 1.0).abs() < 0.01, "{}", player.position.y);
+    }
+
+    #[test]
+    fn fast_falls_dont_tunnel() {
+        let (world, blocks) = (floor(), BlockRegistry::builtin());
+
+        // Far further than the floor is thick, from high up and from resting on it
+        let high = walker(Vec3::new(0.5, 20.0, 0.5)).aabb();
+        let moved = sweep(&world, &blocks, &high, 1, -100.0);
+        assert!((high.min.y + moved - (1.0 + SKIN)).abs() < 1e-4, "{}", high.min.y + moved);
+        let resting = walker(Vec3::new(0.5, 1.0 + SKIN, 0.5)).aabb();
+        assert!(sweep(&world, &blocks, &resting, 1, -100.0).abs() < 1e-4);
+
+        // Terminal speed over long frames
+        let mut player = walker(Vec3::new(0.5, 30.0, 0.5));
+        player.velocity.y = -TERMINAL_SPEED;
+        for _ in 0..20 {
+            player.update(&world, &blocks, &MoveInput::default(), 0.1);
+        }
+        assert!(player.on_ground);
+        assert!((player.position.y - 1.0).abs() < 0.01, "{}", player.position.y);
+    }
+
+    #[test]
+    fn non_solid_blocks_are_passed_through() {
+        const FOLIAGE: u16 = 2;
+        let blocks = BlockRegistry::parse(
+            r#"(
+                textures: [],
+                blocks: [
+                    (id: 1, name: "Stone"),
+                    (id: 2, name: "Foliage", opaque: false, solid: false),
+                ],
+            )"#,
+            None,
+        )
+        .unwrap();
+        let mut world = floor();
+        fill(&mut world, IVec3::new(-8, 3, -8), IVec3::new(7, 3, 7), FOLIAGE);
+        fill(&mut world, IVec3::new(3, 1, -8), IVec3::new(3, 2, 7), FOLIAGE);
+
+        let mut player = walker(Vec3::new(0.5, 6.0, 0.5));
+        run(&mut player, &world, &blocks, Vec2::ZERO, 2.0);
+        assert!((player.position.y - 1.0).abs() < 0.01, "{}", player.position.y);
+        run(&mut player, &world, &blocks, Vec2::X, 1.0);
+        assert!(player.position.x > 4.0, "{}", player.position.x);
+    }
+}
@@ -0,0 +1,28 @@
+// picking.rs
+
+use crate::mesh::Mesh;
+use crate::ray::Ray;
+use crate::scene::{NodeId, Scene};
+
+// Closest scene node hit by a world space ray, with the world space distance to the hit
+pub fn pick(scene: &Scene, meshes: &[Mesh], ray: &Ray) -> Option<(NodeId, f32)> {
+    scene
+        .world_matrices()
+        .into_iter()
+        .filter_map(|(id, world)| {
+            let mesh = &meshes[scene.node(id)?.mesh?.0];
+
+            // Cast in the mesh's local space instead of transforming every triangle
+            let inverse = world.inverse();
+            let local_origin = inverse.transform_point3(ray.origin);
+            let local_ray = Ray::new(local_origin, inverse.transform_vector3(ray.direction));
+            if !local_ray.direction.is_finite() {
+                return None; // Degenerate transform, e.g. a zero scale
+            }
+
+            let t = mesh.intersect_ray(&local_ray)?;
+            let hit = world.transform_point3(local_ray.at(t));
+            Some((id, hit.distance(ray.origin)))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
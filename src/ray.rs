@@ -0,0 +1,81 @@
+// ray.rs
+
+use crate::bounds::Aabb;
+use glam::Vec3;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3, // Always normalized
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    // Parameters of the closest approach between the ray and an infinite line,
+    // returned as (distance along the ray, distance along the line). None if they are parallel.
+    pub fn closest_to_line(&self, point: Vec3, direction: Vec3) -> Option<(f32, f32)> {
+        let w = self.origin - point;
+        let b = self.direction.dot(direction);
+        let c = direction.dot(direction);
+        let d = self.direction.dot(w);
+        let e = direction.dot(w);
+        let denom = c - b * b;
+        if denom.abs() < 1e-6 {
+            return None;
+        }
+        Some(((b * e - c * d) / denom, (e - b * d) / denom))
+    }
+
+    pub fn intersect_plane(&self, point: Vec3, normal: Vec3) -> Option<f32> {
+        let denom = normal.dot(self.direction);
+        if denom.abs() < 1e-6 {
+            return None;
+        }
+        let t = (point - self.origin).dot(normal) / denom;
+        (t >= 0.0).then_some(t)
+    }
+
+    // Slab test. Returns the distance to the entry point, or 0 if the ray starts inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let inverse = self.direction.recip();
+        let t1 = (aabb.min - self.origin) * inverse;
+        let t2 = (aabb.max - self.origin) * inverse;
+        let t_enter = t1.min(t2).max_element();
+        let t_exit = t1.max(t2).min_element();
+        (t_exit >= t_enter.max(0.0)).then_some(t_enter.max(0.0))
+    }
+
+    // Möller–Trumbore, hitting both sides of the triangle
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let det = edge1.dot(p);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = self.origin - a;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(q) * inv_det;
+        (t >= 0.0).then_some(t)
+    }
+}
@@ -0,0 +1,358 @@
+// region.rs
+
+use crate::voxel::{BlockId, Chunk, CHUNK_SIZE, CHUNK_VOLUME};
+use glam::IVec3;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+
+// Region files hold a 16x16 area of chunks on one chunk layer:
+//   magic "VXRG", format version (u16), chunk size (u16)
+//   offset table: per chunk slot an absolute offset and byte length (u32 each), zero if absent
+//   chunk payloads: an encoding byte followed by the encoded voxels
+// All numbers are little endian.
+pub const REGION_SIZE: i32 = 16;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const MAGIC: &[u8; 4] = b"VXRG";
+const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 8 + REGION_CHUNKS * 8;
+
+// Chunk payload encodings
+const ENCODING_RAW: u8 = 0; // One u16 per voxel
+const ENCODING_RLE: u8 = 1; // (run length u16, block u16) pairs
+
+// Raw is the largest a payload gets, since RLE is only used when it comes out smaller
+const MAX_PAYLOAD: usize = 1 + CHUNK_VOLUME * 2;
+
+// Region containing a chunk, and the chunk's slot in that region's table
+pub fn region_coord(chunk: IVec3) -> (IVec3, usize) {
+    let region = IVec3::new(chunk.x.div_euclid(REGION_SIZE), chunk.y, chunk.z.div_euclid(REGION_SIZE));
+    let slot = chunk.z.rem_euclid(REGION_SIZE) * REGION_SIZE + chunk.x.rem_euclid(REGION_SIZE);
+    (region, slot as usize)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Where a payload from the offset table lies in a file of `file_len` bytes, None if the slot
+// is empty. Corrupt tables are errors rather than huge allocations or reads out of bounds.
+fn payload_range(offset: u32, length: u32, file_len: u64) -> io::Result<Option<Range<usize>>> {
+    if offset == 0 {
+        return Ok(None);
+    }
+    let (start, length) = (offset as usize, length as usize);
+    if start < HEADER_SIZE {
+        return Err(invalid_data("chunk offset points into the region header"));
+    }
+    if length == 0 || length > MAX_PAYLOAD {
+        return Err(invalid_data(&format!("chunk payload has an impossible length of {} bytes", length)));
+    }
+    if (start + length) as u64 > file_len {
+        return Err(invalid_data("chunk extends past the end of the region file"));
+    }
+    Ok(Some(start..start + length))
+}
+
+// Chunk storage in a directory of region files. Chunks are read one at a time, seeking
+// straight to them through the offset table; saving rewrites the region it lands in.
+#[derive(Debug, Clone)]
+pub struct RegionStore {
+    dir: PathBuf,
+}
+
+impl RegionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn region_path(&self, region: IVec3) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}.vxr", region.x, region.y, region.z))
+    }
+
+    // Offset table of a region file, after checking its header
+    fn read_table(file: &mut File) -> io::Result<Vec<(u32, u32)>> {
+        let mut header = vec![0; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(invalid_data("not a region file"));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != VERSION {
+            return Err(invalid_data(&format!("unsupported region file version {}", version)));
+        }
+        if u16::from_le_bytes([header[6], header[7]]) as usize != CHUNK_SIZE {
+            return Err(invalid_data("region file was saved with a different chunk size"));
+        }
+
+        Ok(header[8..]
+            .chunks_exact(8)
+            .map(|entry| {
+                let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+                let length = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+                (offset, length)
+            })
+            .collect())
+    }
+
+    // Ok(None) if the chunk was never saved
+    pub fn load_chunk(&self, coord: IVec3) -> io::Result<Option<Chunk>> {
+        let (region, slot) = region_coord(coord);
+        let mut file = match File::open(self.region_path(region)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let (offset, length) = Self::read_table(&mut file)?[slot];
+        let Some(range) = payload_range(offset, length, file.metadata()?.len())? else { return Ok(None) };
+        let mut payload = vec![0; range.len()];
+        file.seek(SeekFrom::Start(range.start as u64))?;
+        file.read_exact(&mut payload)?;
+        decode_chunk(&payload).map(Some)
+    }
+
+    // Every stored payload of a region, indexed by slot
+    fn read_region(&self, region: IVec3) -> io::Result<Vec<Option<Vec<u8>>>> {
+        let mut file = match File::open(self.region_path(region)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![None; REGION_CHUNKS]),
+            Err(e) => return Err(e),
+        };
+
+        let table = Self::read_table(&mut file)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let file_len = (HEADER_SIZE + data.len()) as u64;
+        table
+            .into_iter()
+            .map(|(offset, length)| {
+                let range = payload_range(offset, length, file_len)?;
+                Ok(range.map(|range| data[range.start - HEADER_SIZE..range.end - HEADER_SIZE].to_vec()))
+            })
+            .collect()
+    }
+
+    // Written to a temporary file first so a crash mid-write can't corrupt the region
+    fn write_region(&self, region: IVec3, payloads: &[Option<Vec<u8>>]) -> io::Result<()> {
+        let mut table = Vec::with_capacity(REGION_CHUNKS * 8);
+        let mut body = Vec::new();
+        for payload in payloads {
+            let (offset, length) = match payload {
+                Some(payload) => {
+                    let offset = (HEADER_SIZE + body.len()) as u32;
+                    body.extend_from_slice(payload);
+                    (offset, payload.len() as u32)
+                }
+                None => (0, 0),
+            };
+            table.extend_from_slice(&offset.to_le_bytes());
+            table.extend_from_slice(&length.to_le_bytes());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let path = self.region_path(region);
+        let temp_path = path.with_extension("vxr.tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&(CHUNK_SIZE as u16).to_le_bytes())?;
+        file.write_all(&table)?;
+        file.write_all(&body)?;
+        file.sync_all()?;
+        fs::rename(temp_path, path)
+    }
+
+    pub fn save_chunks<'a>(&self, chunks: impl IntoIterator<Item = (IVec3, &'a Chunk)>) -> io::Result<()> {
+        let mut by_region: HashMap<IVec3, Vec<(usize, &Chunk)>> = HashMap::new();
+        for (coord, chunk) in chunks {
+            let (region, slot) = region_coord(coord);
+            by_region.entry(region).or_default().push((slot, chunk));
+        }
+
+        for (region, chunks) in by_region {
+            let mut payloads = self.read_region(region)?;
+            for (slot, chunk) in chunks {
+                payloads[slot] = Some(encode_chunk(chunk));
+            }
+            self.write_region(region, &payloads)?;
+        }
+        Ok(())
+    }
+}
+
+// Whichever encoding comes out smaller
+fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let blocks = chunk.to_blocks();
+
+    let mut rle = vec![ENCODING_RLE];
+    let mut i = 0;
+    while i < blocks.len() {
+        let block = blocks[i];
+        let run = blocks[i..].iter().take(u16::MAX as usize).take_while(|b| **b == block).count();
+        rle.extend_from_slice(&(run as u16).to_le_bytes());
+        rle.extend_from_slice(&block.to_le_bytes());
+        i += run;
+    }
+
+    if rle.len() < 1 + CHUNK_VOLUME * 2 {
+        return rle;
+    }
+    let mut raw = vec![ENCODING_RAW];
+    for block in blocks {
+        raw.extend_from_slice(&block.to_le_bytes());
+    }
+    raw
+}
+
+fn decode_chunk(payload: &[u8]) -> io::Result<Chunk> {
+    let (&encoding, data) = payload.split_first().ok_or_else(|| invalid_data("empty chunk payload"))?;
+    let words = data.chunks_exact(2).map(|w| u16::from_le_bytes([w[0], w[1]]));
+
+    let blocks: Vec<BlockId> = match encoding {
+        ENCODING_RAW => words.collect(),
+        ENCODING_RLE => {
+            let pairs: Vec<u16> = words.collect();
+            let mut blocks = Vec::with_capacity(CHUNK_VOLUME);
+            for pair in pairs.chunks_exact(2) {
+                if blocks.len() + pair[0] as usize > CHUNK_VOLUME {
+                    return Err(invalid_data("chunk payload has too many voxels"));
+                }
+                blocks.extend(std::iter::repeat_n(pair[1], pair[0] as usize));
+            }
+            blocks
+        }
+        _ => return Err(invalid_data(&format!("unknown chunk encoding {}", encoding))),
+    };
+    if blocks.len() != CHUNK_VOLUME {
+        return Err(invalid_data("chunk payload has the wrong number of voxels"));
+    }
+    Ok(Chunk::from_blocks(&blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> (RegionStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("voxxele-region-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        (RegionStore::new(&dir), dir)
+    }
+
+    // Layers of a few blocks, which RLE compresses well
+    fn layered_chunk() -> Chunk {
+        let blocks: Vec<BlockId> = (0..CHUNK_VOLUME).map(|i| (i / (CHUNK_SIZE * CHUNK_SIZE)) as BlockId % 4).collect();
+        Chunk::from_blocks(&blocks)
+    }
+
+    // A different block almost every voxel, so RLE would come out larger than raw
+    fn noisy_chunk(seed: u32) -> Chunk {
+        let blocks: Vec<BlockId> = (0..CHUNK_VOLUME as u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761).wrapping_add(seed) >> 24) as BlockId)
+            .collect();
+        Chunk::from_blocks(&blocks)
+    }
+
+    #[test]
+    fn encodings_round_trip() {
+        let layered = layered_chunk();
+        let payload = encode_chunk(&layered);
+        assert_eq!(payload[0], ENCODING_RLE);
+        assert_eq!(decode_chunk(&payload).unwrap(), layered);
+
+        let noisy = noisy_chunk(1);
+        let payload = encode_chunk(&noisy);
+        assert_eq!(payload[0], ENCODING_RAW);
+        assert_eq!(payload.len(), MAX_PAYLOAD);
+        assert_eq!(decode_chunk(&payload).unwrap().to_blocks(), noisy.to_blocks());
+    }
+
+    #[test]
+    fn bad_payloads_are_errors() {
+        assert!(decode_chunk(&[]).is_err());
+        assert!(decode_chunk(&[7, 0, 0]).is_err());
+        // Runs adding up to more voxels than a chunk has
+        let mut rle = vec![ENCODING_RLE];
+        for _ in 0..2 {
+            rle.extend_from_slice(&u16::MAX.to_le_bytes());
+            rle.extend_from_slice(&1u16.to_le_bytes());
+        }
+        assert!(decode_chunk(&rle).is_err());
+    }
+
+    #[test]
+    fn chunks_in_one_region_round_trip() {
+        let (store, dir) = temp_store("round-trip");
+        let chunks = [
+            (IVec3::new(0, 0, 0), layered_chunk()),
+            (IVec3::new(15, 0, 15), noisy_chunk(2)),
+            (IVec3::new(3, 0, 9), Chunk::filled(5)),
+        ];
+        store.save_chunks(chunks.iter().map(|(coord, chunk)| (*coord, chunk))).unwrap();
+        // A second save into the same region keeps what's already there
+        let extra = noisy_chunk(3);
+        store.save_chunks([(IVec3::new(7, 0, 1), &extra)]).unwrap();
+
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        for (coord, chunk) in &chunks {
+            assert_eq!(store.load_chunk(*coord).unwrap().unwrap().to_blocks(), chunk.to_blocks());
+        }
+        assert_eq!(store.load_chunk(IVec3::new(7, 0, 1)).unwrap().unwrap().to_blocks(), extra.to_blocks());
+        assert!(store.load_chunk(IVec3::new(1, 0, 0)).unwrap().is_none());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn missing_region_is_none() {
+        let (store, _) = temp_store("missing");
+        assert!(store.load_chunk(IVec3::new(-40, 2, 100)).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_header_is_an_error() {
+        let (store, dir) = temp_store("truncated");
+        store.save_chunks([(IVec3::ZERO, &layered_chunk())]).unwrap();
+        let path = store.region_path(IVec3::ZERO);
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..HEADER_SIZE / 2]).unwrap();
+        assert!(store.load_chunk(IVec3::ZERO).is_err());
+        assert!(store.save_chunks([(IVec3::X, &layered_chunk())]).is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn corrupt_offsets_are_invalid_data() {
+        let (store, dir) = temp_store("corrupt");
+        store.save_chunks([(IVec3::ZERO, &layered_chunk())]).unwrap();
+        let path = store.region_path(IVec3::ZERO);
+        let original = fs::read(&path).unwrap();
+
+        // Slot 0's entry is right after the magic, version and chunk size
+        let header = HEADER_SIZE as u32;
+        for (offset, length) in [(4, 10), (header, u32::MAX), (header, 100_000), (u32::MAX, 8)] {
+            let mut data = original.clone();
+            data[8..12].copy_from_slice(&offset.to_le_bytes());
+            data[12..16].copy_from_slice(&length.to_le_bytes());
+            fs::write(&path, &data).unwrap();
+            let error = store.load_chunk(IVec3::ZERO).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "offset {} length {}", offset, length);
+            assert_eq!(store.read_region(IVec3::ZERO).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let (store, dir) = temp_store("version");
+        store.save_chunks([(IVec3::ZERO, &layered_chunk())]).unwrap();
+        let path = store.region_path(IVec3::ZERO);
+        let mut data = fs::read(&path).unwrap();
+        data[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        fs::write(&path, &data).unwrap();
+        assert_eq!(store.load_chunk(IVec3::ZERO).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,1503 @@
+// renderer.rs
+
+use crate::bounds::{Aabb, Frustum};
+use crate::blocks::TEXTURE_SIZE;
+use crate::camera::Camera;
+use crate::environment::{Environment, Sky, ENVIRONMENT_MIPS, ENVIRONMENT_SIZE};
+use crate::instance::{Instance, InstanceBatchId, InstanceBuffer, InstanceRaw};
+use crate::lights::LightsUniform;
+use crate::mesh::{Mesh, MeshId};
+use crate::scene::{MaterialTextures, NodeId, Scene, ShadingModel};
+use crate::shadows::{shadow_views, ShadowMaps, ShadowSettings, ShadowUniform};
+use crate::texture::{MipmapGenerator, SamplerSettings, Texture, TextureId};
+use crate::vertex::{Vertex, VoxelVertex};
+use crate::voxel::CHUNK_SIZE;
+use bytemuck::{Pod, Zeroable};
+use egui_wgpu::wgpu;
+use glam::{IVec3, Mat4, Vec3};
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+
+// The stencil part is used to mask the selection outline
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+const OUTLINE_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+const FRUSTUM_COLOR: [f32; 3] = [1.0, 0.9, 0.2];
+// Chunk tint per level of detail when they're shown
+const LOD_COLORS: [[f32; 4]; 3] = [[0.5, 1.0, 0.5, 1.0], [1.0, 1.0, 0.4, 1.0], [1.0, 0.45, 0.45, 1.0]];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    position: [f32; 4],
+}
+
+// Per-object data, bound with a dynamic offset so that every node gets its own slot in one buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct ObjectUniform {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+    normal: [[f32; 4]; 4], // Inverse transpose of the model matrix, keeps normals upright under uneven scaling
+    material: [f32; 4],    // Specular strength and shininess
+    pbr: [f32; 4],         // Metallic, roughness, normal scale and occlusion strength
+    emissive: [f32; 4],    // Emitted color and alpha cutoff
+}
+
+impl ObjectUniform {
+    fn new(model: Mat4, color: [f32; 4]) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            color,
+            normal: model.inverse().transpose().to_cols_array_2d(),
+            material: [0.0; 4],
+            pbr: [0.0; 4],
+            emissive: [0.0; 4],
+        }
+    }
+}
+
+// What frustum culling let through in the last frame
+#[derive(Debug, Copy, Clone, Default)]
+struct CullingStats {
+    visible_objects: usize,
+    total_objects: usize,
+    visible_chunks: usize,
+    total_chunks: usize,
+}
+
+// Culling state kept by each view the scene is drawn from
+#[derive(Debug, Clone, Default)]
+pub struct ViewCulling {
+    frozen_frustum: Option<Frustum>, // Culls in place of the camera's while frozen
+    stats: CullingStats,
+}
+
+pub struct SceneRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    challenge_render_pipeline: wgpu::RenderPipeline,
+    instanced_pipeline: wgpu::RenderPipeline,
+    challenge_instanced_pipeline: wgpu::RenderPipeline,
+    instance_batches: Vec<(MeshId, InstanceBuffer)>,
+    pbr_pipeline: wgpu::RenderPipeline,
+    pbr_double_sided_pipeline: wgpu::RenderPipeline,
+    textures: Vec<Texture>,
+    mipmaps: MipmapGenerator,
+    white_texture: Texture,
+    flat_normal_texture: Texture,
+    material_layout: wgpu::BindGroupLayout,
+    material_sampler: wgpu::Sampler,
+    pub material_sampling: SamplerSettings,
+    built_sampling: SamplerSettings, // What the material sampler was created from
+    material_bind_groups: HashMap<MaterialTextures, wgpu::BindGroup>,
+    pub environment: Environment,
+    baked_sky: Option<Sky>, // What the environment bind group was baked from
+    environment_irradiance: [Vec3; 9],
+    environment_layout: wgpu::BindGroupLayout,
+    environment_sampler: wgpu::Sampler,
+    environment_buffer: wgpu::Buffer,
+    environment_bind_group: wgpu::BindGroup,
+    voxel_pipeline: wgpu::RenderPipeline,
+    voxel_chunks: HashMap<IVec3, (Mesh, u32)>, // Mesh and level of detail per chunk
+    pub show_lod_levels: bool,
+    block_texture_layout: wgpu::BindGroupLayout,
+    block_sampler: wgpu::Sampler,
+    block_texture_bind_group: wgpu::BindGroup,
+    line_pipeline: wgpu::RenderPipeline,
+    outline_mask_pipeline: wgpu::RenderPipeline,
+    outline_pipeline: wgpu::RenderPipeline,
+    outlined: Option<NodeId>,
+    line_buffer: wgpu::Buffer,
+    line_capacity: usize, // Vertices the line buffer holds
+    line_count: u32,
+    camera_buffer: wgpu::Buffer,
+    lights_buffer: wgpu::Buffer,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    camera_bind_group: wgpu::BindGroup,
+    pub shadows: ShadowSettings,
+    shadow_maps: ShadowMaps,
+    object_bind_group_layout: wgpu::BindGroupLayout,
+    object_buffer: wgpu::Buffer,
+    object_bind_group: wgpu::BindGroup,
+    object_stride: wgpu::BufferAddress,
+    object_capacity: usize,
+    meshes: Vec<Mesh>,
+    pub frustum_culling: bool,
+    freeze_frustum: bool,
+    draw_frustum: bool,
+}
+
+impl SceneRenderer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: &wgpu::SurfaceConfiguration) -> Self {
+        // Load shaders
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Main Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        let challenge_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Challenge Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("challenge_shader.wgsl").into()),
+        });
+
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::bytes_of(&CameraUniform::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // The scene's lights change at most once per frame, like the camera, so they share its group
+        let lights_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lights Buffer"),
+            contents: bytemuck::bytes_of(&LightsUniform::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Camera Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // Shadow matrices, the shadow map array and its comparison sampler
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                        count: None,
+                    },
+                ],
+            });
+
+        let object_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Object Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<ObjectUniform>() as u64,
+                        ),
+                    },
+                    count: None,
+                }],
+            });
+
+        // Dynamic offsets have to respect the device's uniform offset alignment
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let object_size = std::mem::size_of::<ObjectUniform>() as wgpu::BufferAddress;
+        let object_stride = object_size.div_ceil(alignment) * alignment;
+        let object_capacity = 16;
+        let line_capacity = 256;
+        let (object_buffer, object_bind_group) = Self::create_object_buffer(
+            device,
+            &object_bind_group_layout,
+            object_stride,
+            object_capacity,
+        );
+
+        let shadows = ShadowSettings::new();
+        let shadow_maps = ShadowMaps::new(device, &object_bind_group_layout, config.format, shadows.resolution);
+        let camera_bind_group = Self::create_camera_bind_group(
+            device,
+            &camera_bind_group_layout,
+            &camera_buffer,
+            &lights_buffer,
+            &shadow_maps,
+        );
+
+        // Pipeline compilation options
+        let mut constants = HashMap::new();
+        constants.insert("MY_CONSTANT".to_string(), 1.0); // Example constant value, replace as needed
+
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: &constants, // Pipeline-overridable constants
+            zero_initialize_workgroup_memory: true, // Set based on your requirements
+        };
+
+        // Create render pipeline layout
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout, &object_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = Self::create_pipeline(
+            device,
+            "Render Pipeline",
+            &render_pipeline_layout,
+            &shader,
+            config.format,
+            false,
+            compilation_options.clone(),
+        );
+
+        let challenge_render_pipeline = Self::create_pipeline(
+            device,
+            "Challenge Render Pipeline",
+            &render_pipeline_layout,
+            &challenge_shader,
+            config.format,
+            false,
+            compilation_options.clone(),
+        );
+
+        // Instanced draws don't use the object uniform
+        let instanced_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Instanced Pipeline Layout"),
+                bind_group_layouts: &[&camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let instanced_pipeline = Self::create_pipeline(
+            device,
+            "Instanced Render Pipeline",
+            &instanced_pipeline_layout,
+            &shader,
+            config.format,
+            true,
+            compilation_options.clone(),
+        );
+
+        let challenge_instanced_pipeline = Self::create_pipeline(
+            device,
+            "Challenge Instanced Render Pipeline",
+            &instanced_pipeline_layout,
+            &challenge_shader,
+            config.format,
+            true,
+            compilation_options,
+        );
+
+        let pbr_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("PBR Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("pbr.wgsl").into()),
+        });
+
+        // Material textures, in the order of the slots in MaterialTextures, and their sampler
+        let material_layout = crate::texture::bind_group_layout(device, "Material Bind Group Layout", 5);
+        let material_sampling = SamplerSettings::new();
+        let material_sampler = material_sampling.create(device, "Material Sampler");
+        let mipmaps = MipmapGenerator::new(device);
+
+        // Stand-ins for empty slots that leave the material's factors unchanged
+        let white_texture = Texture::solid(device, queue, "White", [255; 4]);
+        let flat_normal_texture = Texture::solid(device, queue, "Flat Normal", [128, 128, 255, 255]);
+
+        let environment_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let environment_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let environment = Environment::new();
+        let environment_irradiance = environment.sky.irradiance();
+        let environment_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Buffer"),
+            contents: bytemuck::bytes_of(&environment.to_uniform(&environment_irradiance)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let environment_bind_group = Self::create_environment(
+            device,
+            queue,
+            &environment_layout,
+            &environment_sampler,
+            &environment_buffer,
+            &environment.sky,
+        );
+
+        let pbr_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("PBR Pipeline Layout"),
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                &object_bind_group_layout,
+                &material_layout,
+                &environment_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pbr_pipeline = Self::create_pbr_pipeline(device, &pbr_pipeline_layout, &pbr_shader, config.format, false);
+        let pbr_double_sided_pipeline =
+            Self::create_pbr_pipeline(device, &pbr_pipeline_layout, &pbr_shader, config.format, true);
+
+        let voxel_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Voxel Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("voxel.wgsl").into()),
+        });
+
+        // Block textures are one texture array, each face picks its layer
+        let block_texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Block Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        // Crisp texels up close, mipmapped in the distance
+        let block_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Block Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // A single white layer until real textures are set
+        let block_texture_bind_group = Self::create_block_textures(
+            device,
+            queue,
+            &block_texture_layout,
+            &block_sampler,
+            &[vec![255; TEXTURE_SIZE * TEXTURE_SIZE * 4]],
+        );
+
+        let voxel_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Voxel Pipeline Layout"),
+            bind_group_layouts: &[&camera_bind_group_layout, &object_bind_group_layout, &block_texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        let voxel_pipeline = Self::create_voxel_pipeline(
+            device,
+            &voxel_pipeline_layout,
+            &voxel_shader,
+            config.format,
+        );
+
+        let line_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Line Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("line.wgsl").into()),
+        });
+
+        let line_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Line Pipeline Layout"),
+            bind_group_layouts: &[&camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let line_pipeline = Self::create_line_pipeline(device, &line_pipeline_layout, &line_shader, config.format);
+
+        let outline_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("outline.wgsl").into()),
+        });
+
+        // First pass marks the selected object's pixels in the stencil buffer,
+        // second pass draws an enlarged copy everywhere except those pixels
+        let outline_mask_pipeline = Self::create_outline_pipeline(
+            device,
+            "Outline Mask Pipeline",
+            &render_pipeline_layout,
+            &outline_shader,
+            config.format,
+            wgpu::ColorWrites::empty(),
+            wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Always,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Replace,
+            },
+        );
+
+        let outline_pipeline = Self::create_outline_pipeline(
+            device,
+            "Outline Pipeline",
+            &render_pipeline_layout,
+            &outline_shader,
+            config.format,
+            wgpu::ColorWrites::ALL,
+            wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::NotEqual,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Keep,
+            },
+        );
+
+        Self {
+            render_pipeline,
+            challenge_render_pipeline,
+            instanced_pipeline,
+            challenge_instanced_pipeline,
+            instance_batches: Vec::new(),
+            pbr_pipeline,
+            pbr_double_sided_pipeline,
+            textures: Vec::new(),
+            mipmaps,
+            white_texture,
+            flat_normal_texture,
+            material_layout,
+            material_sampler,
+            material_sampling,
+            built_sampling: material_sampling,
+            material_bind_groups: HashMap::new(),
+            baked_sky: Some(environment.sky.clone()),
+            environment,
+            environment_irradiance,
+            environment_layout,
+            environment_sampler,
+            environment_buffer,
+            environment_bind_group,
+            voxel_pipeline,
+            voxel_chunks: HashMap::new(),
+            show_lod_levels: false,
+            block_texture_layout,
+            block_sampler,
+            block_texture_bind_group,
+            line_pipeline,
+            outline_mask_pipeline,
+            outline_pipeline,
+            outlined: None,
+            line_buffer: Self::create_line_buffer(device, line_capacity),
+            line_capacity,
+            line_count: 0,
+            camera_buffer,
+            lights_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+            shadows,
+            shadow_maps,
+            object_bind_group_layout,
+            object_buffer,
+            object_bind_group,
+            object_stride,
+            object_capacity,
+            meshes: Vec::new(),
+            frustum_culling: true,
+            freeze_frustum: false,
+            draw_frustum: true,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        instanced: bool,
+        compilation_options: wgpu::PipelineCompilationOptions,
+    ) -> wgpu::RenderPipeline {
+        // Instanced pipelines read the model matrix from a second, per-instance vertex buffer
+        let vertex_buffers = [Vertex::desc(), InstanceRaw::desc()];
+        let (entry_point, buffers) = if instanced {
+            ("vs_instanced", &vertex_buffers[..])
+        } else {
+            ("vs_main", &vertex_buffers[..1])
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point,
+                buffers, // Use the Vertex description
+                compilation_options: compilation_options.clone(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main", // Entry point in your fragment shader
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent::REPLACE,
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options,
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    // Double-sided materials keep their back faces
+    fn create_pbr_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        double_sided: bool,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(if double_sided { "Double-Sided PBR Pipeline" } else { "PBR Pipeline" }),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: (!double_sided).then_some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn create_voxel_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Voxel Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[VoxelVertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    // Overlay lines ignore the depth buffer so gizmos stay visible through geometry
+    fn create_line_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn create_outline_pipeline(
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        write_mask: wgpu::ColorWrites,
+        stencil_face: wgpu::StencilFaceState,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask,
+                })],
+                compilation_options: Default::default(),
+            }),
+            // Both sides, so flat meshes get outlined too
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: stencil_face,
+                    back: stencil_face,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn create_camera_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        lights_buffer: &wgpu::Buffer,
+        shadow_maps: &ShadowMaps,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: shadow_maps.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&shadow_maps.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&shadow_maps.sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_line_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Line Vertex Buffer"),
+            size: (std::mem::size_of::<Vertex>() * capacity) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_object_buffer(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        stride: wgpu::BufferAddress,
+        capacity: usize,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Object Buffer"),
+            size: stride * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Object Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<ObjectUniform>() as u64),
+                }),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+
+    // Depth and stencil buffer for a render target of the given size
+    pub fn create_depth_view(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    // Uploads square RGBA8 sRGB layers of TEXTURE_SIZE pixels, with a box filtered mip chain
+    fn create_block_textures(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        layers: &[Vec<u8>],
+    ) -> wgpu::BindGroup {
+        let mip_level_count = TEXTURE_SIZE.ilog2() + 1;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Block Textures"),
+            size: wgpu::Extent3d {
+                width: TEXTURE_SIZE as u32,
+                height: TEXTURE_SIZE as u32,
+                depth_or_array_layers: layers.len() as u32,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (layer, pixels) in layers.iter().enumerate() {
+            let mut level = pixels.clone();
+            let mut size = TEXTURE_SIZE;
+            for mip in 0..mip_level_count {
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level: mip,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &level,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(size as u32 * 4),
+                        rows_per_image: Some(size as u32),
+                    },
+                    wgpu::Extent3d {
+                        width: size as u32,
+                        height: size as u32,
+                        depth_or_array_layers: 1,
+                    },
+                );
+                if size > 1 {
+                    level = crate::texture::downsample(&level, size, size, true);
+                    size /= 2;
+                }
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Block Texture Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    // Bakes the sky into a cube map prefiltered for every roughness
+    fn create_environment(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        buffer: &wgpu::Buffer,
+        sky: &Sky,
+    ) -> wgpu::BindGroup {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Environment Map"),
+            size: wgpu::Extent3d {
+                width: ENVIRONMENT_SIZE,
+                height: ENVIRONMENT_SIZE,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: ENVIRONMENT_MIPS,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (mip, texels) in sky.bake_specular().iter().enumerate() {
+            let size = ENVIRONMENT_SIZE >> mip;
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: mip as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(texels),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size * 8),
+                    rows_per_image: Some(size),
+                },
+                wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn set_block_textures(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layers: &[Vec<u8>]) {
+        self.block_texture_bind_group =
+            Self::create_block_textures(device, queue, &self.block_texture_layout, &self.block_sampler, layers);
+    }
+
+    // Edges of the views' frozen culling frustums, when they're frozen and meant to be drawn
+    pub fn frustum_lines<'a>(&self, views: impl IntoIterator<Item = &'a ViewCulling>) -> Vec<Vertex> {
+        let mut lines = Vec::new();
+        if !(self.freeze_frustum && self.draw_frustum) {
+            return lines;
+        }
+        let edges = [(0, 1), (2, 3), (4, 5), (6, 7), (0, 2), (1, 3), (4, 6), (5, 7), (0, 4), (1, 5), (2, 6), (3, 7)];
+        for frustum in views.into_iter().filter_map(|view| view.frozen_frustum) {
+            let corners = frustum.corners();
+            for (a, b) in edges {
+                lines.push(Vertex::new(corners[a].to_array(), FRUSTUM_COLOR));
+                lines.push(Vertex::new(corners[b].to_array(), FRUSTUM_COLOR));
+            }
+        }
+        lines
+    }
+
+    // Shows what culling let through in each named view
+    pub fn culling_ui<'a>(&mut self, ui: &mut egui::Ui, views: impl IntoIterator<Item = (&'a str, &'a ViewCulling)>) {
+        ui.checkbox(&mut self.frustum_culling, "Frustum culling");
+        ui.add_enabled_ui(self.frustum_culling, |ui| {
+            ui.checkbox(&mut self.freeze_frustum, "Freeze culling frustum");
+            ui.add_enabled(self.freeze_frustum, egui::Checkbox::new(&mut self.draw_frustum, "Draw frozen frustum"));
+        });
+        egui::Grid::new("culling_stats").num_columns(3).show(ui, |ui| {
+            ui.label("");
+            ui.label("Objects drawn");
+            ui.label("Chunks drawn");
+            ui.end_row();
+            for (name, view) in views {
+                let stats = view.stats;
+                ui.label(name);
+                ui.label(format!("{} / {}", stats.visible_objects, stats.total_objects));
+                ui.label(format!("{} / {}", stats.visible_chunks, stats.total_chunks));
+                ui.end_row();
+            }
+        });
+    }
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        self.meshes.push(mesh);
+        MeshId(self.meshes.len() - 1)
+    }
+
+    pub fn replace_mesh(&mut self, id: MeshId, mesh: Mesh) {
+        self.meshes[id.0] = mesh;
+    }
+
+    // Draws `mesh` once per instance every frame until the batch is updated
+    pub fn add_instance_batch(
+        &mut self,
+        device: &wgpu::Device,
+        mesh: MeshId,
+        instances: &[Instance],
+    ) -> InstanceBatchId {
+        let raw: Vec<InstanceRaw> = instances.iter().map(|i| i.to_raw()).collect();
+        self.instance_batches.push((mesh, InstanceBuffer::new(device, &raw)));
+        InstanceBatchId(self.instance_batches.len() - 1)
+    }
+
+    pub fn update_instance_batch(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        batch: InstanceBatchId,
+        instances: &[Instance],
+    ) {
+        let raw: Vec<InstanceRaw> = instances.iter().map(|i| i.to_raw()).collect();
+        self.instance_batches[batch.0].1.update(device, queue, &raw);
+    }
+
+    // Sets or clears (with `None`) the mesh drawn for a voxel chunk, meshed at the given level of detail
+    pub fn set_chunk_mesh(&mut self, coord: IVec3, mesh: Option<Mesh>, level: u32) {
+        match mesh {
+            Some(mesh) => self.voxel_chunks.insert(coord, (mesh, level)),
+            None => self.voxel_chunks.remove(&coord),
+        };
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    // Uploads an RGBA8 image and generates its mipmaps
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        srgb: bool,
+    ) -> TextureId {
+        self.add_texture(Texture::from_rgba8(device, queue, &self.mipmaps, name, width, height, pixels, srgb))
+    }
+
+    // Loads a PNG or JPEG file
+    pub fn load_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<std::path::Path>,
+        srgb: bool,
+    ) -> Result<TextureId, image::ImageError> {
+        let texture = Texture::load(device, queue, &self.mipmaps, path, srgb)?;
+        Ok(self.add_texture(texture))
+    }
+
+    fn add_texture(&mut self, texture: Texture) -> TextureId {
+        self.textures.push(texture);
+        TextureId(self.textures.len() - 1)
+    }
+
+    pub fn textures(&self) -> &[Texture] {
+        &self.textures
+    }
+
+    // Bind groups are shared by every material with the same textures
+    fn prepare_material(&mut self, device: &wgpu::Device, textures: MaterialTextures) {
+        if self.material_bind_groups.contains_key(&textures) {
+            return;
+        }
+        let view = |slot: Option<TextureId>, fallback| texture_view(&self.textures, slot, fallback);
+        let views = [
+            view(textures.base_color, &self.white_texture),
+            view(textures.metallic_roughness, &self.white_texture),
+            view(textures.normal, &self.flat_normal_texture),
+            view(textures.occlusion, &self.white_texture),
+            view(textures.emissive, &self.white_texture),
+        ];
+        let bind_group = crate::texture::bind_group(
+            device,
+            "Material Bind Group",
+            &self.material_layout,
+            &views,
+            &self.material_sampler,
+        );
+        self.material_bind_groups.insert(textures, bind_group);
+    }
+
+    // Node drawn with a selection outline
+    pub fn set_outlined(&mut self, node: Option<NodeId>) {
+        self.outlined = node;
+    }
+
+    // Replaces the overlay lines drawn on top of the scene, two vertices per line. The buffer
+    // only grows when there are more lines than it has ever held.
+    pub fn set_overlay_lines(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lines: &[Vertex]) {
+        if lines.len() > self.line_capacity {
+            self.line_capacity = lines.len().next_power_of_two();
+            self.line_buffer = Self::create_line_buffer(device, self.line_capacity);
+        }
+        if !lines.is_empty() {
+            queue.write_buffer(&self.line_buffer, 0, bytemuck::cast_slice(lines));
+        }
+        self.line_count = lines.len() as u32;
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        depth_view: &wgpu::TextureView,
+        scene: &Scene,
+        camera: &Camera,
+        aspect: f32,
+        active_shader: &str,
+        culling: &mut ViewCulling,
+    ) {
+        let camera_uniform = CameraUniform {
+            view_proj: camera.view_projection_matrix(aspect).to_cols_array_2d(),
+            position: camera.position.extend(1.0).to_array(),
+        };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&camera_uniform));
+        queue.write_buffer(
+            &self.lights_buffer,
+            0,
+            bytemuck::bytes_of(&scene.lights.to_uniform(self.shadows.cascades())),
+        );
+
+        if self.shadow_maps.set_resolution(device, self.shadows.resolution) {
+            self.camera_bind_group = Self::create_camera_bind_group(
+                device,
+                &self.camera_bind_group_layout,
+                &self.camera_buffer,
+                &self.lights_buffer,
+                &self.shadow_maps,
+            );
+        }
+        let shadow_views = match self.shadows.enabled {
+            true => shadow_views(&self.shadows, &scene.lights, camera, aspect),
+            false => Vec::new(),
+        };
+        let shadow_uniform = ShadowUniform::new(&self.shadows, &shadow_views, camera);
+        queue.write_buffer(&self.shadow_maps.uniform_buffer, 0, bytemuck::bytes_of(&shadow_uniform));
+
+        if self.baked_sky.as_ref() != Some(&self.environment.sky) {
+            self.environment_bind_group = Self::create_environment(
+                device,
+                queue,
+                &self.environment_layout,
+                &self.environment_sampler,
+                &self.environment_buffer,
+                &self.environment.sky,
+            );
+            self.environment_irradiance = self.environment.sky.irradiance();
+            self.baked_sky = Some(self.environment.sky.clone());
+        }
+        if self.built_sampling != self.material_sampling {
+            self.material_sampler = self.material_sampling.create(device, "Material Sampler");
+            self.material_bind_groups.clear();
+            self.built_sampling = self.material_sampling;
+        }
+
+        let environment_uniform = self.environment.to_uniform(&self.environment_irradiance);
+        queue.write_buffer(&self.environment_buffer, 0, bytemuck::bytes_of(&environment_uniform));
+
+        let camera_frustum = Frustum::from_matrix(camera.view_projection_matrix(aspect));
+        // Freezing keeps the frustum of the frame it was turned on in
+        let frustum = if self.freeze_frustum {
+            *culling.frozen_frustum.get_or_insert(camera_frustum)
+        } else {
+            culling.frozen_frustum = None;
+            camera_frustum
+        };
+        let frustum_culling = self.frustum_culling;
+        let in_view = |bounds: &Aabb| !frustum_culling || frustum.intersects_aabb(bounds);
+        let mut stats = CullingStats::default();
+
+        // Every scene node with a mesh and every visible voxel chunk gets a slot in the object
+        // buffer. Nodes out of view still need theirs to cast shadows.
+        let mut objects = Vec::new();
+        let mut scene_draws = Vec::new();
+        let mut shadow_casters = Vec::new();
+        for (id, world) in scene.world_matrices() {
+            let Some(node) = scene.node(id) else { continue };
+            let Some(mesh) = node.mesh else { continue };
+            stats.total_objects += 1;
+            let bounds = self.meshes[mesh.0].bounds.transformed(&world);
+            if !shadow_views.is_empty() {
+                shadow_casters.push((mesh, objects.len(), bounds));
+            }
+            let material = &node.material;
+            if in_view(&bounds) {
+                stats.visible_objects += 1;
+                // Physically based materials are drawn with their textures and sidedness
+                let pbr = (material.shading == ShadingModel::MetallicRoughness)
+                    .then_some((material.textures, material.double_sided));
+                scene_draws.push((id, mesh, objects.len(), pbr));
+            } else if shadow_views.is_empty() {
+                continue;
+            }
+            objects.push(ObjectUniform {
+                material: [material.specular, material.shininess, 0.0, 0.0],
+                pbr: [material.metallic, material.roughness, material.normal_scale, material.occlusion_strength],
+                emissive: Vec3::from(material.emissive).extend(material.alpha_cutoff).to_array(),
+                ..ObjectUniform::new(world, material.color)
+            });
+        }
+
+        for (.., pbr) in &scene_draws {
+            if let Some((textures, _)) = pbr {
+                self.prepare_material(device, *textures);
+            }
+        }
+
+        let mut chunk_draws = Vec::new();
+        for (coord, (mesh, level)) in &self.voxel_chunks {
+            stats.total_chunks += 1;
+            let origin = (*coord * CHUNK_SIZE as i32).as_vec3();
+            let bounds = Aabb::new(mesh.bounds.min + origin, mesh.bounds.max + origin);
+            if !in_view(&bounds) {
+                continue;
+            }
+            stats.visible_chunks += 1;
+            chunk_draws.push((mesh, objects.len()));
+            let color = match self.show_lod_levels {
+                true => LOD_COLORS[(*level as usize).min(LOD_COLORS.len() - 1)],
+                false => [1.0; 4],
+            };
+            objects.push(ObjectUniform::new(Mat4::from_translation(origin), color));
+        }
+
+        culling.stats = stats;
+
+        // The outline is an extra draw of the outlined mesh, enlarged around its center
+        // by an amount that stays roughly constant on screen
+        let outline = self.outlined.and_then(|id| {
+            let (_, mesh_id, mask_slot, _) = *scene_draws.iter().find(|(node, ..)| *node == id)?;
+            let world = Mat4::from_cols_array_2d(&objects[mask_slot].model);
+            let bounds = self.meshes[mesh_id.0].bounds;
+            let world_bounds = bounds.transformed(&world);
+            let radius = (world_bounds.size().max_element() * 0.5).max(1e-3);
+            let thickness = camera.apparent_distance(world_bounds.center()) * 0.006;
+            let scale = 1.0 + thickness / radius;
+            let center = bounds.center();
+            let enlarged = world
+                * Mat4::from_translation(center)
+                * Mat4::from_scale(Vec3::splat(scale))
+                * Mat4::from_translation(-center);
+            objects.push(ObjectUniform::new(enlarged, OUTLINE_COLOR));
+            Some((mesh_id, mask_slot, objects.len() - 1))
+        });
+
+        if objects.len() > self.object_capacity {
+            self.object_capacity = objects.len().next_power_of_two();
+            let (buffer, bind_group) = Self::create_object_buffer(
+                device,
+                &self.object_bind_group_layout,
+                self.object_stride,
+                self.object_capacity,
+            );
+            self.object_buffer = buffer;
+            self.object_bind_group = bind_group;
+        }
+
+        let mut object_data = vec![0u8; self.object_stride as usize * objects.len()];
+        for (i, uniform) in objects.iter().enumerate() {
+            let offset = i * self.object_stride as usize;
+            object_data[offset..offset + std::mem::size_of::<ObjectUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(uniform));
+        }
+        if !object_data.is_empty() {
+            queue.write_buffer(&self.object_buffer, 0, &object_data);
+        }
+        let slot_offset = |slot: usize| (slot as wgpu::BufferAddress * self.object_stride) as wgpu::DynamicOffset;
+
+        let casters: Vec<_> = shadow_casters
+            .iter()
+            .map(|(mesh, slot, bounds)| (&self.meshes[mesh.0], slot_offset(*slot), *bounds))
+            .collect();
+        let instanced: Vec<_> = self
+            .instance_batches
+            .iter()
+            .map(|(mesh, instances)| (&self.meshes[mesh.0], instances))
+            .collect();
+        self.shadow_maps
+            .render(queue, encoder, &shadow_views, &self.object_bind_group, &casters, &instanced);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.1,
+                        g: 0.2,
+                        b: 0.3,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+            }),
+            occlusion_query_set: None, // Default value, as occlusion queries aren't used
+            timestamp_writes: None,    // Default value, as no timestamps are written
+        });
+
+        match active_shader {
+            "main" => render_pass.set_pipeline(&self.render_pipeline),
+            "challenge" => render_pass.set_pipeline(&self.challenge_render_pipeline),
+            _ => render_pass.set_pipeline(&self.render_pipeline), // Default fallback
+        }
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+
+        // The challenge shader replaces every material
+        let challenge = active_shader == "challenge";
+        for (_, mesh, slot, pbr) in &scene_draws {
+            if pbr.is_none() || challenge {
+                render_pass.set_bind_group(1, &self.object_bind_group, &[slot_offset(*slot)]);
+                self.meshes[mesh.0].draw(&mut render_pass);
+            }
+        }
+
+        if !challenge {
+            render_pass.set_bind_group(3, &self.environment_bind_group, &[]);
+            for (_, mesh, slot, pbr) in &scene_draws {
+                let Some((textures, double_sided)) = pbr else { continue };
+                render_pass.set_pipeline(match double_sided {
+                    true => &self.pbr_double_sided_pipeline,
+                    false => &self.pbr_pipeline,
+                });
+                render_pass.set_bind_group(1, &self.object_bind_group, &[slot_offset(*slot)]);
+                render_pass.set_bind_group(2, &self.material_bind_groups[textures], &[]);
+                self.meshes[mesh.0].draw(&mut render_pass);
+            }
+        }
+
+        render_pass.set_pipeline(&self.voxel_pipeline);
+        render_pass.set_bind_group(2, &self.block_texture_bind_group, &[]);
+        for (mesh, slot) in &chunk_draws {
+            render_pass.set_bind_group(1, &self.object_bind_group, &[slot_offset(*slot)]);
+            mesh.draw(&mut render_pass);
+        }
+
+        match active_shader {
+            "challenge" => render_pass.set_pipeline(&self.challenge_instanced_pipeline),
+            _ => render_pass.set_pipeline(&self.instanced_pipeline),
+        }
+        for (mesh, instances) in &self.instance_batches {
+            if instances.count > 0 {
+                render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+                self.meshes[mesh.0].draw_instanced(&mut render_pass, 0..instances.count);
+            }
+        }
+
+        if let Some((mesh, mask_slot, outline_slot)) = outline {
+            let mesh = &self.meshes[mesh.0];
+            render_pass.set_stencil_reference(1);
+            for (pipeline, slot) in [
+                (&self.outline_mask_pipeline, mask_slot),
+                (&self.outline_pipeline, outline_slot),
+            ] {
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(1, &self.object_bind_group, &[slot_offset(slot)]);
+                mesh.draw(&mut render_pass);
+            }
+        }
+
+        if self.line_count > 0 {
+            render_pass.set_pipeline(&self.line_pipeline);
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.line_buffer.slice(..));
+            render_pass.draw(0..self.line_count, 0..1);
+        }
+        drop(render_pass);
+
+        if self.shadows.show_maps {
+            self.shadow_maps.draw_debug(queue, encoder, view, aspect, &shadow_views);
+        }
+    }
+}
+
+// A material slot's texture, or the stand-in for slots left empty
+fn texture_view<'a>(textures: &'a [Texture], slot: Option<TextureId>, fallback: &'a Texture) -> &'a wgpu::TextureView {
+    slot.and_then(|id| textures.get(id.0)).map_or(&fallback.view, |t| &t.view)
+}
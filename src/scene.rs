@@ -0,0 +1,290 @@
+// scene.rs
+
+use crate::lights::SceneLights;
+use crate::mesh::MeshId;
+use crate::texture::TextureId;
+use glam::{Mat4, Quat, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadingModel {
+    BlinnPhong,        // Diffuse light plus a highlight, from `specular` and `shininess`
+    MetallicRoughness, // Physically based, with glTF's material model
+}
+
+// Texture slots of a metallic-roughness material, laid out like glTF's. All of them are
+// sampled with the mesh's uvs.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MaterialTextures {
+    pub base_color: Option<TextureId>,         // sRGB, multiplied with `color`
+    pub metallic_roughness: Option<TextureId>, // Roughness in green, metalness in blue
+    pub normal: Option<TextureId>,             // Tangent space
+    pub occlusion: Option<TextureId>,          // In red
+    pub emissive: Option<TextureId>,           // sRGB, multiplied with `emissive`
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub shading: ShadingModel,
+    pub color: [f32; 4], // Multiplied with the vertex color in the shader
+    pub specular: f32,   // Strength of the highlights, 0 for a matte surface
+    pub shininess: f32,  // Blinn-Phong exponent, higher for smaller and sharper highlights
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3], // Linear light given off, added after shading
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub alpha_cutoff: f32, // Pixels with a lower base color alpha are cut out, 0 keeps them all
+    pub double_sided: bool,
+    pub textures: MaterialTextures,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            shading: ShadingModel::BlinnPhong,
+            color: [1.0, 1.0, 1.0, 1.0],
+            specular: 0.5,
+            shininess: 32.0,
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: [0.0; 3],
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            alpha_cutoff: 0.0,
+            double_sided: false,
+            textures: MaterialTextures::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<MeshId>,
+    pub transform: Transform,
+    pub material: Material,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>, mesh: Option<MeshId>, transform: Transform) -> Self {
+        Self {
+            name: name.into(),
+            mesh,
+            transform,
+            material: Material::default(),
+            parent: None,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+// Nodes live in a slot list so that ids stay stable when other nodes are removed
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    nodes: Vec<Option<Node>>,
+    roots: Vec<NodeId>,
+    pub lights: SceneLights,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, mut node: Node, parent: Option<NodeId>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        let parent = parent.filter(|p| self.node(*p).is_some());
+        node.parent = parent;
+        node.children.clear();
+        self.nodes.push(Some(node));
+
+        match parent {
+            Some(p) => self.nodes[p.0].as_mut().unwrap().children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0).and_then(|n| n.as_ref())
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id.0).and_then(|n| n.as_mut())
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.iter().filter(|n| n.is_some()).count()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| n.as_ref().map(|n| (NodeId(i), n)))
+    }
+
+    // Returns true if `ancestor` is `id` itself or one of its parents
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(c) = current {
+            if c == ancestor {
+                return true;
+            }
+            current = self.node(c).and_then(|n| n.parent);
+        }
+        false
+    }
+
+    // Re-parents a node, keeping its local transform. Refuses to create cycles.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        if self.node(id).is_none() {
+            return false;
+        }
+        if let Some(p) = parent {
+            if self.node(p).is_none() || self.is_ancestor(id, p) {
+                return false;
+            }
+        }
+
+        self.detach(id);
+        self.nodes[id.0].as_mut().unwrap().parent = parent;
+        match parent {
+            Some(p) => self.nodes[p.0].as_mut().unwrap().children.push(id),
+            None => self.roots.push(id),
+        }
+        true
+    }
+
+    // Removes a node together with all of its descendants
+    pub fn remove_node(&mut self, id: NodeId) -> Option<Node> {
+        self.node(id)?;
+        self.detach(id);
+
+        let mut stack = vec![id];
+        let mut removed = None;
+        while let Some(current) = stack.pop() {
+            if let Some(node) = self.nodes[current.0].take() {
+                stack.extend_from_slice(&node.children);
+                if current == id {
+                    removed = Some(node);
+                }
+            }
+        }
+        removed
+    }
+
+    // Copies a node and its whole subtree next to the original
+    pub fn duplicate_node(&mut self, id: NodeId) -> Option<NodeId> {
+        let node = self.node(id)?;
+        let parent = node.parent;
+        let mut copy = node.clone();
+        copy.name = format!("{} (copy)", node.name);
+        let copy_id = self.add_node(copy, parent);
+        self.copy_children(id, copy_id);
+        Some(copy_id)
+    }
+
+    fn copy_children(&mut self, from: NodeId, to: NodeId) {
+        let children = self.nodes[from.0].as_ref().unwrap().children.clone();
+        for child in children {
+            let copy = self.nodes[child.0].as_ref().unwrap().clone();
+            let copy_id = self.add_node(copy, Some(to));
+            self.copy_children(child, copy_id);
+        }
+    }
+
+    fn detach(&mut self, id: NodeId) {
+        match self.nodes[id.0].as_ref().unwrap().parent {
+            Some(p) => {
+                if let Some(parent) = self.nodes[p.0].as_mut() {
+                    parent.children.retain(|c| *c != id);
+                }
+            }
+            None => self.roots.retain(|r| *r != id),
+        }
+    }
+
+    pub fn world_matrix(&self, id: NodeId) -> Mat4 {
+        let mut matrix = Mat4::IDENTITY;
+        let mut current = Some(id);
+        while let Some(c) = current {
+            match self.node(c) {
+                Some(node) => {
+                    matrix = node.transform.matrix() * matrix;
+                    current = node.parent;
+                }
+                None => break,
+            }
+        }
+        matrix
+    }
+
+    // World matrices for every node, parents before children
+    pub fn world_matrices(&self) -> Vec<(NodeId, Mat4)> {
+        let mut result = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<(NodeId, Mat4)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|r| (*r, Mat4::IDENTITY))
+            .collect();
+
+        while let Some((id, parent_matrix)) = stack.pop() {
+            let Some(node) = self.node(id) else { continue };
+            let world = parent_matrix * node.transform.matrix();
+            result.push((id, world));
+            for child in node.children.iter().rev() {
+                stack.push((*child, world));
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,303 @@
+// scene_editor.rs
+
+use crate::mesh::MeshId;
+use crate::scene::{Material, Node, NodeId, Scene, ShadingModel, Transform};
+use crate::texture::TextureId;
+use glam::{EulerRot, Quat};
+
+// Outliner and inspector state. Holds the current selection shared by both panels.
+#[derive(Default)]
+pub struct SceneEditor {
+    pub selected: Option<NodeId>,
+}
+
+// Deferred so the scene isn't modified while the tree is being iterated
+enum OutlinerAction {
+    Add(Option<NodeId>, Option<MeshId>, String),
+    Duplicate(NodeId),
+    Delete(NodeId),
+}
+
+impl SceneEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn outliner_ui(&mut self, ui: &mut egui::Ui, scene: &mut Scene, meshes: &[(&str, MeshId)]) {
+        let mut action = None;
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{} objects", scene.len()));
+            ui.menu_button("Add", |ui| {
+                if ui.button("Empty").clicked() {
+                    action = Some(OutlinerAction::Add(None, None, "Empty".to_string()));
+                    ui.close_menu();
+                }
+                for (name, mesh) in meshes {
+                    if ui.button(*name).clicked() {
+                        action = Some(OutlinerAction::Add(None, Some(*mesh), name.to_string()));
+                        ui.close_menu();
+                    }
+                }
+            });
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for root in scene.roots().to_vec() {
+                self.node_ui(ui, scene, root, meshes, &mut action);
+            }
+        });
+
+        match action {
+            Some(OutlinerAction::Add(parent, mesh, name)) => {
+                let id = scene.add_node(Node::new(name, mesh, Transform::default()), parent);
+                self.selected = Some(id);
+            }
+            Some(OutlinerAction::Duplicate(id)) => {
+                self.selected = scene.duplicate_node(id).or(self.selected);
+            }
+            Some(OutlinerAction::Delete(id)) => {
+                scene.remove_node(id);
+                if self.selected.is_some_and(|s| scene.node(s).is_none()) {
+                    self.selected = None;
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn node_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        scene: &Scene,
+        id: NodeId,
+        meshes: &[(&str, MeshId)],
+        action: &mut Option<OutlinerAction>,
+    ) {
+        let Some(node) = scene.node(id) else { return };
+
+        let mut header = |ui: &mut egui::Ui| {
+            let response = ui.selectable_label(self.selected == Some(id), &node.name);
+            if response.clicked() {
+                self.selected = Some(id);
+            }
+            response.context_menu(|ui| {
+                ui.menu_button("Add child", |ui| {
+                    if ui.button("Empty").clicked() {
+                        *action = Some(OutlinerAction::Add(Some(id), None, "Empty".to_string()));
+                        ui.close_menu();
+                    }
+                    for (name, mesh) in meshes {
+                        if ui.button(*name).clicked() {
+                            *action = Some(OutlinerAction::Add(Some(id), Some(*mesh), name.to_string()));
+                            ui.close_menu();
+                        }
+                    }
+                });
+                if ui.button("Duplicate").clicked() {
+                    *action = Some(OutlinerAction::Duplicate(id));
+                    ui.close_menu();
+                }
+                if ui.button("Delete").clicked() {
+                    *action = Some(OutlinerAction::Delete(id));
+                    ui.close_menu();
+                }
+            });
+        };
+
+        if node.children().is_empty() {
+            ui.horizontal(|ui| {
+                // Line leaves up with the labels of nodes that have a collapse arrow
+                ui.add_space(ui.spacing().indent);
+                header(ui);
+            });
+        } else {
+            let state = egui::collapsing_header::CollapsingState::load_with_default_open(
+                ui.ctx(),
+                ui.make_persistent_id(id),
+                true,
+            );
+            state
+                .show_header(ui, header)
+                .body(|ui| {
+                    for child in node.children() {
+                        self.node_ui(ui, scene, *child, meshes, action);
+                    }
+                });
+        }
+    }
+
+    pub fn inspector_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        scene: &mut Scene,
+        meshes: &[(&str, MeshId)],
+        textures: &[(&str, TextureId)],
+    ) {
+        let Some(id) = self.selected else {
+            ui.label("Nothing selected");
+            return;
+        };
+
+        // Candidate parents: everything that isn't the node itself or one of its descendants
+        let parents: Vec<(NodeId, String)> = scene
+            .iter()
+            .filter(|(other, _)| !scene.is_ancestor(id, *other))
+            .map(|(other, node)| (other, node.name.clone()))
+            .collect();
+        let Some(node) = scene.node_mut(id) else {
+            self.selected = None;
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut node.name);
+        });
+
+        let mut parent = node.parent();
+        egui::ComboBox::from_label("Parent")
+            .selected_text(
+                parent
+                    .and_then(|p| parents.iter().find(|(other, _)| *other == p))
+                    .map_or("None", |(_, name)| name.as_str()),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut parent, None, "None");
+                for (other, name) in &parents {
+                    ui.selectable_value(&mut parent, Some(*other), name);
+                }
+            });
+
+        ui.separator();
+        ui.label("Transform");
+        let transform = &mut node.transform;
+        egui::Grid::new("transform_grid").num_columns(4).show(ui, |ui| {
+            ui.label("Position");
+            ui.add(egui::DragValue::new(&mut transform.translation.x).speed(0.01).prefix("x: "));
+            ui.add(egui::DragValue::new(&mut transform.translation.y).speed(0.01).prefix("y: "));
+            ui.add(egui::DragValue::new(&mut transform.translation.z).speed(0.01).prefix("z: "));
+            ui.end_row();
+
+            // Rotation is edited as XYZ euler angles in degrees
+            let (x, y, z) = transform.rotation.to_euler(EulerRot::XYZ);
+            let mut euler = [x.to_degrees(), y.to_degrees(), z.to_degrees()];
+            ui.label("Rotation");
+            let mut changed = false;
+            for (value, prefix) in euler.iter_mut().zip(["x: ", "y: ", "z: "]) {
+                changed |= ui
+                    .add(egui::DragValue::new(value).speed(0.5).suffix("°").prefix(prefix))
+                    .changed();
+            }
+            if changed {
+                transform.rotation = Quat::from_euler(
+                    EulerRot::XYZ,
+                    euler[0].to_radians(),
+                    euler[1].to_radians(),
+                    euler[2].to_radians(),
+                );
+            }
+            ui.end_row();
+
+            ui.label("Scale");
+            ui.add(egui::DragValue::new(&mut transform.scale.x).speed(0.01).prefix("x: "));
+            ui.add(egui::DragValue::new(&mut transform.scale.y).speed(0.01).prefix("y: "));
+            ui.add(egui::DragValue::new(&mut transform.scale.z).speed(0.01).prefix("z: "));
+            ui.end_row();
+        });
+        if ui.button("Reset transform").clicked() {
+            node.transform = Transform::default();
+        }
+
+        ui.separator();
+        ui.label("Mesh and material");
+        egui::ComboBox::from_label("Mesh")
+            .selected_text(
+                node.mesh
+                    .and_then(|m| meshes.iter().find(|(_, mesh)| *mesh == m))
+                    .map_or("None", |(name, _)| *name),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut node.mesh, None, "None");
+                for (name, mesh) in meshes {
+                    ui.selectable_value(&mut node.mesh, Some(*mesh), *name);
+                }
+            });
+        material_ui(ui, &mut node.material, textures);
+
+        if parent != node.parent() {
+            scene.set_parent(id, parent);
+        }
+    }
+}
+
+fn material_ui(ui: &mut egui::Ui, material: &mut Material, textures: &[(&str, TextureId)]) {
+    ui.horizontal(|ui| {
+        ui.label("Shading");
+        ui.selectable_value(&mut material.shading, ShadingModel::BlinnPhong, "Blinn-Phong");
+        ui.selectable_value(&mut material.shading, ShadingModel::MetallicRoughness, "Metallic-roughness");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Color");
+        ui.color_edit_button_rgba_unmultiplied(&mut material.color);
+    });
+
+    if material.shading == ShadingModel::BlinnPhong {
+        ui.horizontal(|ui| {
+            ui.label("Specular");
+            ui.add(egui::DragValue::new(&mut material.specular).range(0.0..=4.0).speed(0.01));
+            ui.label("Shininess");
+            ui.add(egui::DragValue::new(&mut material.shininess).range(1.0..=1024.0).speed(0.5));
+        });
+        return;
+    }
+
+    egui::Grid::new("pbr_material").num_columns(2).show(ui, |ui| {
+        ui.label("Metallic");
+        ui.add(egui::Slider::new(&mut material.metallic, 0.0..=1.0));
+        ui.end_row();
+        ui.label("Roughness");
+        ui.add(egui::Slider::new(&mut material.roughness, 0.0..=1.0));
+        ui.end_row();
+        ui.label("Emissive");
+        ui.color_edit_button_rgb(&mut material.emissive);
+        ui.end_row();
+        ui.label("Normal scale");
+        ui.add(egui::DragValue::new(&mut material.normal_scale).range(0.0..=4.0).speed(0.01));
+        ui.end_row();
+        ui.label("Occlusion strength");
+        ui.add(egui::Slider::new(&mut material.occlusion_strength, 0.0..=1.0));
+        ui.end_row();
+        ui.label("Alpha cutoff");
+        ui.add(egui::Slider::new(&mut material.alpha_cutoff, 0.0..=1.0));
+        ui.end_row();
+    });
+    ui.checkbox(&mut material.double_sided, "Double-sided");
+
+    ui.label("Textures");
+    let slots = &mut material.textures;
+    egui::Grid::new("pbr_textures").num_columns(2).show(ui, |ui| {
+        for (label, slot) in [
+            ("Base color", &mut slots.base_color),
+            ("Metallic-roughness", &mut slots.metallic_roughness),
+            ("Normal", &mut slots.normal),
+            ("Occlusion", &mut slots.occlusion),
+            ("Emissive", &mut slots.emissive),
+        ] {
+            ui.label(label);
+            egui::ComboBox::from_id_source(label)
+                .selected_text(
+                    slot.and_then(|t| textures.iter().find(|(_, texture)| *texture == t))
+                        .map_or("None", |(name, _)| *name),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(slot, None, "None");
+                    for (name, texture) in textures {
+                        ui.selectable_value(slot, Some(*texture), *name);
+                    }
+                });
+            ui.end_row();
+        }
+    });
+}
@@ -1,29 +1,228 @@
 // Vertex shader
 
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+struct ObjectUniform {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    normal: mat4x4<f32>,
+    material: vec4<f32>, // Specular strength, shininess
+    pbr: vec4<f32>,      // Metallic, roughness, normal scale, occlusion strength
+    emissive: vec4<f32>, // Emitted color, alpha cutoff
+};
+
+const MAX_LIGHTS: u32 = 16u;
+const MAX_SHADOW_MAPS: u32 = 8u;
+const DIRECTIONAL_LIGHT: f32 = 0.0;
+const SPOT_LIGHT: f32 = 2.0;
+
+struct Light {
+    position: vec4<f32>,  // w: 0 directional, 1 point, 2 spot
+    direction: vec4<f32>, // w: range
+    color: vec4<f32>,
+    cone: vec4<f32>,      // Cosines of the inner and outer spot angles
+    shadow: vec4<f32>,    // First shadow map layer and layer count, no shadows with a count of 0
+};
+
+struct Lights {
+    ambient: vec4<f32>,
+    count: u32,
+    lights: array<Light, MAX_LIGHTS>,
+};
+
+struct Shadows {
+    matrices: array<mat4x4<f32>, MAX_SHADOW_MAPS>,
+    texels: array<vec4<f32>, 2>, // World size of a texel per map; per unit of distance for spot lights
+    splits: vec4<f32>,           // Distance from the camera where each cascade ends
+    view: vec4<f32>,             // Camera forward, cascade count
+    params: vec4<f32>,           // Depth bias, normal bias in texels, filter radius, tint cascades
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(0) @binding(1)
+var<uniform> lights: Lights;
+
+@group(0) @binding(2)
+var<uniform> shadows: Shadows;
+
+@group(0) @binding(3)
+var shadow_maps: texture_depth_2d_array;
+
+@group(0) @binding(4)
+var shadow_sampler: sampler_comparison;
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniform;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+};
+
+// Per-instance model matrix and color, used instead of the object uniform when instancing
+struct InstanceInput {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+    @location(9) color: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) material: vec2<f32>,
 };
 
 @vertex
 fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
+    let world_position = object.model * vec4<f32>(model.position, 1.0);
+    var out: VertexOutput;
+    out.color = model.color * object.color.rgb;
+    out.world_position = world_position.xyz;
+    out.normal = (object.normal * vec4<f32>(model.normal, 0.0)).xyz;
+    out.material = object.material.xy;
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+@vertex
+fn vs_instanced(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_0,
+        instance.model_1,
+        instance.model_2,
+        instance.model_3,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
     var out: VertexOutput;
-    out.color = model.color;
-    out.clip_position = vec4<f32>(model.position, 1.0);
+    out.color = model.color * instance.color.rgb;
+    out.world_position = world_position.xyz;
+    // Instances are scaled evenly, so the model matrix works for normals too
+    out.normal = (model_matrix * vec4<f32>(model.normal, 0.0)).xyz;
+    out.material = vec2<f32>(0.5, 32.0);
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
 // Fragment shader
 
+// Cascade of the sun's shadow a point falls in, the cascade count past the last one
+fn cascade_index(position: vec3<f32>) -> u32 {
+    let depth = dot(position - camera.position.xyz, shadows.view.xyz);
+    let count = u32(shadows.view.w);
+    for (var i = 0u; i < count; i++) {
+        if depth < shadows.splits[i] {
+            return i;
+        }
+    }
+    return count;
+}
+
+// How much of a light reaches a point, from 0 in full shadow to 1. The point is pushed out
+// along the surface normal by a few shadow map texels before the lookup, and the comparisons
+// around it are averaged for soft edges.
+fn shadow(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if light.shadow.y < 1.0 {
+        return 1.0;
+    }
+    var layer = u32(light.shadow.x);
+    var texel: f32;
+    if light.position.w == DIRECTIONAL_LIGHT {
+        let cascade = cascade_index(position);
+        if cascade >= u32(light.shadow.y) {
+            return 1.0;
+        }
+        layer += cascade;
+        texel = shadows.texels[layer / 4u][layer % 4u];
+    } else {
+        texel = shadows.texels[layer / 4u][layer % 4u] * distance(light.position.xyz, position);
+    }
+
+    let clip = shadows.matrices[layer] * vec4<f32>(position + normal * texel * shadows.params.y, 1.0);
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if clip.w <= 0.0 || any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let radius = i32(shadows.params.z);
+    let size = vec2<f32>(textureDimensions(shadow_maps));
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) / size;
+            lit += textureSampleCompareLevel(shadow_maps, shadow_sampler, uv + offset, layer, ndc.z - shadows.params.x);
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// Debug colors for the shadow cascades
+fn cascade_tint(position: vec3<f32>) -> vec3<f32> {
+    var tints = array<vec3<f32>, 5>(
+        vec3<f32>(1.0, 0.5, 0.5),
+        vec3<f32>(0.5, 1.0, 0.5),
+        vec3<f32>(0.5, 0.6, 1.0),
+        vec3<f32>(1.0, 1.0, 0.4),
+        vec3<f32>(1.0, 1.0, 1.0),
+    );
+    return tints[min(cascade_index(position), 4u)];
+}
+
+// Lambert diffuse plus a Blinn-Phong highlight from one light
+fn shade(light: Light, position: vec3<f32>, normal: vec3<f32>, view: vec3<f32>, albedo: vec3<f32>, material: vec2<f32>) -> vec3<f32> {
+    var to_light = -light.direction.xyz;
+    var attenuation = 1.0;
+    if light.position.w != DIRECTIONAL_LIGHT {
+        let offset = light.position.xyz - position;
+        let distance = length(offset);
+        to_light = offset / max(distance, 1e-4);
+        // Smooth inverse square falloff that reaches zero at the range
+        let range = light.direction.w;
+        let fade = clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0);
+        attenuation = fade * fade / (1.0 + distance * distance);
+        if light.position.w == SPOT_LIGHT {
+            let cos_angle = dot(-to_light, normalize(light.direction.xyz));
+            attenuation *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+        }
+    }
+
+    let n_dot_l = max(dot(normal, to_light), 0.0);
+    if n_dot_l <= 0.0 || attenuation <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let halfway = normalize(to_light + view);
+    let specular = material.x * pow(max(dot(normal, halfway), 0.0), material.y);
+    return (albedo * n_dot_l + vec3<f32>(specular * n_dot_l)) * light.color.rgb * attenuation;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
-}
\ No newline at end of file
+    let view = normalize(camera.position.xyz - in.world_position);
+    let normal = normalize(in.normal);
+
+    var color = lights.ambient.rgb * in.color;
+    for (var i = 0u; i < min(lights.count, MAX_LIGHTS); i++) {
+        let light = lights.lights[i];
+        color += shade(light, in.world_position, normal, view, in.color, in.material) * shadow(light, in.world_position, normal);
+    }
+    if shadows.params.w > 0.5 {
+        color *= cascade_tint(in.world_position);
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,47 @@
+// Depth-only shadow pass, rendering scene objects from one light's point of view
+
+struct ShadowPass {
+    view_proj: mat4x4<f32>,
+};
+
+struct ObjectUniform {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    normal: mat4x4<f32>,
+    material: vec4<f32>, // Specular strength, shininess
+    pbr: vec4<f32>,      // Metallic, roughness, normal scale, occlusion strength
+    emissive: vec4<f32>, // Emitted color, alpha cutoff
+};
+
+@group(0) @binding(0)
+var<uniform> shadow_pass: ShadowPass;
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_0: vec4<f32>,
+    @location(6) model_1: vec4<f32>,
+    @location(7) model_2: vec4<f32>,
+    @location(8) model_3: vec4<f32>,
+};
+
+@vertex
+fn vs_main(model: VertexInput) -> @builtin(position) vec4<f32> {
+    return shadow_pass.view_proj * object.model * vec4<f32>(model.position, 1.0);
+}
+
+@vertex
+fn vs_instanced(model: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_0,
+        instance.model_1,
+        instance.model_2,
+        instance.model_3,
+    );
+    return shadow_pass.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+}
@@ -0,0 +1,59 @@
+// Shadow map thumbnails drawn over the scene, one instance per layer
+
+const MAX_SHADOW_MAPS: u32 = 8u;
+
+struct Debug {
+    layers: array<vec4<f32>, MAX_SHADOW_MAPS>, // Near and far plane, 1 for perspective views
+    params: vec4<f32>,                         // Layer count, thumbnail height in clip space, aspect ratio
+};
+
+@group(0) @binding(0)
+var<uniform> debug: Debug;
+
+// Bound as plain floats, which every backend can load from
+@group(0) @binding(1)
+var shadow_maps: texture_2d_array<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) layer: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex];
+    let margin = 0.02;
+    let size = vec2<f32>(debug.params.y / debug.params.z, debug.params.y);
+    let origin = vec2<f32>(-1.0 + margin + f32(layer) * (size.x + margin), -1.0 + margin);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(origin + corner * size, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    out.layer = layer;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(shadow_maps));
+    let texel = clamp(vec2<i32>(in.uv * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    let depth = textureLoad(shadow_maps, texel, i32(in.layer), 0).r;
+    // Shown as distance from the light, from black at the near plane (or the light itself
+    // for cascades, whose near plane is pulled back to catch casters) to white at the far plane
+    let range = debug.layers[in.layer];
+    var distance = range.x + depth * (range.y - range.x);
+    if range.z > 0.5 {
+        distance = range.x * range.y / (range.y - depth * (range.y - range.x));
+    }
+    let start = max(range.x, 0.0);
+    let gray = (distance - start) / (range.y - start);
+    return vec4<f32>(vec3<f32>(gray), 1.0);
+}
@@ -0,0 +1,626 @@
+// shadows.rs
+
+use crate::bounds::{Aabb, Frustum};
+use crate::camera::Camera;
+use crate::instance::{InstanceBuffer, InstanceRaw};
+use crate::lights::{LightKind, SceneLights};
+use crate::mesh::Mesh;
+use crate::vertex::Vertex;
+use bytemuck::{Pod, Zeroable};
+use egui_wgpu::wgpu;
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+// Layers of the shadow map array, shared by all lights
+pub const MAX_SHADOW_MAPS: usize = 8;
+pub const MAX_CASCADES: usize = 4;
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+const RESOLUTIONS: [u32; 4] = [512, 1024, 2048, 4096];
+// Objects this far towards the sun from a cascade still cast shadows into it
+const CASTER_DISTANCE: f32 = 100.0;
+// Spot light shadows start this close to the light
+const SPOT_NEAR: f32 = 0.05;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    pub resolution: u32, // Edge length of every shadow map
+    pub depth_bias: f32, // Subtracted from the depth before comparing
+    pub normal_bias: f32, // How far surfaces are pushed out along their normal, in shadow map texels
+    pub pcf_radius: u32, // The filter averages (2r + 1)² samples
+    pub cascade_count: usize,
+    pub cascade_splits: [f32; MAX_CASCADES], // Distance from the camera where each cascade ends
+    pub show_maps: bool,
+    pub show_cascades: bool, // Tints surfaces by the cascade they read the sun's shadow from
+}
+
+impl ShadowSettings {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            resolution: 2048,
+            depth_bias: 0.0002,
+            normal_bias: 1.5,
+            pcf_radius: 1,
+            cascade_count: 3,
+            cascade_splits: [6.0, 18.0, 50.0, 100.0],
+            show_maps: false,
+            show_cascades: false,
+        }
+    }
+
+    // Number of maps each directional light gets, 0 with shadows off
+    pub fn cascades(&self) -> usize {
+        match self.enabled {
+            true => self.cascade_count.clamp(1, MAX_CASCADES),
+            false => 0,
+        }
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "Shadows");
+        ui.label("Scene objects cast and receive them; the voxel terrain keeps its baked light.");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            egui::Grid::new("shadow_settings").num_columns(2).show(ui, |ui| {
+                ui.label("Resolution");
+                egui::ComboBox::from_id_source("shadow_resolution")
+                    .selected_text(self.resolution.to_string())
+                    .show_ui(ui, |ui| {
+                        for resolution in RESOLUTIONS {
+                            ui.selectable_value(&mut self.resolution, resolution, resolution.to_string());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Depth bias");
+                ui.add(egui::DragValue::new(&mut self.depth_bias).range(0.0..=0.01).speed(0.00001).max_decimals(5));
+                ui.end_row();
+
+                ui.label("Normal bias");
+                ui.add(egui::DragValue::new(&mut self.normal_bias).range(0.0..=8.0).speed(0.05).suffix(" texels"));
+                ui.end_row();
+
+                ui.label("Filter radius");
+                ui.add(egui::Slider::new(&mut self.pcf_radius, 0..=3));
+                ui.end_row();
+
+                ui.label("Sun cascades");
+                ui.add(egui::Slider::new(&mut self.cascade_count, 1..=MAX_CASCADES));
+                ui.end_row();
+
+                // Each split stays past the one before it
+                for i in 0..self.cascade_count {
+                    let previous = if i == 0 { 0.5 } else { self.cascade_splits[i - 1] + 0.5 };
+                    ui.label(format!("Cascade {} end", i + 1));
+                    ui.add(
+                        egui::DragValue::new(&mut self.cascade_splits[i])
+                            .range(previous..=1000.0)
+                            .speed(0.1)
+                            .suffix(" m"),
+                    );
+                    self.cascade_splits[i] = self.cascade_splits[i].max(previous);
+                    ui.end_row();
+                }
+            });
+            ui.checkbox(&mut self.show_maps, "Show shadow maps");
+            ui.checkbox(&mut self.show_cascades, "Tint cascades");
+        });
+    }
+}
+
+// What one layer of the shadow map array sees
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowView {
+    pub view_proj: Mat4,
+    texel: f32, // World size of a texel; for spot lights, per unit of distance from the light
+    planes: (f32, f32), // Near and far plane
+    perspective: bool,
+}
+
+// Views for every shadow map layer handed out to the scene's lights, in layer order
+pub fn shadow_views(settings: &ShadowSettings, lights: &SceneLights, camera: &Camera, aspect: f32) -> Vec<ShadowView> {
+    let cascades = settings.cascades();
+    let mut views = Vec::new();
+    for (light, layer) in lights.shaded().zip(lights.shadow_layers(cascades)) {
+        if layer.is_none() {
+            continue;
+        }
+        match light.kind {
+            LightKind::Directional => {
+                let mut near = camera.znear;
+                for far in &settings.cascade_splits[..cascades] {
+                    let far = far.min(camera.zfar);
+                    views.push(cascade_view(light.direction, camera, aspect, near, far, settings.resolution));
+                    near = far;
+                }
+            }
+            LightKind::Spot => views.push(spot_view(
+                light.position,
+                light.direction,
+                light.outer_angle,
+                light.range,
+                settings.resolution,
+            )),
+            LightKind::Point => {}
+        }
+    }
+    views
+}
+
+// An orthographic view around the sphere enclosing the slice of the camera's frustum
+// between `near` and `far`. The sphere keeps the size fixed as the camera turns, and
+// snapping to whole texels keeps the edges from crawling as it moves.
+fn cascade_view(direction: Vec3, camera: &Camera, aspect: f32, near: f32, far: f32, resolution: u32) -> ShadowView {
+    let forward = (camera.target - camera.position).normalize_or(Vec3::NEG_Z);
+    let right = forward.cross(camera.up).normalize_or(Vec3::X);
+    let up = right.cross(forward);
+    let corners: Vec<Vec3> = [near, far]
+        .iter()
+        .flat_map(|distance| {
+            let half_height = camera.half_height_at(*distance);
+            let half_width = half_height * aspect;
+            let center = camera.position + forward * *distance;
+            [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+                .map(|(x, y)| center + right * (x * half_width) + up * (y * half_height))
+        })
+        .collect();
+    let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+    let radius = corners.iter().map(|c| c.distance(center)).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let direction = direction.normalize_or(Vec3::NEG_Y);
+    let view = Mat4::look_at_rh(center - direction * radius, center, any_up(direction));
+    let projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, -CASTER_DISTANCE, radius * 2.0);
+    let origin = (projection * view).project_point3(Vec3::ZERO).truncate() * (resolution as f32 * 0.5);
+    let snap = (origin.round() - origin) / (resolution as f32 * 0.5);
+    ShadowView {
+        view_proj: Mat4::from_translation(snap.extend(0.0)) * projection * view,
+        texel: radius * 2.0 / resolution as f32,
+        planes: (-CASTER_DISTANCE, radius * 2.0),
+        perspective: false,
+    }
+}
+
+fn spot_view(position: Vec3, direction: Vec3, outer_angle: f32, range: f32, resolution: u32) -> ShadowView {
+    let direction = direction.normalize_or(Vec3::NEG_Y);
+    let fov = (outer_angle * 2.0).clamp(1.0, 170.0).to_radians();
+    let far = range.max(SPOT_NEAR * 2.0);
+    let view = Mat4::look_at_rh(position, position + direction, any_up(direction));
+    ShadowView {
+        view_proj: Mat4::perspective_rh(fov, 1.0, SPOT_NEAR, far) * view,
+        texel: (fov * 0.5).tan() * 2.0 / resolution as f32,
+        planes: (SPOT_NEAR, far),
+        perspective: true,
+    }
+}
+
+// An up vector that isn't parallel to `direction`
+fn any_up(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::X
+    } else {
+        Vec3::Y
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ShadowUniform {
+    matrices: [[[f32; 4]; 4]; MAX_SHADOW_MAPS],
+    texels: [[f32; 4]; MAX_SHADOW_MAPS / 4], // ShadowView::texel, four layers to a vector
+    splits: [f32; 4],
+    view: [f32; 4],   // Camera forward, cascade count
+    params: [f32; 4], // Depth bias, normal bias, filter radius, tint cascades
+}
+
+impl ShadowUniform {
+    pub fn new(settings: &ShadowSettings, views: &[ShadowView], camera: &Camera) -> Self {
+        let mut uniform = Self::zeroed();
+        for (i, view) in views.iter().enumerate().take(MAX_SHADOW_MAPS) {
+            uniform.matrices[i] = view.view_proj.to_cols_array_2d();
+            uniform.texels[i / 4][i % 4] = view.texel;
+        }
+        uniform.splits = settings.cascade_splits;
+        let forward = (camera.target - camera.position).normalize_or(Vec3::NEG_Z);
+        uniform.view = forward.extend(settings.cascades() as f32).to_array();
+        uniform.params = [
+            settings.depth_bias,
+            settings.normal_bias,
+            settings.pcf_radius as f32,
+            settings.show_cascades as u32 as f32,
+        ];
+        uniform
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct DebugUniform {
+    layers: [[f32; 4]; MAX_SHADOW_MAPS], // Near and far plane, 1 for perspective views
+    params: [f32; 4],                    // Layer count, thumbnail height in clip space, aspect ratio
+}
+
+// The shadow map array with the depth-only pipelines that fill it, and the overlay that shows it
+pub struct ShadowMaps {
+    pipeline: wgpu::RenderPipeline,
+    instanced_pipeline: wgpu::RenderPipeline,
+    pass_buffer: wgpu::Buffer, // One light matrix per layer, bound with a dynamic offset
+    pass_bind_group: wgpu::BindGroup,
+    pass_stride: wgpu::BufferAddress,
+    pub uniform_buffer: wgpu::Buffer,
+    pub sampler: wgpu::Sampler,
+    resolution: u32,
+    pub view: wgpu::TextureView, // Every layer, for sampling
+    layer_views: Vec<wgpu::TextureView>,
+    debug_pipeline: wgpu::RenderPipeline,
+    debug_layout: wgpu::BindGroupLayout,
+    debug_buffer: wgpu::Buffer,
+    debug_bind_group: wgpu::BindGroup,
+}
+
+impl ShadowMaps {
+    pub fn new(
+        device: &wgpu::Device,
+        object_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        resolution: u32,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+
+        let pass_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Pass Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Mat4>() as u64),
+                },
+                count: None,
+            }],
+        });
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let pass_stride = (std::mem::size_of::<Mat4>() as wgpu::BufferAddress).div_ceil(alignment) * alignment;
+        let pass_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Pass Buffer"),
+            size: pass_stride * MAX_SHADOW_MAPS as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Pass Bind Group"),
+            layout: &pass_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &pass_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<Mat4>() as u64),
+                }),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&pass_layout, object_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, false);
+        let instanced_pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, true);
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Buffer"),
+            contents: bytemuck::bytes_of(&ShadowUniform::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Hardware filtered comparisons, giving a 2x2 blend for every sample of the filter
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let debug_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Debug Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow_debug.wgsl").into()),
+        });
+        let debug_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Debug Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let debug_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Debug Pipeline Layout"),
+            bind_group_layouts: &[&debug_layout],
+            push_constant_ranges: &[],
+        });
+        let debug_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Debug Pipeline"),
+            layout: Some(&debug_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &debug_shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &debug_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let debug_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Debug Buffer"),
+            contents: bytemuck::bytes_of(&DebugUniform::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let (view, layer_views) = Self::create_maps(device, resolution);
+        let debug_bind_group = Self::create_debug_bind_group(device, &debug_layout, &debug_buffer, &view);
+        Self {
+            pipeline,
+            instanced_pipeline,
+            pass_buffer,
+            pass_bind_group,
+            pass_stride,
+            uniform_buffer,
+            sampler,
+            resolution,
+            view,
+            layer_views,
+            debug_pipeline,
+            debug_layout,
+            debug_buffer,
+            debug_bind_group,
+        }
+    }
+
+    // Depth only, both sides so that flat meshes cast shadows too. The slope scaled bias keeps
+    // surfaces at grazing angles from shadowing themselves.
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        instanced: bool,
+    ) -> wgpu::RenderPipeline {
+        let vertex_buffers = [Vertex::desc(), InstanceRaw::desc()];
+        let (label, entry_point, buffers) = match instanced {
+            true => ("Instanced Shadow Pipeline", "vs_instanced", &vertex_buffers[..]),
+            false => ("Shadow Pipeline", "vs_main", &vertex_buffers[..1]),
+        };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point,
+                buffers,
+                compilation_options: Default::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 0,
+                    slope_scale: 1.5,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn create_maps(device: &wgpu::Device, resolution: u32) -> (wgpu::TextureView, Vec<wgpu::TextureView>) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Maps"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: MAX_SHADOW_MAPS as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..MAX_SHADOW_MAPS as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Shadow Map Layer"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        (view, layer_views)
+    }
+
+    fn create_debug_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Debug Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+            ],
+        })
+    }
+
+    // Reallocates the maps at a new size. Returns whether it did, as bind groups sampling
+    // the old maps have to be made again.
+    pub fn set_resolution(&mut self, device: &wgpu::Device, resolution: u32) -> bool {
+        if resolution == self.resolution {
+            return false;
+        }
+        (self.view, self.layer_views) = Self::create_maps(device, resolution);
+        self.debug_bind_group = Self::create_debug_bind_group(
+            device,
+            &self.debug_layout,
+            &self.debug_buffer,
+            &self.view,
+        );
+        self.resolution = resolution;
+        true
+    }
+
+    // Renders one layer per view. Casters are culled against each view on their own,
+    // since objects the camera can't see still throw shadows into view.
+    pub fn render(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        views: &[ShadowView],
+        object_bind_group: &wgpu::BindGroup,
+        casters: &[(&Mesh, wgpu::DynamicOffset, Aabb)],
+        instanced: &[(&Mesh, &InstanceBuffer)],
+    ) {
+        let mut matrices = vec![0u8; self.pass_stride as usize * views.len()];
+        for (i, view) in views.iter().enumerate() {
+            let offset = i * self.pass_stride as usize;
+            let matrix = view.view_proj.to_cols_array();
+            matrices[offset..offset + std::mem::size_of::<Mat4>()].copy_from_slice(bytemuck::bytes_of(&matrix));
+        }
+        if !matrices.is_empty() {
+            queue.write_buffer(&self.pass_buffer, 0, &matrices);
+        }
+
+        for (i, (view, layer_view)) in views.iter().zip(&self.layer_views).enumerate() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: layer_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            let frustum = Frustum::from_matrix(view.view_proj);
+            render_pass.set_pipeline(&self.pipeline);
+            let pass_offset = (i as wgpu::BufferAddress * self.pass_stride) as wgpu::DynamicOffset;
+            render_pass.set_bind_group(0, &self.pass_bind_group, &[pass_offset]);
+            // Instanced draws don't read the object uniform, but the layout still has it
+            render_pass.set_bind_group(1, object_bind_group, &[0]);
+            for (mesh, offset, bounds) in casters {
+                if frustum.intersects_aabb(bounds) {
+                    render_pass.set_bind_group(1, object_bind_group, &[*offset]);
+                    mesh.draw(&mut render_pass);
+                }
+            }
+            render_pass.set_pipeline(&self.instanced_pipeline);
+            for (mesh, instances) in instanced {
+                if instances.count > 0 {
+                    render_pass.set_vertex_buffer(1, instances.buffer.slice(..));
+                    mesh.draw_instanced(&mut render_pass, 0..instances.count);
+                }
+            }
+        }
+    }
+
+    // Thumbnails of the maps in use along the bottom of the target, nearest depth black
+    pub fn draw_debug(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        aspect: f32,
+        views: &[ShadowView],
+    ) {
+        if views.is_empty() {
+            return;
+        }
+        let count = views.len().min(MAX_SHADOW_MAPS);
+        let mut uniform = DebugUniform::zeroed();
+        for (layer, view) in uniform.layers.iter_mut().zip(views) {
+            *layer = [view.planes.0, view.planes.1, view.perspective as u32 as f32, 0.0];
+        }
+        // Square thumbnails, as large as fits up to a quarter of the height
+        let margin = 0.02;
+        let height = ((2.0 - margin) / count as f32 - margin) * aspect;
+        uniform.params = [count as f32, height.min(0.5), aspect, 0.0];
+        queue.write_buffer(&self.debug_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Debug Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.debug_pipeline);
+        render_pass.set_bind_group(0, &self.debug_bind_group, &[]);
+        render_pass.draw(0..6, 0..count as u32);
+    }
+}
@@ -0,0 +1,514 @@
+// terrain.rs
+
+use crate::voxel::{BlockId, Chunk, AIR, CHUNK_SIZE, CHUNK_VOLUME};
+use glam::{IVec3, Vec3};
+
+const STONE: BlockId = 1;
+const DIRT: BlockId = 2;
+const GRASS: BlockId = 3;
+const SAND: BlockId = 4;
+const SNOW: BlockId = 5;
+
+// Cave noise is sampled every few voxels and interpolated in between, which is much
+// cheaper than evaluating it per voxel and smooth enough for round tunnels
+const CAVE_STEP: i32 = 4;
+
+// Offsets mixed into the seed so each noise layer is independent
+const HEIGHT_SALT: u32 = 0x68e3_1da4;
+const BIOME_SALT: u32 = 0xb529_7a4d;
+const CAVE_SALT: u32 = 0x1b56_c4e9;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TerrainParams {
+    pub seed: u32,
+    pub base_height: i32,
+    pub height_scale: f32,
+    pub frequency: f32, // Heightmap features per voxel at the first octave
+    pub octaves: u32,
+    pub persistence: f32, // Amplitude multiplier between octaves
+    pub lacunarity: f32,  // Frequency multiplier between octaves
+    pub biome_frequency: f32,
+    pub snow_height: i32,
+    pub caves: bool,
+    pub cave_frequency: f32,
+    pub cave_threshold: f32, // Cave noise above this is carved out
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        Self {
+            seed: 1,
+            base_height: -12,
+            height_scale: 10.0,
+            frequency: 0.01,
+            octaves: 4,
+            persistence: 0.5,
+            lacunarity: 2.0,
+            biome_frequency: 0.004,
+            snow_height: 12,
+            caves: true,
+            cave_frequency: 0.04,
+            cave_threshold: 0.3,
+        }
+    }
+}
+
+impl TerrainParams {
+    // Tells apart terrains with different settings. Computed without std's hasher, which
+    // isn't guaranteed to stay the same between builds, since it names the save directory.
+    pub fn fingerprint(&self) -> u64 {
+        let words = [
+            self.seed,
+            self.base_height as u32,
+            self.height_scale.to_bits(),
+            self.frequency.to_bits(),
+            self.octaves,
+            self.persistence.to_bits(),
+            self.lacunarity.to_bits(),
+            self.biome_frequency.to_bits(),
+            self.snow_height as u32,
+            self.caves as u32,
+            self.cave_frequency.to_bits(),
+            self.cave_threshold.to_bits(),
+        ];
+        // 64 bit FNV-1a
+        words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+}
+
+// Deterministic terrain: the same parameters always produce the same voxels, no matter
+// in which order or how often chunks are generated
+#[derive(Debug, Clone)]
+pub struct TerrainGenerator {
+    pub params: TerrainParams,
+}
+
+impl TerrainGenerator {
+    pub fn new(params: TerrainParams) -> Self {
+        Self { params }
+    }
+
+    // Low frequency noise deciding the biome; negative is dry, positive is mountainous
+    fn biome_value(&self, x: i32, z: i32) -> f32 {
+        let p = self.params.biome_frequency;
+        fbm2(self.params.seed ^ BIOME_SALT, x as f32 * p, z as f32 * p, 2, 0.5, 2.0)
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let value = self.biome_value(x, z);
+        if value < -0.2 {
+            Biome::Desert
+        } else if value > 0.25 {
+            Biome::Mountains
+        } else {
+            Biome::Plains
+        }
+    }
+
+    // Y of the topmost solid voxel in a column, ignoring caves
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let p = &self.params;
+        let noise = fbm2(
+            p.seed ^ HEIGHT_SALT,
+            x as f32 * p.frequency,
+            z as f32 * p.frequency,
+            p.octaves,
+            p.persistence,
+            p.lacunarity,
+        );
+        // Mountains fade in with the biome value so there are no cliffs at biome borders
+        let mountains = smoothstep(0.1, 0.4, self.biome_value(x, z));
+        let amplitude = p.height_scale * (1.0 + 2.0 * mountains);
+        p.base_height + (noise * amplitude).round() as i32
+    }
+
+    fn surface_block(&self, biome: Biome, height: i32, y: i32) -> BlockId {
+        let depth = height - y;
+        match biome {
+            Biome::Plains if depth == 0 => GRASS,
+            Biome::Plains if depth <= 3 => DIRT,
+            Biome::Desert if depth <= 4 => SAND,
+            Biome::Mountains if depth == 0 && height >= self.params.snow_height => SNOW,
+            _ => STONE,
+        }
+    }
+
+    fn cave_sample(&self, lattice: IVec3) -> f32 {
+        let p = lattice.as_vec3() * CAVE_STEP as f32 * self.params.cave_frequency;
+        // Squashed vertically so caves run mostly sideways
+        fbm3(self.params.seed ^ CAVE_SALT, p.x, p.y * 1.5, p.z, 2, 0.5, 2.0)
+    }
+
+    // One voxel at a time, which the tests check `generate_chunk` against
+    #[cfg(test)]
+    pub(crate) fn block_at(&self, pos: IVec3) -> BlockId {
+        let height = self.height_at(pos.x, pos.z);
+        if pos.y > height {
+            return AIR;
+        }
+        if self.params.caves {
+            let cell = pos.div_euclid(IVec3::splat(CAVE_STEP));
+            let corners = cube_corners().map(|offset| self.cave_sample(cell + offset));
+            let t = pos.rem_euclid(IVec3::splat(CAVE_STEP)).as_vec3() / CAVE_STEP as f32;
+            if trilinear(&corners, t) > self.params.cave_threshold {
+                return AIR;
+            }
+        }
+        self.surface_block(self.biome_at(pos.x, pos.z), height, pos.y)
+    }
+
+    // Same voxels as `block_at`, computed a whole chunk at a time. Returns None for chunks
+    // that would be all air.
+    pub fn generate_chunk(&self, coord: IVec3) -> Option<Chunk> {
+        let size = CHUNK_SIZE as i32;
+        let origin = coord * size;
+
+        let mut columns = Vec::with_capacity(CHUNK_SIZE * CHUNK_SIZE);
+        for z in 0..size {
+            for x in 0..size {
+                let (wx, wz) = (origin.x + x, origin.z + z);
+                columns.push((self.height_at(wx, wz), self.biome_at(wx, wz)));
+            }
+        }
+        let highest = columns.iter().map(|(height, _)| *height).max().unwrap();
+        if origin.y > highest {
+            return None;
+        }
+
+        // The chunk spans whole lattice cells since its size is a multiple of the step
+        let cells = size / CAVE_STEP;
+        let first_cell = origin.div_euclid(IVec3::splat(CAVE_STEP));
+        let lattice_side = (cells + 1) as usize;
+        let lattice: Vec<f32> = if self.params.caves {
+            (0..lattice_side.pow(3))
+                .map(|i| {
+                    let (x, z, y) = (i % lattice_side, (i / lattice_side) % lattice_side, i / lattice_side.pow(2));
+                    self.cave_sample(first_cell + IVec3::new(x as i32, y as i32, z as i32))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let lattice_at = |p: IVec3| lattice[(p.y as usize * lattice_side + p.z as usize) * lattice_side + p.x as usize];
+
+        let mut blocks = vec![AIR; CHUNK_VOLUME];
+        let mut solid = false;
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let (height, biome) = columns[(z * size + x) as usize];
+                    let wy = origin.y + y;
+                    if wy > height {
+                        continue;
+                    }
+                    if self.params.caves {
+                        let local = IVec3::new(x, y, z);
+                        let cell = local / CAVE_STEP;
+                        let corners = cube_corners().map(|offset| lattice_at(cell + offset));
+                        let t = (local % CAVE_STEP).as_vec3() / CAVE_STEP as f32;
+                        if trilinear(&corners, t) > self.params.cave_threshold {
+                            continue;
+                        }
+                    }
+                    blocks[((y * size + z) * size + x) as usize] = self.surface_block(biome, height, wy);
+                    solid = true;
+                }
+            }
+        }
+
+        solid.then(|| Chunk::from_blocks(&blocks))
+    }
+
+    // Returns true if any parameter was changed
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = self.params;
+        let p = &mut self.params;
+        egui::Grid::new("terrain_params").num_columns(2).show(ui, |ui| {
+            ui.label("Seed");
+            ui.add(egui::DragValue::new(&mut p.seed));
+            ui.end_row();
+            ui.label("Base height");
+            ui.add(egui::DragValue::new(&mut p.base_height).range(-64..=32));
+            ui.end_row();
+            ui.label("Height scale");
+            ui.add(egui::DragValue::new(&mut p.height_scale).speed(0.1).range(0.0..=64.0));
+            ui.end_row();
+            ui.label("Frequency");
+            ui.add(egui::DragValue::new(&mut p.frequency).speed(0.0005).range(0.0005..=0.2));
+            ui.end_row();
+            ui.label("Octaves");
+            ui.add(egui::DragValue::new(&mut p.octaves).range(1..=8));
+            ui.end_row();
+            ui.label("Persistence");
+            ui.add(egui::DragValue::new(&mut p.persistence).speed(0.01).range(0.0..=1.0));
+            ui.end_row();
+            ui.label("Lacunarity");
+            ui.add(egui::DragValue::new(&mut p.lacunarity).speed(0.01).range(1.0..=4.0));
+            ui.end_row();
+            ui.label("Biome frequency");
+            ui.add(egui::DragValue::new(&mut p.biome_frequency).speed(0.0002).range(0.0002..=0.05));
+            ui.end_row();
+            ui.label("Snow height");
+            ui.add(egui::DragValue::new(&mut p.snow_height).range(-64..=64));
+            ui.end_row();
+            ui.label("Caves");
+            ui.checkbox(&mut p.caves, "");
+            ui.end_row();
+            ui.label("Cave frequency");
+            ui.add_enabled(p.caves, egui::DragValue::new(&mut p.cave_frequency).speed(0.001).range(0.005..=0.2));
+            ui.end_row();
+            ui.label("Cave threshold");
+            ui.add_enabled(p.caves, egui::DragValue::new(&mut p.cave_threshold).speed(0.005).range(0.0..=1.0));
+            ui.end_row();
+        });
+        if ui.button("Reset").clicked() {
+            self.params = TerrainParams::default();
+        }
+        self.params != before
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Corner offsets in the order `trilinear` expects: x fastest, then y, then z
+fn cube_corners() -> [IVec3; 8] {
+    std::array::from_fn(|i| IVec3::new(i as i32 & 1, (i as i32 >> 1) & 1, (i as i32 >> 2) & 1))
+}
+
+fn trilinear(c: &[f32; 8], t: Vec3) -> f32 {
+    let x0 = lerp(c[0], c[1], t.x);
+    let x1 = lerp(c[2], c[3], t.x);
+    let x2 = lerp(c[4], c[5], t.x);
+    let x3 = lerp(c[6], c[7], t.x);
+    lerp(lerp(x0, x1, t.y), lerp(x2, x3, t.y), t.z)
+}
+
+// Integer hash of a lattice point, so noise needs no permutation tables
+fn hash(seed: u32, x: i32, y: i32, z: i32) -> u32 {
+    let mut h = seed
+        ^ (x as u32).wrapping_mul(0x27d4_eb2d)
+        ^ (y as u32).wrapping_mul(0x1656_67b1)
+        ^ (z as u32).wrapping_mul(0x9e37_79b1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297a_2d39);
+    h ^ (h >> 15)
+}
+
+fn gradient2(hash: u32, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 => x + y,
+        1 => x - y,
+        2 => -x + y,
+        3 => -x - y,
+        4 => x,
+        5 => -x,
+        6 => y,
+        _ => -y,
+    }
+}
+
+// The 12 cube edge directions, with four repeated to fill 16 slots
+fn gradient3(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    match hash & 15 {
+        0 | 12 => x + y,
+        1 | 13 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 | 14 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+// Perlin gradient noise, roughly in -1..1
+fn perlin2(seed: u32, x: f32, y: f32) -> f32 {
+    let (xi, yi) = (x.floor() as i32, y.floor() as i32);
+    let (xf, yf) = (x - xi as f32, y - yi as f32);
+    let corner = |dx: i32, dy: i32| gradient2(hash(seed, xi + dx, yi + dy, 0), xf - dx as f32, yf - dy as f32);
+    let (u, v) = (fade(xf), fade(yf));
+    lerp(lerp(corner(0, 0), corner(1, 0), u), lerp(corner(0, 1), corner(1, 1), u), v)
+}
+
+fn perlin3(seed: u32, x: f32, y: f32, z: f32) -> f32 {
+    let (xi, yi, zi) = (x.floor() as i32, y.floor() as i32, z.floor() as i32);
+    let f = Vec3::new(x - xi as f32, y - yi as f32, z - zi as f32);
+    let corners = cube_corners().map(|o| {
+        let h = hash(seed, xi + o.x, yi + o.y, zi + o.z);
+        let d = f - o.as_vec3();
+        gradient3(h, d.x, d.y, d.z)
+    });
+    trilinear(&corners, Vec3::new(fade(f.x), fade(f.y), fade(f.z)))
+}
+
+// Fractal sums of octaves, normalized back to roughly -1..1
+fn fbm2(seed: u32, x: f32, y: f32, octaves: u32, persistence: f32, lacunarity: f32) -> f32 {
+    let (mut sum, mut norm, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for octave in 0..octaves {
+        sum += perlin2(seed.wrapping_add(octave), x * frequency, y * frequency) * amplitude;
+        norm += amplitude;
+        amplitude *= persistence;
+        frequency *= lacunarity;
+    }
+    if norm > 0.0 {
+        sum / norm
+    } else {
+        0.0
+    }
+}
+
+fn fbm3(seed: u32, x: f32, y: f32, z: f32, octaves: u32, persistence: f32, lacunarity: f32) -> f32 {
+    let (mut sum, mut norm, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for octave in 0..octaves {
+        sum += perlin3(seed.wrapping_add(octave), x * frequency, y * frequency, z * frequency) * amplitude;
+        norm += amplitude;
+        amplitude *= persistence;
+        frequency *= lacunarity;
+    }
+    if norm > 0.0 {
+        sum / norm
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Around the default surface, plus one deep underground and one in the air
+    const COORDS: [IVec3; 6] = [
+        IVec3::new(0, -1, 0),
+        IVec3::new(3, -1, -2),
+        IVec3::new(-5, -1, 7),
+        IVec3::new(2, 0, 9),
+        IVec3::new(1, -3, -1),
+        IVec3::new(0, 4, 0),
+    ];
+
+    fn generator(seed: u32) -> TerrainGenerator {
+        TerrainGenerator::new(TerrainParams {
+            seed,
+            ..TerrainParams::default()
+        })
+    }
+
+    #[test]
+    fn same_seed_same_chunks() {
+        let (a, b) = (generator(7), generator(7));
+        let first: Vec<Option<Chunk>> = COORDS.iter().map(|coord| a.generate_chunk(*coord)).collect();
+        // In the opposite order, so nothing depends on what was generated before
+        let mut second: Vec<Option<Chunk>> = COORDS.iter().rev().map(|coord| b.generate_chunk(*coord)).collect();
+        second.reverse();
+        assert_eq!(first, second);
+        assert!(first[0].is_some());
+        assert!(first[5].is_none(), "chunks high above the surface are all air");
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let (a, b) = (generator(1), generator(2));
+        assert!(COORDS.iter().any(|coord| a.generate_chunk(*coord) != b.generate_chunk(*coord)));
+        assert_ne!(a.height_at(100, -40), b.height_at(100, -40));
+    }
+
+    #[test]
+    fn chunks_match_single_voxels() {
+        let terrain = generator(3);
+        let size = CHUNK_SIZE as i32;
+        for coord in COORDS {
+            let chunk = terrain.generate_chunk(coord).unwrap_or_default();
+            for y in (0..size).step_by(3) {
+                for z in (0..size).step_by(2) {
+                    for x in (0..size).step_by(2) {
+                        let pos = coord * size + IVec3::new(x, y, z);
+                        let block = chunk.get(x as usize, y as usize, z as usize);
+                        assert_eq!(block, terrain.block_at(pos), "voxel {} of chunk {}", pos, coord);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn surface_blocks_follow_the_biome() {
+        let terrain = generator(5);
+        for (x, z) in [(0, 0), (40, -75), (-300, 220), (512, 512)] {
+            let height = terrain.height_at(x, z);
+            let top = terrain.block_at(IVec3::new(x, height, z));
+            assert_eq!(terrain.block_at(IVec3::new(x, height + 1, z)), AIR);
+            let expected = match terrain.biome_at(x, z) {
+                Biome::Plains => GRASS,
+                Biome::Desert => SAND,
+                Biome::Mountains if height >= terrain.params.snow_height => SNOW,
+                Biome::Mountains => STONE,
+            };
+            // The top voxel can be carved out by a cave
+            assert!(top == expected || top == AIR, "({}, {}) has {} on top", x, z, top);
+        }
+    }
+
+    // Pinned output of one seed, so changes to the noise or the biomes show up here. Update the
+    // values only when changing the terrain on purpose, since it changes every existing world.
+    #[test]
+    fn fixed_seed_gives_known_voxels() {
+        let terrain = generator(1234);
+        let heights = [((0, 0), -12), ((-663, 847), -14), ((114, -266), -8), ((743, -1167), -8)];
+        for ((x, z), height) in heights {
+            assert_eq!(terrain.height_at(x, z), height, "height at {}, {}", x, z);
+        }
+        assert_eq!(terrain.biome_at(0, 0), Biome::Plains);
+        assert_eq!(terrain.biome_at(-145, 105), Biome::Desert);
+        assert_eq!(terrain.biome_at(114, -266), Biome::Mountains);
+
+        let voxels = [
+            (IVec3::new(0, -11, 0), AIR),
+            (IVec3::new(0, -12, 0), GRASS),
+            (IVec3::new(0, -13, 0), DIRT),
+            (IVec3::new(0, -18, 0), STONE),
+            // A cave reaching the surface
+            (IVec3::new(-90, -12, 33), AIR),
+            (IVec3::new(-90, -15, 33), DIRT),
+            (IVec3::new(-145, -15, 105), SAND),
+            (IVec3::new(-145, -19, 105), SAND),
+            (IVec3::new(-145, -20, 105), STONE),
+            (IVec3::new(114, -8, -266), STONE),
+            (IVec3::new(114, -7, -266), AIR),
+            (IVec3::new(743, -8, -1167), GRASS),
+        ];
+        let size = IVec3::splat(CHUNK_SIZE as i32);
+        for (pos, block) in voxels {
+            assert_eq!(terrain.block_at(pos), block, "voxel {}", pos);
+            let chunk = terrain.generate_chunk(pos.div_euclid(size)).unwrap_or_default();
+            let local = pos.rem_euclid(size).as_uvec3();
+            assert_eq!(chunk.get(local.x as usize, local.y as usize, local.z as usize), block, "voxel {}", pos);
+        }
+    }
+}
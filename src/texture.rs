@@ -0,0 +1,386 @@
+// texture.rs
+
+use crate::blocks::{linear_to_srgb, srgb_to_linear};
+use egui_wgpu::wgpu;
+use std::path::Path;
+
+// Handle into the renderer's texture list, shared by every material using the image
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureId(pub usize);
+
+pub struct Texture {
+    pub name: String,
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl Texture {
+    // Uploads RGBA8 pixels, rows from the top, and fills in the mip chain on the GPU. Colors
+    // are stored sRGB encoded; data like normals and roughness is not.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_rgba8(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mipmaps: &MipmapGenerator,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        srgb: bool,
+    ) -> Self {
+        let texture = Self::upload(device, queue, name, width, height, pixels, srgb, mip_level_count(width, height));
+        mipmaps.generate(device, queue, &texture.texture);
+        texture
+    }
+
+    // Reads a PNG or JPEG file
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mipmaps: &MipmapGenerator,
+        path: impl AsRef<Path>,
+        srgb: bool,
+    ) -> Result<Self, image::ImageError> {
+        let path = path.as_ref();
+        let image = image::open(path)?.to_rgba8();
+        let name = path.file_name().map_or("Texture".to_string(), |s| s.to_string_lossy().into_owned());
+        Ok(Self::from_rgba8(device, queue, mipmaps, &name, image.width(), image.height(), &image, srgb))
+    }
+
+    // A single pixel of one color, for material slots without a texture
+    pub fn solid(device: &wgpu::Device, queue: &wgpu::Queue, name: &str, rgba: [u8; 4]) -> Self {
+        Self::upload(device, queue, name, 1, 1, &rgba, false, 1)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn upload(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        srgb: bool,
+        mip_level_count: u32,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(name),
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: match srgb {
+                true => wgpu::TextureFormat::Rgba8UnormSrgb,
+                false => wgpu::TextureFormat::Rgba8Unorm,
+            },
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            name: name.to_string(),
+            texture,
+            view,
+        }
+    }
+
+    // One mip level on its own, decoded the way the shaders see it
+    pub fn mip_view(&self, mip: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Texture Mip View"),
+            base_mip_level: mip,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })
+    }
+}
+
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    width.max(height).max(1).ilog2() + 1
+}
+
+// Halves an RGBA8 image by averaging 2x2 blocks, in linear space when it's sRGB encoded.
+// Odd sizes round down, reusing the last row or column.
+pub fn downsample(pixels: &[u8], width: usize, height: usize, srgb: bool) -> Vec<u8> {
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut out = Vec::with_capacity(half_width * half_height * 4);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            for c in 0..4 {
+                let texel = |dx: usize, dy: usize| {
+                    let (sx, sy) = ((x * 2 + dx).min(width - 1), (y * 2 + dy).min(height - 1));
+                    pixels[(sy * width + sx) * 4 + c] as f32 / 255.0
+                };
+                let samples = [texel(0, 0), texel(1, 0), texel(0, 1), texel(1, 1)];
+                let value = if c == 3 || !srgb {
+                    samples.iter().sum::<f32>() / 4.0
+                } else {
+                    linear_to_srgb(samples.iter().map(|s| srgb_to_linear(*s)).sum::<f32>() / 4.0)
+                };
+                out.push((value * 255.0).round() as u8);
+            }
+        }
+    }
+    out
+}
+
+// Fills in mip levels by drawing each level from the one above it with a linear filter.
+// Drawing into sRGB levels averages the colors in linear space.
+pub struct MipmapGenerator {
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipelines: Vec<(wgpu::TextureFormat, wgpu::RenderPipeline)>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Mipmap Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("mipmap.wgsl").into()),
+        });
+        let layout = bind_group_layout(device, "Mipmap Bind Group Layout", 1);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mipmap Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = [wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureFormat::Rgba8UnormSrgb]
+            .into_iter()
+            .map(|format| {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Mipmap Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                        compilation_options: Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: Default::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                });
+                (format, pipeline)
+            })
+            .collect();
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            layout,
+            sampler,
+            pipelines,
+        }
+    }
+
+    // Overwrites every level past the first. The texture needs to be a render attachment.
+    pub fn generate(&self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        if texture.mip_level_count() < 2 {
+            return;
+        }
+        let Some((_, pipeline)) = self.pipelines.iter().find(|(format, _)| *format == texture.format()) else {
+            log::warn!("No mipmaps for textures in {:?}", texture.format());
+            return;
+        };
+        let level_view = |mip| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Mipmap Level"),
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap Encoder"),
+        });
+        for mip in 1..texture.mip_level_count() {
+            let source = level_view(mip - 1);
+            let target = level_view(mip);
+            let bind_group = bind_group(device, "Mipmap Bind Group", &self.layout, &[&source], &self.sampler);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+// How a texture is filtered and repeated
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SamplerSettings {
+    pub filter: wgpu::FilterMode, // Magnification and minification
+    pub mipmap_filter: wgpu::FilterMode,
+    pub address_mode: wgpu::AddressMode,
+    pub anisotropy: u16, // 1 is off. Needs linear filtering everywhere.
+}
+
+impl SamplerSettings {
+    pub fn new() -> Self {
+        Self {
+            filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            address_mode: wgpu::AddressMode::Repeat,
+            anisotropy: 1,
+        }
+    }
+
+    fn anisotropy_allowed(&self) -> bool {
+        self.filter == wgpu::FilterMode::Linear && self.mipmap_filter == wgpu::FilterMode::Linear
+    }
+
+    pub fn create(&self, device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: self.address_mode,
+            address_mode_v: self.address_mode,
+            address_mode_w: self.address_mode,
+            mag_filter: self.filter,
+            min_filter: self.filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: match self.anisotropy_allowed() {
+                true => self.anisotropy.clamp(1, 16),
+                false => 1,
+            },
+            ..Default::default()
+        })
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("sampler_settings").num_columns(2).show(ui, |ui| {
+            for (label, filter) in [("Filter", &mut self.filter), ("Mipmap filter", &mut self.mipmap_filter)] {
+                ui.label(label);
+                ui.horizontal(|ui| {
+                    ui.selectable_value(filter, wgpu::FilterMode::Nearest, "Nearest");
+                    ui.selectable_value(filter, wgpu::FilterMode::Linear, "Linear");
+                });
+                ui.end_row();
+            }
+
+            ui.label("Address mode");
+            egui::ComboBox::from_id_source("address_mode")
+                .selected_text(format!("{:?}", self.address_mode))
+                .show_ui(ui, |ui| {
+                    for mode in [
+                        wgpu::AddressMode::Repeat,
+                        wgpu::AddressMode::MirrorRepeat,
+                        wgpu::AddressMode::ClampToEdge,
+                    ] {
+                        ui.selectable_value(&mut self.address_mode, mode, format!("{:?}", mode));
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Anisotropy");
+            let allowed = self.anisotropy_allowed();
+            ui.add_enabled(allowed, egui::Slider::new(&mut self.anisotropy, 1..=16).suffix("x"))
+                .on_disabled_hover_text("Needs linear filtering and mipmap filtering");
+            ui.end_row();
+        });
+    }
+}
+
+// Layout of `count` filterable 2D textures at bindings 0 up to `count`, followed by the
+// sampler they share
+pub fn bind_group_layout(device: &wgpu::Device, label: &str, count: u32) -> wgpu::BindGroupLayout {
+    let mut entries: Vec<wgpu::BindGroupLayoutEntry> = (0..count)
+        .map(|binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        })
+        .collect();
+    entries.push(wgpu::BindGroupLayoutEntry {
+        binding: count,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    });
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &entries,
+    })
+}
+
+// Bind group for a layout made by `bind_group_layout`
+pub fn bind_group(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::BindGroupLayout,
+    views: &[&wgpu::TextureView],
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    let mut entries: Vec<wgpu::BindGroupEntry> = views
+        .iter()
+        .enumerate()
+        .map(|(binding, view)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: wgpu::BindingResource::TextureView(view),
+        })
+        .collect();
+    entries.push(wgpu::BindGroupEntry {
+        binding: views.len() as u32,
+        resource: wgpu::BindingResource::Sampler(sampler),
+    });
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &entries,
+    })
+}
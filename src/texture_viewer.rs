@@ -0,0 +1,79 @@
+// texture_viewer.rs
+
+use crate::egui_tools::EguiRenderer;
+use crate::texture::Texture;
+use egui_wgpu::wgpu;
+
+// Shows the renderer's textures one mip level at a time
+pub struct TextureViewer {
+    pub selected: Option<usize>,
+    pub mip: u32,
+    registered: Option<((usize, u32), egui::TextureId)>, // Texture index and mip level egui can draw
+}
+
+impl TextureViewer {
+    pub fn new() -> Self {
+        Self {
+            selected: None,
+            mip: 0,
+            registered: None,
+        }
+    }
+
+    // Registers the level being looked at with egui, in place of the one before it. Runs
+    // before the UI is drawn, which can only use textures egui already knows about.
+    pub fn prepare(&mut self, device: &wgpu::Device, egui_renderer: &mut EguiRenderer, textures: &[Texture]) {
+        let Some((index, texture)) = self.selected.and_then(|i| Some((i, textures.get(i)?))) else { return };
+        let key = (index, self.mip.min(texture.texture.mip_level_count() - 1));
+        if self.registered.is_some_and(|(registered, _)| registered == key) {
+            return;
+        }
+        if let Some((_, id)) = self.registered.take() {
+            egui_renderer.free_native_texture(id);
+        }
+        let view = texture.mip_view(key.1);
+        self.registered = Some((key, egui_renderer.register_native_texture(device, &view, wgpu::FilterMode::Nearest)));
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, textures: &[Texture]) {
+        if textures.is_empty() {
+            ui.label("No textures loaded");
+            return;
+        }
+        let selected_name = self.selected.and_then(|i| textures.get(i)).map_or("None", |t| t.name.as_str());
+        egui::ComboBox::from_label("Texture")
+            .selected_text(selected_name)
+            .show_ui(ui, |ui| {
+                for (i, texture) in textures.iter().enumerate() {
+                    ui.selectable_value(&mut self.selected, Some(i), &texture.name);
+                }
+            });
+        let Some(index) = self.selected.filter(|i| *i < textures.len()) else { return };
+        let texture = &textures[index].texture;
+
+        let levels = texture.mip_level_count();
+        self.mip = self.mip.min(levels - 1);
+        let (width, height) = ((texture.width() >> self.mip).max(1), (texture.height() >> self.mip).max(1));
+        ui.label(format!(
+            "{} x {}, {} mip levels, {:?}",
+            texture.width(),
+            texture.height(),
+            levels,
+            texture.format()
+        ));
+        ui.add_enabled(levels > 1, egui::Slider::new(&mut self.mip, 0..=levels - 1).text("Mip level"));
+        ui.label(format!("Level size: {} x {}", width, height));
+
+        // Registered at the start of the next frame when the selection just changed
+        match self.registered.filter(|(key, _)| *key == (index, self.mip)) {
+            Some((_, id)) => {
+                let scale = (ui.available_width() / width as f32).min(256.0 / height as f32);
+                let size = egui::vec2(width as f32, height as f32) * scale;
+                ui.image(egui::load::SizedTexture::new(id, size));
+            }
+            None => {
+                ui.spinner();
+            }
+        }
+    }
+}
@@ -2,18 +2,25 @@
 
 use bytemuck::{Pod, Zeroable};
 use egui_wgpu::wgpu;
-use wgpu::util::DeviceExt;
+use glam::Vec3;
+
+// Lets meshes keep a CPU copy of their positions for bounds and picking
+pub trait MeshVertex: Pod {
+    fn position(&self) -> Vec3;
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub color: [f32; 3],
+    pub normal: [f32; 3], // Zero for lines, which aren't shaded
+    pub uv: [f32; 2],
 }
 
 impl Vertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+    const ATTRIBS: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x3, 3 => Float32x2];
 
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
@@ -26,62 +33,161 @@ impl Vertex {
     }
 
     pub fn new(position: [f32; 3], color: [f32; 3]) -> Self {
-        Self { position, color }
+        Self { position, color, normal: [0.0; 3], uv: [0.0; 2] }
+    }
+
+    pub fn with_normal(self, normal: [f32; 3]) -> Self {
+        Self { normal, ..self }
+    }
+
+    pub fn with_uv(self, uv: [f32; 2]) -> Self {
+        Self { uv, ..self }
     }
 
-    pub fn generate_cube() -> (Vec<Vertex>, Vec<u16>) {
-        // Define the static vertices of a cube
-        let vertices = vec![
-            // Front face
-            Vertex::new([-0.5, -0.5,  0.5], [1.0, 0.0, 0.0]),  // Bottom-left
-            Vertex::new([ 0.5, -0.5,  0.5], [0.0, 1.0, 0.0]),  // Bottom-right
-            Vertex::new([ 0.5,  0.5,  0.5], [0.0, 0.0, 1.0]),  // Top-right
-            Vertex::new([-0.5,  0.5,  0.5], [1.0, 1.0, 0.0]),  // Top-left
-            // Back face
-            Vertex::new([-0.5, -0.5, -0.5], [1.0, 0.0, 1.0]),  // Bottom-left
-            Vertex::new([ 0.5, -0.5, -0.5], [0.0, 1.0, 1.0]),  // Bottom-right
-            Vertex::new([ 0.5,  0.5, -0.5], [1.0, 1.0, 1.0]),  // Top-right
-            Vertex::new([-0.5,  0.5, -0.5], [0.5, 0.5, 0.5]),  // Top-left
+    pub fn generate_cube() -> (Vec<Vertex>, Vec<u32>) {
+        // Colors of the eight corners, shared by the faces that meet there
+        let corners = [
+            ([-0.5, -0.5,  0.5], [1.0, 0.0, 0.0]),
+            ([ 0.5, -0.5,  0.5], [0.0, 1.0, 0.0]),
+            ([ 0.5,  0.5,  0.5], [0.0, 0.0, 1.0]),
+            ([-0.5,  0.5,  0.5], [1.0, 1.0, 0.0]),
+            ([-0.5, -0.5, -0.5], [1.0, 0.0, 1.0]),
+            ([ 0.5, -0.5, -0.5], [0.0, 1.0, 1.0]),
+            ([ 0.5,  0.5, -0.5], [1.0, 1.0, 1.0]),
+            ([-0.5,  0.5, -0.5], [0.5, 0.5, 0.5]),
         ];
 
-        let indices = vec![
-            // Front face
-            0, 1, 2, 0, 2, 3,
-            // Back face
-            4, 5, 6, 4, 6, 7,
-            // Left face
-            4, 0, 3, 4, 3, 7,
-            // Right face
-            1, 5, 6, 1, 6, 2,
-            // Top face
-            3, 2, 6, 3, 6, 7,
-            // Bottom face
-            4, 5, 1, 4, 1, 0,
+        // Each face gets its own four vertices so that it can have a flat normal and the
+        // whole texture, corners listed counterclockwise from the bottom left
+        let faces = [
+            ([0, 1, 2, 3], [ 0.0,  0.0,  1.0]), // Front
+            ([5, 4, 7, 6], [ 0.0,  0.0, -1.0]), // Back
+            ([4, 0, 3, 7], [-1.0,  0.0,  0.0]), // Left
+            ([1, 5, 6, 2], [ 1.0,  0.0,  0.0]), // Right
+            ([3, 2, 6, 7], [ 0.0,  1.0,  0.0]), // Top
+            ([4, 5, 1, 0], [ 0.0, -1.0,  0.0]), // Bottom
         ];
 
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (face, normal) in faces {
+            let base = vertices.len() as u32;
+            for (corner, uv) in face.into_iter().zip([[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]]) {
+                let (position, color) = corners[corner];
+                vertices.push(Vertex::new(position, color).with_normal(normal).with_uv(uv));
+            }
+            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
         (vertices, indices)
     }
 
-    pub fn generate_polygon(sides: u16, radius: f32) -> (Vec<Vertex>, Vec<u16>) {
+    pub fn generate_polygon(sides: u16, radius: f32) -> (Vec<Vertex>, Vec<u32>) {
         let mut vertices = Vec::new();
         let mut indices = Vec::new();
         let angle_step = 2.0 * std::f32::consts::PI / sides as f32;
 
-        vertices.push(Vertex::new([0.0, 0.0, 0.0], [0.5, 0.0, 0.5]));  // Center vertex
+        let vertex = |x: f32, y: f32| {
+            Vertex::new([x, y, 0.0], [0.5, 0.0, 0.5])  // Adjust color as needed
+                .with_normal([0.0, 0.0, 1.0])
+                .with_uv([0.5 + x / (2.0 * radius), 0.5 - y / (2.0 * radius)])
+        };
+        vertices.push(vertex(0.0, 0.0));  // Center vertex
 
         for i in 0..sides {
             let angle = i as f32 * angle_step;
             let x = radius * angle.cos();
             let y = radius * angle.sin();
-            vertices.push(Vertex::new([x, y, 0.0], [0.5, 0.0, 0.5]));  // Adjust color as needed
+            vertices.push(vertex(x, y));
         }
 
-        for i in 0..sides {
+        for i in 0..sides as u32 {
             indices.push(0);  
             indices.push(i + 1);  
-            indices.push((i + 1) % sides + 1);  
+            indices.push((i + 1) % sides as u32 + 1);  
+        }
+
+        (vertices, indices)
+    }
+
+    // White UV sphere. Texture u runs around the equator, v from the top pole to the bottom one.
+    pub fn generate_sphere(segments: u32, rings: u32, radius: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let (segments, rings) = (segments.max(3), rings.max(2));
+        let mut vertices = Vec::with_capacity(((segments + 1) * (rings + 1)) as usize);
+        let mut indices = Vec::with_capacity((segments * rings * 6) as usize);
+
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let theta = v * std::f32::consts::PI;
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let phi = u * 2.0 * std::f32::consts::PI;
+                let normal = [theta.sin() * phi.cos(), theta.cos(), -theta.sin() * phi.sin()];
+                vertices.push(
+                    Vertex::new(normal.map(|c| c * radius), [1.0, 1.0, 1.0])
+                        .with_normal(normal)
+                        .with_uv([u, v]),
+                );
+            }
+        }
+
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = ring * (segments + 1) + segment;
+                let b = a + segments + 1;
+                indices.extend([a, b, a + 1, a + 1, b, b + 1]);
+            }
         }
 
         (vertices, indices)
     }
 }
+
+impl MeshVertex for Vertex {
+    fn position(&self) -> Vec3 {
+        Vec3::from(self.position)
+    }
+}
+
+// Vertex emitted by the voxel mesher. Positions are local to the chunk.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct VoxelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 3], // Tint multiplied with the texture
+    pub ao: f32,         // 0 = fully occluded corner, 1 = open
+    pub uv: [f32; 2],    // In voxels, so textures repeat once per voxel across merged faces
+    pub layer: u32,      // Layer of the block texture array
+    pub emissive: u32,   // Non-zero for faces that aren't shaded
+    pub light: [f32; 2], // Sky and block light, 0 to 1
+}
+
+impl VoxelVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 8] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x3,
+        3 => Float32,
+        4 => Float32x2,
+        5 => Uint32,
+        6 => Uint32,
+        7 => Float32x2
+    ];
+
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+impl MeshVertex for VoxelVertex {
+    fn position(&self) -> Vec3 {
+        Vec3::from(self.position)
+    }
+}
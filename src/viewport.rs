@@ -0,0 +1,304 @@
+// viewport.rs
+
+use crate::camera::Camera;
+use crate::egui_tools::EguiRenderer;
+use crate::renderer::{SceneRenderer, ViewCulling};
+use egui_wgpu::wgpu;
+use glam::{Vec2, Vec3};
+
+// Color and depth the scene is drawn into, shown by egui as an image
+pub struct RenderTarget {
+    pub view: wgpu::TextureView,
+    pub depth_view: wgpu::TextureView,
+    pub size: [u32; 2],
+    egui_id: egui::TextureId,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        egui_renderer: &mut EguiRenderer,
+        format: wgpu::TextureFormat,
+        size: [u32; 2],
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Viewport Texture"),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let egui_id = egui_renderer.register_native_texture(device, &view, wgpu::FilterMode::Linear);
+        Self {
+            depth_view: SceneRenderer::create_depth_view(device, size[0], size[1]),
+            view,
+            size,
+            egui_id,
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.size[0] as f32 / self.size[1] as f32
+    }
+
+    pub fn free(self, egui_renderer: &mut EguiRenderer) {
+        egui_renderer.free_native_texture(self.egui_id);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViewKind {
+    Perspective,
+    Top,
+    Front,
+    Side,
+}
+
+impl ViewKind {
+    pub const ALL: [ViewKind; 4] = [ViewKind::Perspective, ViewKind::Top, ViewKind::Front, ViewKind::Side];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewKind::Perspective => "Perspective",
+            ViewKind::Top => "Top",
+            ViewKind::Front => "Front",
+            ViewKind::Side => "Side",
+        }
+    }
+
+    // Orthographic views look down the Y axis with -Z up, down -Z and down -X. The
+    // perspective view has no camera of its own.
+    fn camera(self, center: Vec3, half_height: f32) -> Option<Camera> {
+        let (direction, up) = match self {
+            ViewKind::Perspective => return None,
+            ViewKind::Top => (Vec3::NEG_Y, Vec3::NEG_Z),
+            ViewKind::Front => (Vec3::NEG_Z, Vec3::Y),
+            ViewKind::Side => (Vec3::NEG_X, Vec3::Y),
+        };
+        let mut camera = Camera::orthographic(center - direction * 20.0, center, up, half_height);
+        camera.speed = 0.5;
+        Some(camera)
+    }
+}
+
+// One panel the scene is drawn in. The render target follows the size of the panel.
+pub struct SceneViewport {
+    pub kind: ViewKind,
+    pub camera: Option<Camera>, // None follows the main camera
+    pub culling: ViewCulling,
+    target: Option<RenderTarget>,
+    size: [u32; 2], // Wanted by the panel last frame, in pixels
+    rect: egui::Rect, // Where the image was drawn, in window pixels
+    hovered: bool,
+}
+
+impl SceneViewport {
+    pub fn new(kind: ViewKind, center: Vec3) -> Self {
+        Self {
+            kind,
+            camera: kind.camera(center, 8.0),
+            culling: ViewCulling::default(),
+            target: None,
+            size: [0, 0],
+            rect: egui::Rect::NOTHING,
+            hovered: false,
+        }
+    }
+
+    // Makes the target match the panel, or drops it when the panel isn't shown
+    fn prepare(&mut self, device: &wgpu::Device, egui_renderer: &mut EguiRenderer, format: wgpu::TextureFormat, shown: bool) {
+        let wanted = shown && self.size[0] > 0 && self.size[1] > 0;
+        if wanted && self.target.as_ref().is_some_and(|target| target.size == self.size) {
+            return;
+        }
+        if let Some(target) = self.target.take() {
+            target.free(egui_renderer);
+        }
+        if wanted {
+            self.target = Some(RenderTarget::new(device, egui_renderer, format, self.size));
+        }
+    }
+
+    pub fn camera<'a>(&'a self, main: &'a Camera) -> &'a Camera {
+        self.camera.as_ref().unwrap_or(main)
+    }
+
+    // Fills the space left in the panel with the scene, named in the corner
+    fn ui(&mut self, ui: &mut egui::Ui, focused: bool) -> egui::Response {
+        let size = ui.available_size();
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        self.size = [
+            (size.x * pixels_per_point).round().max(1.0) as u32,
+            (size.y * pixels_per_point).round().max(1.0) as u32,
+        ];
+        let response = match &self.target {
+            Some(target) => ui.add(
+                egui::Image::new(egui::load::SizedTexture::new(target.egui_id, size)).sense(egui::Sense::click_and_drag()),
+            ),
+            None => ui.allocate_response(size, egui::Sense::click_and_drag()),
+        };
+        self.rect = egui::Rect::from_min_max(
+            (response.rect.min.to_vec2() * pixels_per_point).to_pos2(),
+            (response.rect.max.to_vec2() * pixels_per_point).to_pos2(),
+        );
+        self.hovered = response.hovered();
+
+        let painter = ui.painter_at(response.rect);
+        painter.text(
+            response.rect.left_top() + egui::vec2(6.0, 4.0),
+            egui::Align2::LEFT_TOP,
+            self.kind.name(),
+            egui::FontId::proportional(14.0),
+            egui::Color32::WHITE,
+        );
+        if focused {
+            painter.rect_stroke(response.rect.shrink(1.0), 0.0, ui.visuals().selection.stroke);
+        }
+        response
+    }
+}
+
+// Draws the scene inside an egui panel instead of behind the UI, either one perspective view
+// or four views like a modelling tool. Keys and clicks go to the view under the cursor.
+pub struct ViewportLayout {
+    pub enabled: bool,
+    pub four_up: bool,
+    views: Vec<SceneViewport>,
+    focused: usize,
+}
+
+impl ViewportLayout {
+    pub fn new(center: Vec3) -> Self {
+        Self {
+            enabled: true,
+            four_up: false,
+            views: ViewKind::ALL.iter().map(|kind| SceneViewport::new(*kind, center)).collect(),
+            focused: 0,
+        }
+    }
+
+    fn shown_count(&self) -> usize {
+        match (self.enabled, self.four_up) {
+            (false, _) => 0,
+            (true, false) => 1,
+            (true, true) => self.views.len(),
+        }
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, egui_renderer: &mut EguiRenderer, format: wgpu::TextureFormat) {
+        let shown = self.shown_count();
+        for (i, view) in self.views.iter_mut().enumerate() {
+            view.prepare(device, egui_renderer, format, i < shown);
+        }
+        if self.focused >= shown {
+            self.focused = 0;
+        }
+    }
+
+    // False means the scene goes to the window
+    pub fn has_targets(&self) -> bool {
+        self.views[..self.shown_count()].iter().any(|view| view.target.is_some())
+    }
+
+    // Shown views that have a target to draw into, with their camera and culling state
+    pub fn targets<'a>(
+        &'a mut self,
+        main: &'a Camera,
+    ) -> impl Iterator<Item = (&'a RenderTarget, &'a Camera, &'a mut ViewCulling)> {
+        let shown = self.shown_count();
+        self.views[..shown].iter_mut().filter_map(move |view| {
+            let target = view.target.as_ref()?;
+            Some((target, view.camera.as_ref().unwrap_or(main), &mut view.culling))
+        })
+    }
+
+    // Culling state of the main camera when the scene is drawn to the window
+    pub fn window_culling(&mut self) -> &mut ViewCulling {
+        &mut self.views[0].culling
+    }
+
+    // Culling state of each view drawn from, by name
+    pub fn cullings(&self) -> impl Iterator<Item = (&str, &ViewCulling)> {
+        self.views[..self.shown_count().max(1)].iter().map(|view| (view.kind.name(), &view.culling))
+    }
+
+    fn focused_view(&self) -> Option<&SceneViewport> {
+        let view = self.views.get(self.focused).filter(|_| self.focused < self.shown_count())?;
+        view.target.is_some().then_some(view)
+    }
+
+    // Whether mouse input belongs to the scene rather than the UI. `over_ui` is egui's
+    // answer for when the scene fills the window.
+    pub fn takes_pointer(&self, over_ui: bool) -> bool {
+        match self.focused_view() {
+            Some(view) => view.hovered,
+            None => !over_ui,
+        }
+    }
+
+    // The camera of the focused view, and a cursor position in the window relative to the
+    // view along with the size of the view, in pixels
+    pub fn pointer<'a>(&'a self, main: &'a Camera, cursor: Vec2, window_size: Vec2) -> (&'a Camera, Vec2, Vec2) {
+        match self.focused_view() {
+            Some(view) => (
+                view.camera(main),
+                cursor - Vec2::new(view.rect.min.x, view.rect.min.y),
+                Vec2::new(view.rect.width(), view.rect.height()),
+            ),
+            None => (main, cursor, window_size),
+        }
+    }
+
+    // The camera keyboard movement applies to
+    pub fn focused_camera<'a>(&'a mut self, main: &'a mut Camera) -> &'a mut Camera {
+        match self.focused < self.shown_count() {
+            true => self.views[self.focused].camera.as_mut().unwrap_or(main),
+            false => main,
+        }
+    }
+
+    // Lays the views out in the space left in the panel, one or a 2x2 grid
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let shown = self.shown_count();
+        let rect = ui.available_rect_before_wrap();
+        let columns = if shown > 1 { 2 } else { 1 };
+        let rows = shown.div_ceil(columns).max(1);
+        let gap = 2.0;
+        let cell = egui::vec2(
+            (rect.width() - gap * (columns - 1) as f32) / columns as f32,
+            (rect.height() - gap * (rows - 1) as f32) / rows as f32,
+        );
+        // Focus follows the pointer, except while a button is held so drags stay in their view
+        let pointer_down = ui.input(|i| i.pointer.any_down());
+        for (i, view) in self.views[..shown].iter_mut().enumerate() {
+            let offset = egui::vec2((i % columns) as f32, (i / columns) as f32) * (cell + egui::vec2(gap, gap));
+            let cell_rect = egui::Rect::from_min_size(rect.min + offset, cell);
+            let response = ui.allocate_ui_at_rect(cell_rect, |ui| view.ui(ui, i == self.focused)).inner;
+            if response.hovered() && !pointer_down {
+                self.focused = i;
+            }
+        }
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui, center: Vec3) {
+        ui.checkbox(&mut self.enabled, "Draw the scene in a panel");
+        ui.add_enabled_ui(self.enabled, |ui| {
+            ui.checkbox(&mut self.four_up, "Top, front and side views");
+            if ui.button("Center views on the camera target").clicked() {
+                for view in &mut self.views {
+                    if let Some(camera) = view.camera {
+                        view.camera = view.kind.camera(center, camera.ortho.unwrap_or(8.0));
+                    }
+                }
+            }
+        });
+    }
+}
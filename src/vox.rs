@@ -0,0 +1,739 @@
+// vox.rs
+
+use crate::blocks::{block_color, linear_to_srgb, srgb_to_linear, BlockRegistry};
+use crate::mesher::{self, MeshingMode, PaddedChunk};
+use crate::vertex::Vertex;
+use crate::voxel::{BlockId, VoxelWorld, AIR, CHUNK_SIZE};
+use glam::IVec3;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+// MagicaVoxel limits models to 256 voxels per side
+const MAX_MODEL_SIZE: i32 = 256;
+const VERSION: i32 = 150;
+// Newest version read; every version since 150 keeps the same chunk layout
+const MAX_VERSION: i32 = 200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxModel {
+    pub size: IVec3,
+    pub voxels: Vec<[u8; 4]>, // x, y, z and color index
+}
+
+// Axis-aligned rotation and translation in MagicaVoxel's z-up space
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VoxTransform {
+    pub rows: [IVec3; 3],
+    pub translation: IVec3,
+}
+
+impl VoxTransform {
+    pub const IDENTITY: Self = Self {
+        rows: [IVec3::X, IVec3::Y, IVec3::Z],
+        translation: IVec3::ZERO,
+    };
+
+    pub fn apply(&self, v: IVec3) -> IVec3 {
+        IVec3::new(self.rows[0].dot(v), self.rows[1].dot(v), self.rows[2].dot(v)) + self.translation
+    }
+
+    // Applies `child` first, then `self`
+    fn then(&self, child: &Self) -> Self {
+        let column = |i: usize| IVec3::new(child.rows[0][i], child.rows[1][i], child.rows[2][i]);
+        let rows = self.rows.map(|row| IVec3::new(row.dot(column(0)), row.dot(column(1)), row.dot(column(2))));
+        Self {
+            rows,
+            translation: self.apply(child.translation),
+        }
+    }
+
+    // The `_r` attribute: bits 0-1 and 2-3 hold the column of the non-zero entry in the first
+    // two rows, bits 4-6 are set where a row's entry is negative
+    fn from_packed_rotation(packed: u8) -> Option<Self> {
+        let first = (packed & 3) as usize;
+        let second = ((packed >> 2) & 3) as usize;
+        if first > 2 || second > 2 || first == second {
+            return None;
+        }
+        let third = 3 - first - second;
+        let mut rows = [IVec3::ZERO; 3];
+        for (row, column) in [first, second, third].into_iter().enumerate() {
+            rows[row][column] = if packed & (1 << (4 + row)) != 0 { -1 } else { 1 };
+        }
+        Some(Self {
+            rows,
+            translation: IVec3::ZERO,
+        })
+    }
+
+    fn packed_rotation(&self) -> u8 {
+        let mut packed = 0;
+        for (row, values) in self.rows.iter().enumerate() {
+            let column = (0..3).find(|c| values[*c] != 0).unwrap_or(row);
+            if row < 2 {
+                packed |= (column as u8) << (row * 2);
+            }
+            if values[column] < 0 {
+                packed |= 1 << (4 + row);
+            }
+        }
+        packed
+    }
+}
+
+// One placement of a model in the scene
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VoxInstance {
+    pub model: usize,
+    pub transform: VoxTransform,
+}
+
+// Contents of a `.vox` file. Color indices double as block ids when moving voxels
+// in and out of the world.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    pub palette: Option<Box<[[u8; 4]; 256]>>, // Entry i holds color index i + 1
+    pub instances: Vec<VoxInstance>,
+}
+
+enum SceneNode {
+    Transform { transform: VoxTransform, child: i32 },
+    Group(Vec<i32>),
+    Shape(Vec<usize>),
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if count > self.data.len() {
+            return Err(invalid_data("unexpected end of .vox data"));
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> io::Result<usize> {
+        usize::try_from(self.i32()?).map_err(|_| invalid_data("negative length in .vox data"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.len()?;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> io::Result<HashMap<String, String>> {
+        (0..self.len()?).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn i32(&mut self, value: i32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.i32(value.len() as i32);
+        self.data.extend_from_slice(value.as_bytes());
+    }
+
+    fn dict(&mut self, entries: &[(&str, String)]) {
+        self.i32(entries.len() as i32);
+        for (key, value) in entries {
+            self.string(key);
+            self.string(value);
+        }
+    }
+
+    // Chunk header followed by the content written by `content`, with no children
+    fn chunk(&mut self, id: &[u8; 4], content: impl FnOnce(&mut Writer)) {
+        let mut inner = Writer { data: Vec::new() };
+        content(&mut inner);
+        self.data.extend_from_slice(id);
+        self.i32(inner.data.len() as i32);
+        self.i32(0);
+        self.data.extend_from_slice(&inner.data);
+    }
+}
+
+// Converts between MagicaVoxel's z-up and our y-up coordinates, keeping handedness
+fn from_vox_space(v: IVec3) -> IVec3 {
+    IVec3::new(v.x, v.z, -v.y)
+}
+
+fn to_vox_space(v: IVec3) -> IVec3 {
+    IVec3::new(v.x, -v.z, v.y)
+}
+
+impl VoxFile {
+    pub fn read(data: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader { data };
+        if reader.bytes(4)? != b"VOX " {
+            return Err(invalid_data("not a MagicaVoxel file"));
+        }
+        let version = reader.i32()?;
+        if !(VERSION..=MAX_VERSION).contains(&version) {
+            return Err(invalid_data(&format!("unsupported .vox version {}", version)));
+        }
+
+        if reader.bytes(4)? != b"MAIN" {
+            return Err(invalid_data("missing MAIN chunk"));
+        }
+        let content_size = reader.len()?;
+        reader.len()?;
+        reader.bytes(content_size)?;
+
+        let mut models = Vec::new();
+        let mut size = None;
+        let mut palette = None;
+        let mut nodes = HashMap::new();
+
+        // All other chunks are children of MAIN and have no children of their own worth reading
+        while !reader.data.is_empty() {
+            let id: [u8; 4] = reader.bytes(4)?.try_into().unwrap();
+            let content_size = reader.len()?;
+            let children_size = reader.len()?;
+            let mut content = Reader {
+                data: reader.bytes(content_size)?,
+            };
+            reader.bytes(children_size)?;
+
+            match &id {
+                b"SIZE" => size = Some(IVec3::new(content.i32()?, content.i32()?, content.i32()?)),
+                b"XYZI" => {
+                    let size = size.take().ok_or_else(|| invalid_data("XYZI chunk without a SIZE chunk"))?;
+                    let count = content.len()?;
+                    let voxels = content
+                        .bytes(count * 4)?
+                        .chunks_exact(4)
+                        .map(|v| [v[0], v[1], v[2], v[3]])
+                        .collect();
+                    models.push(VoxModel { size, voxels });
+                }
+                b"RGBA" => {
+                    let mut colors = Box::new([[0; 4]; 256]);
+                    for (color, bytes) in colors.iter_mut().zip(content.bytes(256 * 4)?.chunks_exact(4)) {
+                        color.copy_from_slice(bytes);
+                    }
+                    palette = Some(colors);
+                }
+                b"nTRN" => {
+                    let node = content.i32()?;
+                    content.dict()?;
+                    let child = content.i32()?;
+                    content.i32()?; // Reserved
+                    content.i32()?; // Layer
+                    let frames = content.len()?;
+                    let mut transform = VoxTransform::IDENTITY;
+                    // Only the first animation frame is used
+                    if frames > 0 {
+                        let attributes = content.dict()?;
+                        if let Some(r) = attributes.get("_r") {
+                            transform = r
+                                .trim()
+                                .parse()
+                                .ok()
+                                .and_then(VoxTransform::from_packed_rotation)
+                                .ok_or_else(|| invalid_data("invalid rotation"))?;
+                        }
+                        if let Some(t) = attributes.get("_t") {
+                            let values: Vec<i32> = t
+                                .split_whitespace()
+                                .map(|v| v.parse().map_err(|_| invalid_data("invalid translation")))
+                                .collect::<io::Result<_>>()?;
+                            if values.len() != 3 {
+                                return Err(invalid_data("invalid translation"));
+                            }
+                            transform.translation = IVec3::new(values[0], values[1], values[2]);
+                        }
+                    }
+                    nodes.insert(node, SceneNode::Transform { transform, child });
+                }
+                b"nGRP" => {
+                    let node = content.i32()?;
+                    content.dict()?;
+                    let children = (0..content.len()?).map(|_| content.i32()).collect::<io::Result<_>>()?;
+                    nodes.insert(node, SceneNode::Group(children));
+                }
+                b"nSHP" => {
+                    let node = content.i32()?;
+                    content.dict()?;
+                    let mut shape_models = Vec::new();
+                    for _ in 0..content.len()? {
+                        shape_models.push(content.len()?);
+                        content.dict()?;
+                    }
+                    nodes.insert(node, SceneNode::Shape(shape_models));
+                }
+                _ => {}
+            }
+        }
+
+        let mut instances = Vec::new();
+        if nodes.contains_key(&0) {
+            collect_instances(&nodes, 0, VoxTransform::IDENTITY, 0, &mut instances)?;
+        } else {
+            // Without a scene graph every model sits at the origin
+            for (model, data) in models.iter().enumerate() {
+                let transform = VoxTransform {
+                    translation: data.size / 2,
+                    ..VoxTransform::IDENTITY
+                };
+                instances.push(VoxInstance { model, transform });
+            }
+        }
+        if instances.iter().any(|i| i.model >= models.len()) {
+            return Err(invalid_data("shape refers to a missing model"));
+        }
+
+        Ok(Self {
+            models,
+            palette,
+            instances,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&std::fs::read(path)?)
+    }
+
+    // Models followed by a scene graph of one group holding a transform and shape per instance
+    pub fn write(&self) -> Vec<u8> {
+        let mut children = Writer { data: Vec::new() };
+        for model in &self.models {
+            children.chunk(b"SIZE", |w| {
+                w.i32(model.size.x);
+                w.i32(model.size.y);
+                w.i32(model.size.z);
+            });
+            children.chunk(b"XYZI", |w| {
+                w.i32(model.voxels.len() as i32);
+                for voxel in &model.voxels {
+                    w.data.extend_from_slice(voxel);
+                }
+            });
+        }
+
+        children.chunk(b"nTRN", |w| {
+            w.i32(0);
+            w.dict(&[]);
+            w.i32(1);
+            w.i32(-1);
+            w.i32(-1);
+            w.i32(1);
+            w.dict(&[]);
+        });
+        children.chunk(b"nGRP", |w| {
+            w.i32(1);
+            w.dict(&[]);
+            w.i32(self.instances.len() as i32);
+            for i in 0..self.instances.len() {
+                w.i32(2 + 2 * i as i32);
+            }
+        });
+        for (i, instance) in self.instances.iter().enumerate() {
+            let node = 2 + 2 * i as i32;
+            children.chunk(b"nTRN", |w| {
+                w.i32(node);
+                w.dict(&[]);
+                w.i32(node + 1);
+                w.i32(-1);
+                w.i32(0);
+                w.i32(1);
+                let t = instance.transform.translation;
+                let mut attributes = vec![("_t", format!("{} {} {}", t.x, t.y, t.z))];
+                if instance.transform.rows != VoxTransform::IDENTITY.rows {
+                    attributes.push(("_r", instance.transform.packed_rotation().to_string()));
+                }
+                w.dict(&attributes);
+            });
+            children.chunk(b"nSHP", |w| {
+                w.i32(node + 1);
+                w.dict(&[]);
+                w.i32(1);
+                w.i32(instance.model as i32);
+                w.dict(&[]);
+            });
+        }
+
+        if let Some(palette) = &self.palette {
+            children.chunk(b"RGBA", |w| {
+                for color in palette.iter() {
+                    w.data.extend_from_slice(color);
+                }
+            });
+        }
+
+        let mut file = Writer { data: b"VOX ".to_vec() };
+        file.i32(VERSION);
+        file.data.extend_from_slice(b"MAIN");
+        file.i32(0);
+        file.i32(children.data.len() as i32);
+        file.data.extend_from_slice(&children.data);
+        file.data
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.write())
+    }
+
+    // Every voxel of every instance in world space (y up), with its color index
+    pub fn voxels(&self) -> Vec<(IVec3, u8)> {
+        let mut voxels = Vec::new();
+        for instance in &self.instances {
+            let model = &self.models[instance.model];
+            let pivot = model.size / 2;
+            for [x, y, z, color] in &model.voxels {
+                let local = IVec3::new(*x as i32, *y as i32, *z as i32) - pivot;
+                voxels.push((from_vox_space(instance.transform.apply(local)), *color));
+            }
+        }
+        voxels
+    }
+
+    // Splits every chunk of the world into models of at most 256 voxels per side. Blocks
+    // are written as the color index of the same number, colored like in the world.
+    pub fn from_world(world: &VoxelWorld, blocks: &BlockRegistry) -> io::Result<Self> {
+        let mut tiles: HashMap<IVec3, Vec<(IVec3, u8)>> = HashMap::new();
+        for (coord, chunk) in world.chunks() {
+            for (i, block) in chunk.to_blocks().into_iter().enumerate() {
+                if block == AIR {
+                    continue;
+                }
+                let color = u8::try_from(block)
+                    .map_err(|_| invalid_data(&format!("block {} doesn't fit in a .vox palette", block)))?;
+                let (x, z, y) = (i % CHUNK_SIZE, (i / CHUNK_SIZE) % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE));
+                let pos = to_vox_space(coord * CHUNK_SIZE as i32 + IVec3::new(x as i32, y as i32, z as i32));
+                tiles
+                    .entry(pos.div_euclid(IVec3::splat(MAX_MODEL_SIZE)))
+                    .or_default()
+                    .push((pos, color));
+            }
+        }
+
+        let mut tiles: Vec<_> = tiles.into_values().collect();
+        tiles.sort_by_key(|voxels| voxels[0].0.to_array());
+
+        let mut file = Self {
+            models: Vec::new(),
+            palette: Some(Box::new(std::array::from_fn(|i| {
+                let color = blocks.preview_color(i as BlockId + 1);
+                let [r, g, b] = color.map(|c| (linear_to_srgb(c) * 255.0).round() as u8);
+                [r, g, b, 255]
+            }))),
+            instances: Vec::new(),
+        };
+        for voxels in tiles {
+            let min = voxels.iter().fold(IVec3::MAX, |min, (pos, _)| min.min(*pos));
+            let max = voxels.iter().fold(IVec3::MIN, |max, (pos, _)| max.max(*pos));
+            let size = max - min + IVec3::ONE;
+            let voxels = voxels
+                .into_iter()
+                .map(|(pos, color)| {
+                    let local = pos - min;
+                    [local.x as u8, local.y as u8, local.z as u8, color]
+                })
+                .collect();
+            file.instances.push(VoxInstance {
+                model: file.models.len(),
+                transform: VoxTransform {
+                    translation: min + size / 2,
+                    ..VoxTransform::IDENTITY
+                },
+            });
+            file.models.push(VoxModel { size, voxels });
+        }
+        Ok(file)
+    }
+
+    // Writes the voxels into the world with the lowest corner of the scene at `origin`
+    pub fn insert_into(&self, world: &mut VoxelWorld, origin: IVec3) {
+        let voxels = self.voxels();
+        let min = voxels.iter().fold(IVec3::MAX, |min, (pos, _)| min.min(*pos));
+        for (pos, color) in voxels {
+            world.set_voxel(origin + pos - min, color as BlockId);
+        }
+    }
+
+    // Vertex color for a color index, from the file's palette if it has one
+    pub fn color(&self, index: u8) -> [f32; 3] {
+        match &self.palette {
+            Some(palette) if index > 0 => {
+                let [r, g, b, _] = palette[index as usize - 1];
+                [r, g, b].map(|c| srgb_to_linear(c as f32 / 255.0))
+            }
+            _ => block_color(index as BlockId),
+        }
+    }
+
+    // Greedy meshed geometry of the whole scene with ambient occlusion baked into the colors,
+    // standing on the origin and centered on it horizontally
+    pub fn mesh(&self) -> (Vec<Vertex>, Vec<u32>) {
+        let voxels = self.voxels();
+        if voxels.is_empty() {
+            return (Vec::new(), Vec::new());
+        }
+        let min = voxels.iter().fold(IVec3::MAX, |min, (pos, _)| min.min(*pos));
+        let max = voxels.iter().fold(IVec3::MIN, |max, (pos, _)| max.max(*pos));
+        let mut world = VoxelWorld::new();
+        for (pos, color) in voxels {
+            world.set_voxel(pos - min, color as BlockId);
+        }
+
+        let colors = BlockRegistry::untextured((1..=255).map(|i| (i as BlockId, self.color(i))));
+        let center = (max - min + IVec3::ONE).as_vec3() * glam::Vec3::new(0.5, 0.0, 0.5);
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+        for (coord, _) in world.chunks() {
+            let data = mesher::mesh_chunk(&PaddedChunk::from_world(&world, coord), MeshingMode::Greedy, &colors);
+            let base = vertices.len() as u32;
+            let offset = (coord * CHUNK_SIZE as i32).as_vec3() - center;
+            vertices.extend(data.vertices.iter().map(|v| {
+                let shade = 0.45 + 0.55 * v.ao;
+                Vertex::new(
+                    (glam::Vec3::from(v.position) + offset).to_array(),
+                    v.color.map(|c| c * shade),
+                )
+                .with_normal(v.normal)
+            }));
+            indices.extend(data.indices.iter().map(|i| base + i));
+        }
+        (vertices, indices)
+    }
+}
+
+fn collect_instances(
+    nodes: &HashMap<i32, SceneNode>,
+    id: i32,
+    parent: VoxTransform,
+    depth: usize,
+    instances: &mut Vec<VoxInstance>,
+) -> io::Result<()> {
+    // Guards against cycles in malformed files
+    if depth > 64 {
+        return Err(invalid_data("scene graph is nested too deeply"));
+    }
+    match nodes.get(&id) {
+        Some(SceneNode::Transform { transform, child }) => {
+            collect_instances(nodes, *child, parent.then(transform), depth + 1, instances)?
+        }
+        Some(SceneNode::Group(children)) => {
+            for child in children {
+                collect_instances(nodes, *child, parent, depth + 1, instances)?;
+            }
+        }
+        Some(SceneNode::Shape(models)) => {
+            for model in models {
+                instances.push(VoxInstance {
+                    model: *model,
+                    transform: parent,
+                });
+            }
+        }
+        None => return Err(invalid_data(&format!("scene graph refers to missing node {}", id))),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_voxels(world: &VoxelWorld) -> Vec<(IVec3, BlockId)> {
+        let mut voxels = Vec::new();
+        for (coord, chunk) in world.chunks() {
+            for (i, block) in chunk.to_blocks().into_iter().enumerate() {
+                if block != AIR {
+                    let (x, z, y) = (i % CHUNK_SIZE, (i / CHUNK_SIZE) % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE));
+                    voxels.push((coord * CHUNK_SIZE as i32 + IVec3::new(x as i32, y as i32, z as i32), block));
+                }
+            }
+        }
+        voxels.sort_by_key(|(pos, _)| pos.to_array());
+        voxels
+    }
+
+    #[test]
+    fn world_round_trip() {
+        let blocks = BlockRegistry::builtin();
+        let mut world = VoxelWorld::new();
+        // Across chunk borders, and on both sides of the 256 voxel grid models are split on
+        let placed = [
+            (IVec3::new(-1, 0, 0), 1),
+            (IVec3::new(0, 0, 0), 2),
+            (IVec3::new(0, 1, 0), 3),
+            (IVec3::new(5, -3, 31), 4),
+            (IVec3::new(5, -3, 32), 5),
+            (IVec3::new(300, 2, -7), 200),
+        ];
+        for (pos, block) in placed {
+            world.set_voxel(pos, block);
+        }
+
+        let exported = VoxFile::from_world(&world, &blocks).unwrap();
+        assert_eq!(exported.models.len(), 4);
+        let imported = VoxFile::read(&exported.write()).unwrap();
+        assert_eq!(imported, exported);
+        let palette = imported.palette.as_ref().unwrap();
+        let [r, g, b] = blocks.preview_color(3).map(|c| (linear_to_srgb(c) * 255.0).round() as u8);
+        assert_eq!(palette[2], [r, g, b, 255]);
+
+        let mut copy = VoxelWorld::new();
+        let min = placed.iter().fold(IVec3::MAX, |min, (pos, _)| min.min(*pos));
+        imported.insert_into(&mut copy, min);
+        assert_eq!(world_voxels(&copy), world_voxels(&world));
+    }
+
+    // Scene graph: root transform -> group -> (transform -> shape) per entry of `shapes`
+    fn scene_file(shapes: &[(usize, &str, Option<u8>)]) -> Vec<u8> {
+        let mut children = Writer { data: Vec::new() };
+        for size in [IVec3::new(2, 1, 1), IVec3::new(1, 1, 3)] {
+            children.chunk(b"SIZE", |w| {
+                w.i32(size.x);
+                w.i32(size.y);
+                w.i32(size.z);
+            });
+            children.chunk(b"XYZI", |w| {
+                w.i32(2);
+                w.data.extend_from_slice(&[0, 0, 0, 1]);
+                w.data.extend_from_slice(&[(size.x - 1) as u8, 0, (size.z - 1) as u8, 2]);
+            });
+        }
+        children.chunk(b"nTRN", |w| {
+            w.i32(0);
+            w.dict(&[]);
+            w.i32(1);
+            w.i32(-1);
+            w.i32(-1);
+            w.i32(1);
+            w.dict(&[("_t", "100 0 0".to_string())]);
+        });
+        children.chunk(b"nGRP", |w| {
+            w.i32(1);
+            w.dict(&[]);
+            w.i32(shapes.len() as i32);
+            for i in 0..shapes.len() {
+                w.i32(2 + 2 * i as i32);
+            }
+        });
+        for (i, (model, translation, rotation)) in shapes.iter().enumerate() {
+            let node = 2 + 2 * i as i32;
+            children.chunk(b"nTRN", |w| {
+                w.i32(node);
+                w.dict(&[("_name", "part".to_string())]);
+                w.i32(node + 1);
+                w.i32(-1);
+                w.i32(0);
+                w.i32(1);
+                let mut attributes = vec![("_t", translation.to_string())];
+                if let Some(r) = rotation {
+                    attributes.push(("_r", r.to_string()));
+                }
+                w.dict(&attributes);
+            });
+            children.chunk(b"nSHP", |w| {
+                w.i32(node + 1);
+                w.dict(&[]);
+                w.i32(1);
+                w.i32(*model as i32);
+                w.dict(&[]);
+            });
+        }
+
+        let mut file = Writer { data: b"VOX ".to_vec() };
+        file.i32(MAX_VERSION);
+        file.data.extend_from_slice(b"MAIN");
+        file.i32(0);
+        file.i32(children.data.len() as i32);
+        file.data.extend_from_slice(&children.data);
+        file.data
+    }
+
+    #[test]
+    fn scene_graph_transforms() {
+        // 90 degrees around z with rows (0 1 0), (-1 0 0), (0 0 1): the first row's entry in
+        // column 1, the second's in column 0 and negative
+        let data = scene_file(&[(0, "10 0 0", None), (1, "-4 5 6", Some(1 | (1 << 5)))]);
+        let file = VoxFile::read(&data).unwrap();
+        assert_eq!(file.models.len(), 2);
+        assert_eq!(file.instances.len(), 2);
+        assert_eq!(file.instances[0].model, 0);
+        assert_eq!(file.instances[0].transform.translation, IVec3::new(110, 0, 0));
+        assert_eq!(file.instances[1].transform.translation, IVec3::new(96, 5, 6));
+        assert_eq!(file.instances[1].transform.rows, [IVec3::Y, IVec3::NEG_X, IVec3::Z]);
+
+        // Voxels relative to each model's center, in y-up space
+        let voxels = file.voxels();
+        assert_eq!(voxels[0], (from_vox_space(IVec3::new(109, 0, 0)), 1));
+        assert_eq!(voxels[1], (from_vox_space(IVec3::new(110, 0, 0)), 2));
+        // (0, 0, 2) - (0, 0, 1) rotated stays on z
+        assert_eq!(voxels[3], (from_vox_space(IVec3::new(96, 5, 7)), 2));
+
+        // Writing keeps the transforms
+        assert_eq!(VoxFile::read(&file.write()).unwrap().instances, file.instances);
+    }
+
+    #[test]
+    fn bad_files_are_rejected() {
+        let good = scene_file(&[(0, "0 0 0", None)]);
+        assert!(VoxFile::read(&good).is_ok());
+
+        let mut bad_magic = good.clone();
+        bad_magic[0..4].copy_from_slice(b"VOXX");
+        for version in [0i32, 149, 201] {
+            let mut bad_version = good.clone();
+            bad_version[4..8].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(VoxFile::read(&bad_version).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        assert_eq!(VoxFile::read(&bad_magic).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(VoxFile::read(&good[..good.len() - 3]).is_err());
+        assert!(VoxFile::read(&scene_file(&[(5, "0 0 0", None)])).is_err(), "shape of a missing model");
+        assert!(VoxFile::read(&scene_file(&[(0, "0 0", None)])).is_err(), "translation with two values");
+        assert!(VoxFile::read(&scene_file(&[(0, "0 0 0", Some(0))])).is_err(), "rotation with a repeated axis");
+    }
+
+    #[test]
+    fn files_without_voxels_mesh_to_nothing() {
+        let main = |children: Writer| {
+            let mut file = Writer { data: b"VOX ".to_vec() };
+            file.i32(MAX_VERSION);
+            file.data.extend_from_slice(b"MAIN");
+            file.i32(0);
+            file.i32(children.data.len() as i32);
+            file.data.extend_from_slice(&children.data);
+            file.data
+        };
+
+        let mut empty_model = Writer { data: Vec::new() };
+        empty_model.chunk(b"SIZE", |w| {
+            w.i32(4);
+            w.i32(4);
+            w.i32(4);
+        });
+        empty_model.chunk(b"XYZI", |w| w.i32(0));
+        let mut palette_only = Writer { data: Vec::new() };
+        palette_only.chunk(b"RGBA", |w| w.data.extend_from_slice(&[255; 256 * 4]));
+
+        for data in [main(empty_model), main(palette_only)] {
+            let (vertices, indices) = VoxFile::read(&data).unwrap().mesh();
+            assert!(vertices.is_empty());
+            assert!(indices.is_empty());
+        }
+    }
+}
@@ -0,0 +1,597 @@
+// voxel.rs
+
+use glam::IVec3;
+use std::collections::{HashMap, HashSet};
+
+pub const CHUNK_SIZE: usize = 32;
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+pub type BlockId = u16;
+pub const AIR: BlockId = 0;
+
+// Light of voxels that haven't been lit: full sky light and no block light. Light is
+// packed into a byte per voxel, sky light in the high nibble and block light in the low.
+pub const UNLIT: u8 = 0xf0;
+
+// Chunk coordinate containing a world voxel position
+pub fn chunk_coord(pos: IVec3) -> IVec3 {
+    pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32))
+}
+
+// Position of a world voxel inside its chunk
+pub fn local_coord(pos: IVec3) -> IVec3 {
+    pos.rem_euclid(IVec3::splat(CHUNK_SIZE as i32))
+}
+
+fn voxel_index(x: usize, y: usize, z: usize) -> usize {
+    debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE);
+    (y * CHUNK_SIZE + z) * CHUNK_SIZE + x
+}
+
+// Palette-compressed block storage. Each voxel stores an index into the palette using
+// 1, 2, 4, 8 or 16 bits, the fewest that fit the palette; a chunk made of a single block
+// type needs none. Indices never straddle two words, so each u64 holds `64 / bits` of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    palette: Vec<BlockId>,
+    counts: Vec<u32>, // Voxels using each palette entry; zero means the slot can be reused
+    bits: u32,
+    data: Vec<u64>,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::filled(AIR)
+    }
+}
+
+impl Chunk {
+    pub fn filled(block: BlockId) -> Self {
+        Self {
+            palette: vec![block],
+            counts: vec![CHUNK_VOLUME as u32],
+            bits: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockId {
+        self.palette[self.read_index(voxel_index(x, y, z))]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: BlockId) {
+        let index = voxel_index(x, y, z);
+        let old = self.read_index(index);
+        if self.palette[old] == block {
+            return;
+        }
+
+        let new = self.palette_slot(block);
+        self.counts[old] -= 1;
+        self.counts[new] += 1;
+        self.write_index(index, new);
+    }
+
+    // True if every voxel is air
+    pub fn is_empty(&self) -> bool {
+        self.palette
+            .iter()
+            .zip(&self.counts)
+            .all(|(block, count)| *block == AIR || *count == 0)
+    }
+
+    // Bits used per voxel by the packed storage
+    pub fn bits_per_voxel(&self) -> u32 {
+        self.bits
+    }
+
+    // Rebuilds the palette without unused entries, shrinking the storage when possible
+    pub fn compact(&mut self) {
+        if self.counts.iter().all(|c| *c > 0) {
+            return;
+        }
+        let blocks: Vec<BlockId> = (0..CHUNK_VOLUME)
+            .map(|i| self.palette[self.read_index(i)])
+            .collect();
+        *self = Self::from_blocks(&blocks);
+    }
+
+    // Builds a chunk from voxels in x-fastest, then z, then y order
+    pub fn from_blocks(blocks: &[BlockId]) -> Self {
+        assert_eq!(blocks.len(), CHUNK_VOLUME);
+        let mut chunk = Self::filled(blocks[0]);
+        for (i, block) in blocks.iter().enumerate() {
+            let (x, z, y) = (i % CHUNK_SIZE, (i / CHUNK_SIZE) % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE));
+            chunk.set(x, y, z, *block);
+        }
+        chunk
+    }
+
+    // All voxels in the same order accepted by `from_blocks`
+    pub fn to_blocks(&self) -> Vec<BlockId> {
+        (0..CHUNK_VOLUME).map(|i| self.palette[self.read_index(i)]).collect()
+    }
+
+    fn indices_per_word(bits: u32) -> usize {
+        (64 / bits) as usize
+    }
+
+    fn read_index(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = Self::indices_per_word(self.bits);
+        let word = self.data[index / per_word];
+        let shift = (index % per_word) as u32 * self.bits;
+        ((word >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn write_index(&mut self, index: usize, value: usize) {
+        let per_word = Self::indices_per_word(self.bits);
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+
+    // Palette index for a block, reusing free slots and widening the storage if needed
+    fn palette_slot(&mut self, block: BlockId) -> usize {
+        if let Some(slot) = self.palette.iter().position(|b| *b == block) {
+            return slot;
+        }
+        if let Some(slot) = self.counts.iter().position(|c| *c == 0) {
+            self.palette[slot] = block;
+            return slot;
+        }
+
+        self.palette.push(block);
+        self.counts.push(0);
+        let needed_bits = (usize::BITS - (self.palette.len() - 1).leading_zeros()).next_power_of_two();
+        if needed_bits > self.bits {
+            self.repack(needed_bits);
+        }
+        self.palette.len() - 1
+    }
+
+    fn repack(&mut self, bits: u32) {
+        let indices: Vec<usize> = (0..CHUNK_VOLUME).map(|i| self.read_index(i)).collect();
+        self.bits = bits;
+        self.data = vec![0; CHUNK_VOLUME.div_ceil(Self::indices_per_word(bits))];
+        for (i, value) in indices.into_iter().enumerate() {
+            self.write_index(i, value);
+        }
+    }
+}
+
+// Sparse voxel world. Chunks that were never written to don't exist and read as air.
+// Changes are tracked per chunk so only affected meshes get rebuilt.
+#[derive(Debug, Clone, Default)]
+pub struct VoxelWorld {
+    chunks: HashMap<IVec3, Chunk>,
+    dirty: HashSet<IVec3>,
+    modified: HashSet<IVec3>, // Chunks edited since they were inserted, which need saving
+    light: HashMap<IVec3, Vec<u8>>, // Per voxel light of stored chunks, see `UNLIT`
+    unlit: HashSet<IVec3>,          // Chunks inserted since lighting last ran
+    light_edits: Vec<IVec3>,        // Voxels changed since lighting last ran
+    // Voxels set in chunks that weren't stored yet, replayed onto the chunk once it's inserted
+    pending_edits: HashMap<IVec3, Vec<(IVec3, BlockId)>>,
+}
+
+impl VoxelWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_voxel(&self, pos: IVec3) -> BlockId {
+        let local = local_coord(pos);
+        self.chunks
+            .get(&chunk_coord(pos))
+            .map_or(AIR, |chunk| chunk.get(local.x as usize, local.y as usize, local.z as usize))
+    }
+
+    pub fn set_voxel(&mut self, pos: IVec3, block: BlockId) {
+        let coord = chunk_coord(pos);
+        let local = local_coord(pos);
+        // A chunk that isn't there yet may load with something else in this voxel
+        let pending = !self.chunks.contains_key(&coord) || self.pending_edits.contains_key(&coord);
+        if self.get_voxel(pos) == block && !pending {
+            return;
+        }
+        if !self.chunks.contains_key(&coord) {
+            // A new chunk needs lighting like an inserted one
+            self.unlit.insert(coord);
+            self.pending_edits.insert(coord, Vec::new());
+        }
+        if let Some(edits) = self.pending_edits.get_mut(&coord) {
+            edits.push((local, block));
+        }
+        self.chunks
+            .entry(coord)
+            .or_default()
+            .set(local.x as usize, local.y as usize, local.z as usize, block);
+        self.modified.insert(coord);
+        self.light_edits.push(pos);
+        self.mark_voxel_dirty(coord, local);
+    }
+
+    // Flags the chunk holding a voxel, and the neighbours whose meshes see it too when it
+    // lies on the chunk's border
+    fn mark_voxel_dirty(&mut self, coord: IVec3, local: IVec3) {
+        let range = |v: i32| {
+            let low = if v == 0 { -1 } else { 0 };
+            let high = if v == CHUNK_SIZE as i32 - 1 { 1 } else { 0 };
+            low..=high
+        };
+        for dz in range(local.z) {
+            for dy in range(local.y) {
+                for dx in range(local.x) {
+                    let neighbour = coord + IVec3::new(dx, dy, dz);
+                    if self.chunks.contains_key(&neighbour) {
+                        self.dirty.insert(neighbour);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_light(&self, pos: IVec3) -> u8 {
+        let local = local_coord(pos);
+        self.light
+            .get(&chunk_coord(pos))
+            .map_or(UNLIT, |light| light[Self::light_index(local)])
+    }
+
+    // Only stored chunks hold light; setting it anywhere else does nothing
+    pub fn set_light(&mut self, pos: IVec3, value: u8) {
+        let coord = chunk_coord(pos);
+        if !self.chunks.contains_key(&coord) {
+            return;
+        }
+        let local = local_coord(pos);
+        let light = self.light.entry(coord).or_insert_with(|| vec![UNLIT; CHUNK_VOLUME]);
+        let slot = &mut light[Self::light_index(local)];
+        if *slot != value {
+            *slot = value;
+            self.mark_voxel_dirty(coord, local);
+        }
+    }
+
+    pub fn fill_light(&mut self, coord: IVec3, value: u8) {
+        if self.chunks.contains_key(&coord) {
+            self.light.insert(coord, vec![value; CHUNK_VOLUME]);
+            self.dirty.insert(coord);
+        }
+    }
+
+    // Replaces the light of a whole chunk, in the voxel order of `Chunk::to_blocks`
+    pub fn set_chunk_light(&mut self, coord: IVec3, light: Vec<u8>) {
+        if self.chunks.contains_key(&coord) {
+            assert_eq!(light.len(), CHUNK_VOLUME);
+            self.light.insert(coord, light);
+            self.dirty.insert(coord);
+        }
+    }
+
+    // Same voxel order as `Chunk::to_blocks`
+    pub fn chunk_light(&self, coord: IVec3) -> Option<&[u8]> {
+        self.light.get(&coord).map(Vec::as_slice)
+    }
+
+    fn light_index(local: IVec3) -> usize {
+        (local.y as usize * CHUNK_SIZE + local.z as usize) * CHUNK_SIZE + local.x as usize
+    }
+
+    pub fn needs_light(&self, coord: IVec3) -> bool {
+        self.unlit.contains(&coord)
+    }
+
+    // Up to `limit` of the chunks inserted but not lit yet, which count as lit afterwards
+    pub fn take_unlit(&mut self, limit: usize) -> Vec<IVec3> {
+        let coords: Vec<IVec3> = self.unlit.iter().take(limit).copied().collect();
+        for coord in &coords {
+            self.unlit.remove(coord);
+        }
+        coords
+    }
+
+    // Voxels set since the last call
+    pub fn take_light_edits(&mut self) -> Vec<IVec3> {
+        std::mem::take(&mut self.light_edits)
+    }
+
+    pub fn chunk(&self, coord: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&coord)
+    }
+
+    // Edits made through this aren't tracked for remeshing or saving; call `mark_dirty` afterwards
+    pub fn chunk_mut(&mut self, coord: IVec3) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&coord)
+    }
+
+    // Edits made before the chunk was inserted are applied on top of it, leaving it modified
+    pub fn insert_chunk(&mut self, coord: IVec3, mut chunk: Chunk) -> Option<Chunk> {
+        match self.pending_edits.remove(&coord) {
+            Some(edits) => {
+                for (local, block) in edits {
+                    chunk.set(local.x as usize, local.y as usize, local.z as usize, block);
+                }
+                self.modified.insert(coord);
+            }
+            None => {
+                self.modified.remove(&coord);
+            }
+        }
+        self.light.remove(&coord);
+        self.unlit.insert(coord);
+        self.mark_neighbourhood_dirty(coord);
+        self.chunks.insert(coord, chunk)
+    }
+
+    pub fn remove_chunk(&mut self, coord: IVec3) -> Option<Chunk> {
+        let removed = self.chunks.remove(&coord);
+        self.modified.remove(&coord);
+        self.pending_edits.remove(&coord);
+        self.light.remove(&coord);
+        self.unlit.remove(&coord);
+        if removed.is_some() {
+            self.mark_neighbourhood_dirty(coord);
+        }
+        removed
+    }
+
+    // Flags a chunk and all 26 neighbours that exist, since each one's border depends on it
+    fn mark_neighbourhood_dirty(&mut self, coord: IVec3) {
+        self.dirty.insert(coord);
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let neighbour = coord + IVec3::new(dx, dy, dz);
+                    if self.chunks.contains_key(&neighbour) {
+                        self.dirty.insert(neighbour);
+                    }
+                }
+            }
+        }
+    }
+
+    // Removes every chunk, flagging them so their meshes get cleared
+    pub fn clear(&mut self) {
+        self.dirty.extend(self.chunks.drain().map(|(coord, _)| coord));
+        self.modified.clear();
+        self.light.clear();
+        self.unlit.clear();
+        self.light_edits.clear();
+        self.pending_edits.clear();
+    }
+
+    // Chunks that edits created before anything was inserted there
+    pub fn pending_chunks(&self) -> Vec<IVec3> {
+        self.pending_edits.keys().copied().collect()
+    }
+
+    // Takes the edits to chunks that haven't been inserted, removing the chunks they made
+    pub fn take_pending_edits(&mut self) -> Vec<(IVec3, Vec<(IVec3, BlockId)>)> {
+        let pending: Vec<_> = self.pending_edits.drain().collect();
+        for (coord, _) in &pending {
+            self.remove_chunk(*coord);
+        }
+        pending
+    }
+
+    pub fn has_pending_edits(&self, coord: IVec3) -> bool {
+        self.pending_edits.contains_key(&coord)
+    }
+
+    // Keeps a chunk made of edits as it is, when there turned out to be nothing to insert
+    pub fn keep_pending(&mut self, coord: IVec3) {
+        self.pending_edits.remove(&coord);
+    }
+
+    // Flags a stored chunk as needing a save again, as when writing it failed
+    pub fn mark_modified(&mut self, coord: IVec3) {
+        if self.chunks.contains_key(&coord) {
+            self.modified.insert(coord);
+        }
+    }
+
+    pub fn is_modified(&self, coord: IVec3) -> bool {
+        self.modified.contains(&coord)
+    }
+
+    // Chunks edited since the last call, which are considered saved afterwards. Chunks with
+    // pending edits only hold part of their voxels, so they stay modified until inserted.
+    pub fn take_modified(&mut self) -> Vec<IVec3> {
+        let ready: Vec<IVec3> =
+            self.modified.iter().filter(|coord| !self.pending_edits.contains_key(coord)).copied().collect();
+        for coord in &ready {
+            self.modified.remove(coord);
+        }
+        ready
+    }
+
+    pub fn mark_dirty(&mut self, coord: IVec3) {
+        self.dirty.insert(coord);
+    }
+
+    // Chunks whose meshes are out of date since the last call
+    pub fn take_dirty(&mut self) -> Vec<IVec3> {
+        self.dirty.drain().collect()
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (IVec3, &Chunk)> {
+        self.chunks.iter().map(|(coord, chunk)| (*coord, chunk))
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Position of the i-th voxel in `Chunk::to_blocks` order, as x, y, z
+    fn nth(i: usize) -> (usize, usize, usize) {
+        (i % CHUNK_SIZE, i / (CHUNK_SIZE * CHUNK_SIZE), (i / CHUNK_SIZE) % CHUNK_SIZE)
+    }
+
+    // Sets the first `count` voxels to distinct block ids 1..=count
+    fn fill_distinct(chunk: &mut Chunk, count: usize) {
+        for i in 0..count {
+            let (x, y, z) = nth(i);
+            chunk.set(x, y, z, i as BlockId + 1);
+        }
+    }
+
+    #[test]
+    fn palette_grows_across_bit_widths() {
+        // Palette sizes past which the next width is needed, air included
+        let sizes = [(0, 0), (1, 1), (2, 2), (3, 2), (4, 4), (15, 4), (16, 8), (255, 8), (256, 16), (1000, 16)];
+        for (blocks, bits) in sizes {
+            let mut chunk = Chunk::default();
+            fill_distinct(&mut chunk, blocks);
+            assert_eq!(chunk.bits_per_voxel(), bits, "{} blocks besides air", blocks);
+            for i in 0..blocks {
+                let (x, y, z) = nth(i);
+                assert_eq!(chunk.get(x, y, z), i as BlockId + 1);
+            }
+            assert_eq!(chunk.get(CHUNK_SIZE - 1, CHUNK_SIZE - 1, CHUNK_SIZE - 1), AIR);
+        }
+    }
+
+    #[test]
+    fn widening_keeps_existing_voxels() {
+        let mut chunk = Chunk::default();
+        let blocks: Vec<BlockId> = (0..CHUNK_VOLUME).map(|i| (i % 3) as BlockId).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            let (x, y, z) = nth(i);
+            chunk.set(x, y, z, *block);
+        }
+        assert_eq!(chunk.bits_per_voxel(), 2);
+        chunk.set(5, 6, 7, 300);
+        chunk.set(8, 9, 10, 301);
+        assert_eq!(chunk.bits_per_voxel(), 4);
+
+        let mut expected = blocks;
+        expected[(6 * CHUNK_SIZE + 7) * CHUNK_SIZE + 5] = 300;
+        expected[(9 * CHUNK_SIZE + 10) * CHUNK_SIZE + 8] = 301;
+        assert_eq!(chunk.to_blocks(), expected);
+        assert_eq!(Chunk::from_blocks(&expected).to_blocks(), expected);
+    }
+
+    #[test]
+    fn compact_shrinks_after_clearing() {
+        let mut chunk = Chunk::default();
+        fill_distinct(&mut chunk, 20);
+        assert_eq!(chunk.bits_per_voxel(), 8);
+
+        for i in 1..20 {
+            chunk.set(i % CHUNK_SIZE, 0, i / CHUNK_SIZE, AIR);
+        }
+        // Freed palette slots are reused but the storage stays wide until compacted
+        assert_eq!(chunk.bits_per_voxel(), 8);
+        chunk.compact();
+        assert_eq!(chunk.bits_per_voxel(), 1);
+        assert_eq!(chunk.get(0, 0, 0), 1);
+        assert_eq!(chunk.get(1, 0, 0), AIR);
+
+        chunk.set(0, 0, 0, AIR);
+        assert!(chunk.is_empty());
+        chunk.compact();
+        assert_eq!(chunk.bits_per_voxel(), 0);
+        assert_eq!(chunk, Chunk::default());
+    }
+
+    #[test]
+    fn coords_of_negative_positions() {
+        let size = CHUNK_SIZE as i32;
+        assert_eq!(chunk_coord(IVec3::new(0, 0, 0)), IVec3::ZERO);
+        assert_eq!(chunk_coord(IVec3::new(-1, -1, -1)), IVec3::splat(-1));
+        assert_eq!(local_coord(IVec3::new(-1, -1, -1)), IVec3::splat(size - 1));
+        assert_eq!(chunk_coord(IVec3::new(-size, size, -size - 1)), IVec3::new(-1, 1, -2));
+        assert_eq!(local_coord(IVec3::new(-size, size, -size - 1)), IVec3::new(0, 0, size - 1));
+
+        for pos in [IVec3::new(-33, 5, 70), IVec3::new(-64, -65, -1), IVec3::new(31, 32, -32)] {
+            assert_eq!(chunk_coord(pos) * size + local_coord(pos), pos);
+        }
+    }
+
+    #[test]
+    fn set_and_get_across_chunk_borders() {
+        let mut world = VoxelWorld::new();
+        let positions = [
+            IVec3::new(-1, 0, 0),
+            IVec3::new(0, 0, 0),
+            IVec3::new(31, 31, 31),
+            IVec3::new(32, 31, 31),
+            IVec3::new(-33, -1, 64),
+        ];
+        for (i, pos) in positions.iter().enumerate() {
+            world.set_voxel(*pos, i as BlockId + 1);
+        }
+        for (i, pos) in positions.iter().enumerate() {
+            assert_eq!(world.get_voxel(*pos), i as BlockId + 1);
+        }
+        assert_eq!(world.get_voxel(IVec3::new(1, 0, 0)), AIR);
+        assert_eq!(world.get_voxel(IVec3::new(1000, -1000, 0)), AIR);
+        assert_eq!(world.chunk_count(), 4);
+    }
+
+    #[test]
+    fn edits_before_insert_are_replayed() {
+        let mut world = VoxelWorld::new();
+        world.set_voxel(IVec3::new(1, 2, 3), 7);
+        world.set_voxel(IVec3::new(4, 5, 6), AIR);
+        assert!(world.has_pending_edits(IVec3::ZERO));
+        // Not saved while it only holds the edits
+        assert!(world.take_modified().is_empty());
+
+        world.insert_chunk(IVec3::ZERO, Chunk::filled(1));
+        assert!(!world.has_pending_edits(IVec3::ZERO));
+        assert_eq!(world.get_voxel(IVec3::new(1, 2, 3)), 7);
+        assert_eq!(world.get_voxel(IVec3::new(4, 5, 6)), AIR);
+        assert_eq!(world.get_voxel(IVec3::new(0, 0, 0)), 1);
+        assert_eq!(world.take_modified(), vec![IVec3::ZERO]);
+
+        // Chunks inserted without pending edits replace what was there and aren't modified
+        world.insert_chunk(IVec3::ZERO, Chunk::filled(2));
+        assert_eq!(world.get_voxel(IVec3::new(1, 2, 3)), 2);
+        assert!(!world.is_modified(IVec3::ZERO));
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut world = VoxelWorld::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::default());
+        world.insert_chunk(IVec3::X, Chunk::default());
+        world.insert_chunk(IVec3::Y, Chunk::default());
+        world.take_dirty();
+
+        // An interior voxel only touches its own chunk
+        world.set_voxel(IVec3::new(10, 10, 10), 1);
+        assert_eq!(world.take_dirty(), vec![IVec3::ZERO]);
+        assert!(world.take_dirty().is_empty());
+
+        // Setting the same block again changes nothing
+        world.set_voxel(IVec3::new(10, 10, 10), 1);
+        assert!(world.take_dirty().is_empty());
+
+        // A voxel on the +X face also dirties the neighbour there, but not the one above
+        world.set_voxel(IVec3::new(31, 10, 10), 1);
+        let mut dirty = world.take_dirty();
+        dirty.sort_by_key(|c| (c.x, c.y, c.z));
+        assert_eq!(dirty, vec![IVec3::ZERO, IVec3::X]);
+
+        assert!(world.is_modified(IVec3::ZERO));
+        assert!(!world.is_modified(IVec3::X));
+        assert_eq!(world.take_modified(), vec![IVec3::ZERO]);
+        assert!(!world.is_modified(IVec3::ZERO));
+
+        // Removing a chunk flags it and its stored neighbours
+        world.remove_chunk(IVec3::ZERO);
+        let mut dirty = world.take_dirty();
+        dirty.sort_by_key(|c| (c.x, c.y, c.z));
+        assert_eq!(dirty, vec![IVec3::ZERO, IVec3::Y, IVec3::X]);
+    }
+}
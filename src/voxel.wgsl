@@ -0,0 +1,94 @@
+// Voxel chunk shader. Positions are chunk-local; the object uniform places the chunk.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+struct ObjectUniform {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    normal: mat4x4<f32>,
+    material: vec4<f32>, // Specular strength, shininess
+    pbr: vec4<f32>,      // Metallic, roughness, normal scale, occlusion strength
+    emissive: vec4<f32>, // Emitted color, alpha cutoff
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(1) @binding(0)
+var<uniform> object: ObjectUniform;
+
+@group(2) @binding(0)
+var block_textures: texture_2d_array<f32>;
+@group(2) @binding(1)
+var block_sampler: sampler;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
+    @location(3) ao: f32,
+    @location(4) uv: vec2<f32>,
+    @location(5) layer: u32,
+    @location(6) emissive: u32,
+    @location(7) light: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) ao: f32,
+    @location(3) uv: vec2<f32>,
+    @location(4) @interpolate(flat) layer: u32,
+    @location(5) @interpolate(flat) emissive: u32,
+    @location(6) light: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = model.color * object.color.rgb;
+    out.normal = model.normal;
+    out.ao = model.ao;
+    out.uv = model.uv;
+    out.layer = model.layer;
+    out.emissive = model.emissive;
+    out.light = model.light;
+    out.clip_position = camera.view_proj * object.model * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// Fixed sun direction so the faces of a block are told apart
+const SUN_DIRECTION: vec3<f32> = vec3<f32>(0.4, 0.8, 0.3);
+const BLOCK_LIGHT_COLOR: vec3<f32> = vec3<f32>(1.0, 0.85, 0.6);
+
+// Light levels lose a fixed fraction of brightness per step away from their source
+fn light_falloff(level: f32) -> f32 {
+    return pow(0.8, (1.0 - level) * 15.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(block_textures, block_sampler, in.uv, in.layer);
+    // Cut-out transparency, for glass and the like
+    if texel.a < 0.5 {
+        discard;
+    }
+    let albedo = in.color * texel.rgb;
+    if in.emissive != 0u {
+        return vec4<f32>(albedo, 1.0);
+    }
+
+    let diffuse = max(dot(normalize(in.normal), normalize(SUN_DIRECTION)), 0.0);
+    let ambient_occlusion = mix(0.45, 1.0, in.ao);
+    let sun = (0.45 + 0.55 * diffuse) * light_falloff(in.light.x);
+    let lamps = light_falloff(in.light.y) * BLOCK_LIGHT_COLOR;
+    // Block light only shows where it's brighter than the sky
+    let lighting = max(vec3<f32>(sun), lamps * step(0.5 / 15.0, in.light.y));
+    return vec4<f32>(albedo * lighting * ambient_occlusion, 1.0);
+}
@@ -0,0 +1,255 @@
+// voxel_editor.rs
+
+use crate::blocks::{linear_to_srgb, BlockRegistry};
+use crate::ray::Ray;
+use crate::vertex::Vertex;
+use crate::voxel::{BlockId, VoxelWorld, AIR};
+use glam::{IVec3, Vec3};
+
+const MAX_REACH: f32 = 64.0;
+const TARGET_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const UNDO_LIMIT: usize = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VoxelTool {
+    Place,
+    Break,
+    Paint,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BrushShape {
+    Single,
+    Sphere,
+    Box,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoxelHit {
+    pub pos: IVec3,
+    pub normal: IVec3, // Face the ray entered through, zero if it started inside the voxel
+    pub distance: f32,
+}
+
+// First solid voxel along the ray, stepping voxel by voxel (Amanatides & Woo)
+pub fn raycast(world: &VoxelWorld, ray: &Ray, max_distance: f32) -> Option<VoxelHit> {
+    let mut pos = ray.origin.floor().as_ivec3();
+    let step = ray.direction.signum().as_ivec3();
+    // Distance along the ray to cross one voxel, and to reach the next boundary, per axis
+    let t_delta = ray.direction.recip().abs();
+    let next_boundary = pos.as_vec3() + step.max(IVec3::ZERO).as_vec3();
+    let mut t_max = Vec3::select(
+        ray.direction.cmpeq(Vec3::ZERO),
+        Vec3::INFINITY,
+        (next_boundary - ray.origin) / ray.direction,
+    );
+
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        if world.get_voxel(pos) != AIR {
+            return Some(VoxelHit { pos, normal, distance });
+        }
+        let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+            0
+        } else if t_max.y < t_max.z {
+            1
+        } else {
+            2
+        };
+        pos[axis] += step[axis];
+        distance = t_max[axis];
+        t_max[axis] += t_delta[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+    None
+}
+
+// Voxels changed by one brush stroke, with their contents before and after
+struct Edit {
+    changes: Vec<(IVec3, BlockId, BlockId)>,
+}
+
+pub struct VoxelEditor {
+    pub enabled: bool,
+    pub tool: VoxelTool,
+    pub shape: BrushShape,
+    pub size: i32, // Brush radius in voxels, ignored for single voxels
+    pub block: BlockId,
+    target: Option<VoxelHit>,
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+}
+
+impl VoxelEditor {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            tool: VoxelTool::Place,
+            shape: BrushShape::Single,
+            size: 2,
+            block: 1,
+            target: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    pub fn update_target(&mut self, world: &VoxelWorld, ray: Option<&Ray>) {
+        self.target = ray
+            .filter(|_| self.enabled)
+            .and_then(|ray| raycast(world, ray, MAX_REACH));
+    }
+
+    // Center of the brush: the hit voxel, or the empty one in front of it when placing
+    fn brush_center(&self) -> Option<IVec3> {
+        let hit = self.target?;
+        match self.tool {
+            VoxelTool::Place if hit.normal == IVec3::ZERO => None,
+            VoxelTool::Place => Some(hit.pos + hit.normal),
+            VoxelTool::Break | VoxelTool::Paint => Some(hit.pos),
+        }
+    }
+
+    fn brush_radius(&self) -> i32 {
+        match self.shape {
+            BrushShape::Single => 0,
+            BrushShape::Sphere | BrushShape::Box => self.size.max(1),
+        }
+    }
+
+    fn brush_voxels(&self, center: IVec3) -> Vec<IVec3> {
+        let r = self.brush_radius();
+        let mut voxels = Vec::new();
+        for y in -r..=r {
+            for z in -r..=r {
+                for x in -r..=r {
+                    let offset = IVec3::new(x, y, z);
+                    if self.shape != BrushShape::Sphere || offset.length_squared() <= r * r {
+                        voxels.push(center + offset);
+                    }
+                }
+            }
+        }
+        voxels
+    }
+
+    // Applies the current tool at the target and records it for undo
+    pub fn apply(&mut self, world: &mut VoxelWorld) {
+        let Some(center) = self.brush_center() else { return };
+
+        let mut changes = Vec::new();
+        for pos in self.brush_voxels(center) {
+            let before = world.get_voxel(pos);
+            let after = match self.tool {
+                VoxelTool::Place if before == AIR => self.block,
+                VoxelTool::Break => AIR,
+                VoxelTool::Paint if before != AIR => self.block,
+                _ => before,
+            };
+            if after != before {
+                world.set_voxel(pos, after);
+                changes.push((pos, before, after));
+            }
+        }
+
+        if !changes.is_empty() {
+            self.undo_stack.push(Edit { changes });
+            if self.undo_stack.len() > UNDO_LIMIT {
+                self.undo_stack.remove(0);
+            }
+            self.redo_stack.clear();
+        }
+    }
+
+    pub fn undo(&mut self, world: &mut VoxelWorld) {
+        if let Some(edit) = self.undo_stack.pop() {
+            for (pos, before, _) in edit.changes.iter().rev() {
+                world.set_voxel(*pos, *before);
+            }
+            self.redo_stack.push(edit);
+        }
+    }
+
+    pub fn redo(&mut self, world: &mut VoxelWorld) {
+        if let Some(edit) = self.redo_stack.pop() {
+            for (pos, _, after) in &edit.changes {
+                world.set_voxel(*pos, *after);
+            }
+            self.undo_stack.push(edit);
+        }
+    }
+
+    // Wireframe around the targeted voxel, and around the brush if it covers more than that
+    pub fn lines(&self, blocks: &BlockRegistry) -> Vec<Vertex> {
+        let mut lines = Vec::new();
+        let Some(hit) = self.target else { return lines };
+        push_box(&mut lines, hit.pos.as_vec3(), hit.pos.as_vec3() + Vec3::ONE, TARGET_COLOR);
+
+        if let Some(center) = self.brush_center() {
+            let r = self.brush_radius();
+            if r > 0 || center != hit.pos {
+                let color = if self.tool == VoxelTool::Break { [1.0, 0.3, 0.2] } else { blocks.preview_color(self.block) };
+                let min = (center - IVec3::splat(r)).as_vec3();
+                let max = (center + IVec3::splat(r + 1)).as_vec3();
+                push_box(&mut lines, min, max, color);
+            }
+        }
+        lines
+    }
+
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui, world: &mut VoxelWorld, blocks: &BlockRegistry) {
+        ui.checkbox(&mut self.enabled, "Edit voxels with the mouse");
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tool, VoxelTool::Place, "Place");
+            ui.selectable_value(&mut self.tool, VoxelTool::Break, "Break");
+            ui.selectable_value(&mut self.tool, VoxelTool::Paint, "Paint");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Brush:");
+            ui.selectable_value(&mut self.shape, BrushShape::Single, "Single");
+            ui.selectable_value(&mut self.shape, BrushShape::Sphere, "Sphere");
+            ui.selectable_value(&mut self.shape, BrushShape::Box, "Box");
+        });
+        ui.add_enabled_ui(self.shape != BrushShape::Single, |ui| {
+            ui.add(egui::DragValue::new(&mut self.size).range(1..=16).prefix("radius: "));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Block:");
+            let selected = blocks.get(self.block).map_or_else(|| format!("#{}", self.block), |b| b.name.clone());
+            egui::ComboBox::from_id_source("voxel_block").selected_text(selected).show_ui(ui, |ui| {
+                for (id, block) in blocks.iter() {
+                    ui.selectable_value(&mut self.block, id, &block.name);
+                }
+            });
+            let [r, g, b] = blocks.preview_color(self.block).map(|c| (linear_to_srgb(c) * 255.0) as u8);
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+            ui.painter().rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+        });
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!self.undo_stack.is_empty(), egui::Button::new("Undo")).clicked() {
+                self.undo(world);
+            }
+            if ui.add_enabled(!self.redo_stack.is_empty(), egui::Button::new("Redo")).clicked() {
+                self.redo(world);
+            }
+        });
+    }
+}
+
+fn push_box(lines: &mut Vec<Vertex>, min: Vec3, max: Vec3, color: [f32; 3]) {
+    // Slightly inflated so the lines don't sit exactly on the voxel faces
+    let (min, max) = (min - Vec3::splat(0.005), max + Vec3::splat(0.005));
+    let corner = |i: usize| {
+        Vec3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        )
+    };
+    for (a, b) in [(0, 1), (2, 3), (4, 5), (6, 7), (0, 2), (1, 3), (4, 6), (5, 7), (0, 4), (1, 5), (2, 6), (3, 7)] {
+        lines.push(Vertex::new(corner(a).to_array(), color));
+        lines.push(Vertex::new(corner(b).to_array(), color));
+    }
+}
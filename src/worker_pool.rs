@@ -0,0 +1,117 @@
+// worker_pool.rs
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+
+struct JobQueue<J> {
+    jobs: VecDeque<J>,
+    shutdown: bool,
+}
+
+type SharedQueue<J> = Arc<(Mutex<JobQueue<J>>, Condvar)>;
+
+// Threads running the same function over queued jobs, handing results back through a channel.
+// Jobs start in the order they were submitted.
+pub struct WorkerPool<J, R> {
+    queue: SharedQueue<J>,
+    results: Receiver<R>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<J: Send + 'static, R: Send + 'static> WorkerPool<J, R> {
+    pub fn new<F>(name: &str, threads: usize, work: F) -> Self
+    where
+        F: Fn(J) -> R + Send + Clone + 'static,
+    {
+        let queue = Arc::new((
+            Mutex::new(JobQueue {
+                jobs: VecDeque::new(),
+                shutdown: false,
+            }),
+            Condvar::new(),
+        ));
+        let (sender, results) = mpsc::channel();
+
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let queue = queue.clone();
+                let sender = sender.clone();
+                let work = work.clone();
+                std::thread::Builder::new()
+                    .name(format!("{} {}", name, i))
+                    .spawn(move || Self::worker(&queue, &sender, work))
+                    .expect("Failed to spawn worker thread")
+            })
+            .collect();
+
+        Self {
+            queue,
+            results,
+            workers,
+        }
+    }
+
+    // One thread is left for the render loop
+    pub fn with_available_threads<F>(name: &str, work: F) -> Self
+    where
+        F: Fn(J) -> R + Send + Clone + 'static,
+    {
+        let threads = std::thread::available_parallelism().map_or(2, |n| n.get());
+        Self::new(name, threads.saturating_sub(1), work)
+    }
+
+    fn worker(queue: &(Mutex<JobQueue<J>>, Condvar), results: &Sender<R>, work: impl Fn(J) -> R) {
+        let (lock, condvar) = queue;
+        loop {
+            let job = {
+                let mut queue = lock.lock().unwrap();
+                loop {
+                    if queue.shutdown {
+                        return;
+                    }
+                    if let Some(job) = queue.jobs.pop_front() {
+                        break job;
+                    }
+                    queue = condvar.wait(queue).unwrap();
+                }
+            };
+
+            if results.send(work(job)).is_err() {
+                return; // The pool was dropped
+            }
+        }
+    }
+
+    pub fn submit(&self, job: J) {
+        let (lock, condvar) = &*self.queue;
+        lock.lock().unwrap().jobs.push_back(job);
+        condvar.notify_one();
+    }
+
+    // Drops queued jobs that haven't started yet for which `keep` returns false
+    pub fn retain_queued(&self, keep: impl FnMut(&J) -> bool) {
+        self.queue.0.lock().unwrap().jobs.retain(keep);
+    }
+
+    pub fn try_recv(&self) -> Option<R> {
+        self.results.try_recv().ok()
+    }
+
+    // Blocks until a result comes in, None if every worker has stopped
+    pub fn recv(&self) -> Option<R> {
+        self.results.recv().ok()
+    }
+}
+
+impl<J, R> Drop for WorkerPool<J, R> {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.queue;
+        lock.lock().unwrap().shutdown = true;
+        condvar.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}